@@ -1,13 +1,336 @@
+use std::collections::HashMap;
+use std::env;
 use std::error;
 use std::fmt;
 use std::fs::{self, OpenOptions};
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Read, Write};
+use std::net;
+use std::os::unix::fs::PermissionsExt;
+use std::panic;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU8, Ordering};
+use std::sync::{mpsc, Mutex, OnceLock};
 use std::thread;
 use std::time;
 
-const MAX_LINE_LENGTH: u8 = 64;
-const INSTALLATION_STEPS_COUNT: u8 = 34;
+const MAX_LINE_LENGTH: usize = 64;
+// Banners are printed as plain text in terminals narrower than this.
+const MIN_BANNER_LINE_LENGTH: usize = 24;
+// Built-in profiles which answers files can extend with "extends = <name>". ((Name, Answers))
+const ANSWER_PROFILES: &[(&str, &str)] = &[
+    (
+        "minimal",
+        "skip = kde, sddm, numlock
+Which kind of swap do you want to use? => choice:zram
+Enter the zram size expression. =>
+Which compression algorithm do you want zram to use? => choice:zstd
+Do you want to apply sensible defaults for journald and kernel parameters? => y
+Which maintenance timers do you want to enable? => choice:paccache.timer (Cleans the package cache weekly)",
+    ),
+    (
+        "desktop-kde",
+        "extends = minimal
+skip =
+Do you want to enable systemd-oomd to kill processes before the system runs out of memory? => y
+Where do you want numlock to be enabled on boot? => choice:SDDM login screen
+Which maintenance timers do you want to enable? => choice:paccache.timer (Cleans the package cache weekly), choice:reflector.timer (Updates the mirror list weekly)",
+    ),
+];
+// Number of choices the picker shows at once.
+const PICKER_ROWS_COUNT: usize = 10;
+// ioctl request which reads the window size of a terminal.
+const TIOCGWINSZ: u64 = 0x5413;
+// Installation steps with their stable IDs and the IDs of the steps they depend on.
+// Execution order is computed from these dependencies and follows the declaration order where possible.
+const INSTALLATION_STEPS: &[(&str, &[&str])] = &[
+    ("installation_mode", &[]),
+    ("encryption", &[]),
+    ("timedatectl", &[]),
+    ("mirrors", &[]),
+    ("pacman", &[]),
+    ("partitioning", &[]),
+    ("partition_names", &["installation_mode", "partitioning"]),
+    ("format_partitions", &["encryption", "partition_names"]),
+    ("swap", &["encryption", "partitioning"]),
+    ("mount_partitions", &["format_partitions"]),
+    ("pacstrap", &["mount_partitions", "mirrors", "pacman"]),
+    ("fstab", &["pacstrap", "swap"]),
+    ("zram_and_swap_encryption", &["fstab"]),
+    ("fstab_review", &["zram_and_swap_encryption"]),
+    ("target_pacman", &["pacstrap"]),
+    ("time_zone", &["pacstrap"]),
+    ("hardware_clock", &["time_zone"]),
+    ("time_sync", &["time_zone"]),
+    ("locale", &["pacstrap"]),
+    ("console_keymap", &["pacstrap"]),
+    ("keyboard_layout", &["pacstrap"]),
+    ("hostname", &["pacstrap"]),
+    ("hosts", &["hostname"]),
+    ("root_password", &["pacstrap"]),
+    ("create_user", &["pacstrap"]),
+    ("user_password", &["create_user"]),
+    ("user_groups", &["create_user"]),
+    ("user_directories", &["create_user"]),
+    ("privilege_escalation", &["root_password", "user_groups"]),
+    ("grub_install", &["pacstrap"]),
+    ("trim", &["pacstrap"]),
+    ("grub_config", &["grub_install", "trim", "swap"]),
+    ("mkinitcpio", &["console_keymap", "swap"]),
+    ("grub_mkconfig", &["grub_config", "mkinitcpio"]),
+    ("crypttab", &["zram_and_swap_encryption", "trim"]),
+    ("network_manager", &["pacstrap"]),
+    ("dns", &["network_manager"]),
+    ("maintenance_timers", &["mirrors", "pacstrap"]),
+    ("system_defaults", &["swap", "pacstrap"]),
+    ("kde", &["pacstrap"]),
+    ("sddm", &["kde"]),
+    ("numlock", &["kde"]),
+    ("first_boot", &["pacstrap"]),
+    ("paru", &["privilege_escalation"]),
+    ("dotfiles", &["create_user"]),
+    ("post_install_hooks", &["pacstrap"]),
+    (
+        "verification",
+        &[
+            "fstab_review",
+            "grub_mkconfig",
+            "crypttab",
+            "post_install_hooks",
+        ],
+    ),
+    ("install_report", &["verification"]),
+    ("unmount_partitions", &["install_report"]),
+];
+// Steps which prepare disks or the boot loader, so they don't apply to container installations. Steps which
+// configure the live environment are left out too, because the installer then runs on a host system whose clock,
+// mirror list and pacman.conf mustn't be changed.
+const CONTAINER_SKIPPED_STEPS: &[&str] = &[
+    "installation_mode",
+    "encryption",
+    "timedatectl",
+    "mirrors",
+    "pacman",
+    "partitioning",
+    "partition_names",
+    "format_partitions",
+    "swap",
+    "mount_partitions",
+    "fstab",
+    "zram_and_swap_encryption",
+    "fstab_review",
+    "hardware_clock",
+    "grub_install",
+    "trim",
+    "grub_config",
+    "mkinitcpio",
+    "grub_mkconfig",
+    "crypttab",
+    "verification",
+];
+// Base packages which containers don't need, because they use the kernel of their host and don't boot by themselves.
+const CONTAINER_EXCLUDED_PACKAGES: &[&str] =
+    &["linux", "linux-firmware", "grub", "dosfstools", "mtools"];
+// Commands of the Arch Linux live ISO which the steps run, with the packages which provide them.
+const HOST_TOOLS: &[(&str, &str)] = &[
+    ("pacstrap", "arch-install-scripts"),
+    ("arch-chroot", "arch-install-scripts"),
+    ("genfstab", "arch-install-scripts"),
+    ("reflector", "reflector"),
+];
+// Console font of terminus-font for high resolution screens. (16x32 pixels, bold)
+const LARGE_CONSOLE_FONT: &str = "ter-132b";
+// Values of GRUB_TIMEOUT_STYLE with what they do.
+const GRUB_TIMEOUT_STYLES: &[&str] = &[
+    "menu (Always shown until the timeout ends)",
+    "hidden (Shown only when Esc is pressed before the timeout ends)",
+    "countdown (Hidden with a countdown. Esc shows it)",
+];
+// GPT partition type GUID of UEFI system partitions.
+const ESP_PARTITION_TYPE: &str = "c12a7328-f81f-11d2-ba4b-00a0c93ec93b";
+// btrfs profiles of a root file system with several devices.
+const BTRFS_PROFILES: &[&str] = &[
+    "raid1 (Every block is stored on two devices. Survives one failed device)",
+    "raid10 (Mirrored like raid1 and striped for speed. Needs 4 devices)",
+    "single (Space of all devices is added up without copies. Metadata is still mirrored)",
+];
+const CONFIG_FILE_PATH: &str = "./arch_linux_installer.conf";
+// Version of the config file format. Version 1 was the positional format without a version line.
+const CONFIG_VERSION: u32 = 2;
+const CUSTOMIZATION_FILE_PATH: &str = "./arch_linux_installer_custom.conf";
+const LOG_FILE_PATH: &str = "./arch_linux_installer.log";
+// Commands and file changes of the installation are recorded here as a shell script which can replay them.
+const INSTALL_SCRIPT_PATH: &str = "./install.sh";
+// Answers files given as URLs are downloaded here.
+const DOWNLOADED_ANSWERS_FILE_PATH: &str = "/tmp/arch_linux_installer_answers.txt";
+// Answers file written by the clone subcommand.
+const CLONED_ANSWERS_FILE_PATH: &str = "./arch_linux_installer_answers.txt";
+const DEFAULT_IMAGE_SIZE: &str = "20G";
+// Packages are downloaded ahead of pacstrap to this directory, which is in RAM, with their own sync databases.
+const DOWNLOAD_AHEAD_CACHE_PATH: &str = "/tmp/arch_linux_installer_packages";
+const DOWNLOAD_AHEAD_DATABASE_PATH: &str = "/tmp/arch_linux_installer_download_db";
+// Packages of pacstrap and their dependencies are about 1 GiB, so downloading ahead needs this much free RAM.
+const DOWNLOAD_AHEAD_MIN_FREE_SPACE: u64 = 2 * 1024 * 1024 * 1024;
+// Mirrors are tested by downloading the core database, which is small but big enough to measure the speed.
+const MIRROR_TEST_FILE: &str = "core/os/x86_64/core.db";
+const MIRROR_TEST_TIMEOUT_SECONDS: &str = "10";
+// Lock files of the live environment's and the new system's pacman databases.
+const PACMAN_LOCK_PATHS: &[&str] = &["/var/lib/pacman/db.lck", "/mnt/var/lib/pacman/db.lck"];
+// Clock showing an earlier year is certainly wrong. Package signatures would look like they were made in the future.
+const MIN_CLOCK_YEAR: u64 = 2025;
+// How long timedatectl may take to synchronize the clock with NTP.
+const TIME_SYNC_TIMEOUT: time::Duration = time::Duration::from_secs(60);
+// Background processes started by a command (e.g. gpg-agent) may keep its output open after it has exited,
+// so the rest of the output is only waited for this long.
+const OUTPUT_DRAIN_TIMEOUT: time::Duration = time::Duration::from_secs(2);
+// Mirrors and git servers may fail for a moment, so commands which download something are tried again.
+const NETWORK_RETRY_POLICY: RetryPolicy = RetryPolicy {
+    attempts: 4,
+    delay: time::Duration::from_secs(5),
+};
+// Customization keys for luksFormat parameters and the cryptsetup options they are passed as.
+const LUKS_FORMAT_OPTIONS: &[(&str, &str)] = &[
+    ("luks_cipher", "--cipher"),
+    ("luks_key_size", "--key-size"),
+    ("luks_pbkdf", "--pbkdf"),
+    ("luks_iter_time", "--iter-time"),
+    ("luks_pbkdf_memory", "--pbkdf-memory"),
+    ("luks_sector_size", "--sector-size"),
+];
+const SUPPLEMENTARY_GROUPS: &[&str] = &[
+    "video", "audio", "input", "storage", "docker", "libvirt", "uucp",
+];
+// Packages installed by pacstrap, besides the microcode of the CPU.
+const BASE_PACKAGES: &[&str] = &[
+    "base",
+    "linux",
+    "linux-firmware",
+    "sudo",
+    "helix",
+    "grub",
+    "dosfstools",
+    "mtools",
+    "networkmanager",
+    "git",
+    "base-devel",
+];
+const KDE_PACKAGES: &[&str] = &[
+    "sddm",
+    "bluedevil",
+    "breeze",
+    "breeze-gtk",
+    "kactivitymanagerd",
+    "kde-gtk-config",
+    "kgamma5",
+    "kpipewire",
+    "kscreen",
+    "kscreenlocker",
+    "ksystemstats",
+    "kwayland-integration",
+    "kwin",
+    "libkscreen",
+    "libksysguard",
+    "plasma-desktop",
+    "plasma-disks",
+    "plasma-firewall",
+    "plasma-nm",
+    "plasma-pa",
+    "plasma-systemmonitor",
+    "plasma-workspace",
+    "plasma-workspace-wallpapers",
+    "powerdevil",
+    "sddm-kcm",
+    "systemsettings",
+    "ark",
+    "dolphin",
+    "elisa",
+    "gwenview",
+    "kalarm",
+    "kcalc",
+    "kdeconnect",
+    "kdialog",
+    "konsole",
+    "ktimer",
+    "okular",
+    "partitionmanager",
+    "print-manager",
+    "spectacle",
+    "firefox",
+];
+const ZRAM_COMPRESSION_ALGORITHMS: &[&str] = &["zstd", "lz4", "lzo-rle"];
+const LOCALE_CATEGORIES: &[&str] = &[
+    "LC_ADDRESS",
+    "LC_COLLATE",
+    "LC_CTYPE",
+    "LC_IDENTIFICATION",
+    "LC_MEASUREMENT",
+    "LC_MESSAGES",
+    "LC_MONETARY",
+    "LC_NAME",
+    "LC_NUMERIC",
+    "LC_PAPER",
+    "LC_TELEPHONE",
+    "LC_TIME",
+];
+
+// Message catalogs of the languages other than English. Each line has the form "<English text> => <translation>".
+// "{}" stands for a value (a path, a name, ...) which is filled in, in the same order, in the translation.
+const TRANSLATIONS: &[(&str, &str)] = &[
+    ("de", include_str!("../translations/de.txt")),
+    ("fa", include_str!("../translations/fa.txt")),
+];
+
+const SIGINT: i32 = 2;
+const SIGTERM: i32 = 15;
+
+// Plain output has no colors and no banners, for serial consoles and logs.
+static PLAIN_OUTPUT: AtomicBool = AtomicBool::new(false);
+// Colors are disabled with --no-color, NO_COLOR or plain output, but the banners are kept.
+static COLORLESS_OUTPUT: AtomicBool = AtomicBool::new(false);
+// Accessible output is plain output for screen readers, with steps announced as sentences.
+static ACCESSIBLE_OUTPUT: AtomicBool = AtomicBool::new(false);
+// Verbosity given on the command line, stored as the discriminant of Verbosity.
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+// Number of the received SIGINT or SIGTERM signal. (0 if none was received)
+static RECEIVED_SIGNAL: AtomicI32 = AtomicI32::new(0);
+// Process ID of the running external command. (0 if none is running)
+static RUNNING_CHILD_PROCESS_ID: AtomicU32 = AtomicU32::new(0);
+// Number of external commands run by run_command and run_command_with_input, used in the installation summary.
+static EXECUTED_COMMANDS_COUNT: AtomicU32 = AtomicU32::new(0);
+// URL which receives a POST request when the installation finishes, fails or waits for input.
+static NOTIFICATION_URL: OnceLock<String> = OnceLock::new();
+// Longest time an external command may run. Set with the command_timeout customization key.
+static COMMAND_TIMEOUT: OnceLock<time::Duration> = OnceLock::new();
+// Messages of the selected language as (English text, translation) pairs. Not set for English.
+static MESSAGE_CATALOG: OnceLock<Vec<(&str, &str)>> = OnceLock::new();
+// ID of the running step, used by the panic hook.
+static CURRENT_STEP_ID: Mutex<String> = Mutex::new(String::new());
+// Work which runs in the background while later steps ask questions.
+static BACKGROUND_TASKS: Mutex<Vec<BackgroundTask>> = Mutex::new(Vec::new());
+
+// Name of a background task and the thread which runs it.
+type BackgroundTask = (&'static str, thread::JoinHandle<Result<(), String>>);
+
+extern "C" {
+    fn signal(signal_number: i32, handler: extern "C" fn(i32)) -> usize;
+    fn ioctl(file_descriptor: i32, request: u64, ...) -> i32;
+    fn geteuid() -> u32;
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct WindowSize {
+    rows: u16,
+    columns: u16,
+    width_in_pixels: u16,
+    height_in_pixels: u16,
+}
+
+// Only stores the signal number, the interruption itself is handled by the thread started in watch_signals.
+extern "C" fn handle_signal(signal_number: i32) {
+    RECEIVED_SIGNAL.store(signal_number, Ordering::SeqCst);
+}
 
 enum PrintFormat {
     Bordered,
@@ -17,26 +340,141 @@ enum PrintFormat {
 
 struct Question {
     answer: String,
+    last_answer_time: time::Instant,
+    // Answers given in this session, offered again with the up and down keys.
+    history: Vec<String>,
+    // Answers of the answers file which weren't used yet. ((Part of the question, Answer))
+    preset_answers: Vec<(String, String)>,
+    // Questions without a preset answer fail the installation in the hands-off mode.
+    is_unattended: bool,
 }
 
 impl Question {
     fn new() -> Self {
         Self {
             answer: String::new(),
+            last_answer_time: time::Instant::now(),
+            history: Vec::new(),
+            preset_answers: Vec::new(),
+            is_unattended: false,
+        }
+    }
+
+    // Reads an answers file (a path or an HTTP(S) URL) and the profiles it extends. Returns the IDs of the steps which
+    // the answers file skips.
+    fn load_preset_answers(&mut self, answers_location: &str) -> Result<Vec<String>, AppError> {
+        let answers_content = read_answers_file(answers_location)?;
+        let answers_file = parse_answers_file(&answers_content, answers_location, &mut Vec::new())?;
+        self.preset_answers = answers_file.answers;
+
+        Ok(answers_file.skipped_steps.unwrap_or_default())
+    }
+
+    // Removes and returns the first preset answer whose question part appears in the question. Every line of the
+    // answers file is used once, so a question which is asked several times needs a line for each time.
+    fn take_preset_answer(&mut self, question: &str) -> Option<String> {
+        let answer_index = self
+            .preset_answers
+            .iter()
+            .position(|(question_part, _)| question.contains(question_part.as_str()))?;
+
+        Some(self.preset_answers.remove(answer_index).1)
+    }
+
+    // Finds the choice of a preset answer. The answer is "choice:<text>", the text of the choice or its number.
+    fn take_preset_choice(&mut self, question: &str, choices: &[&str]) -> Option<usize> {
+        let preset_answer = self.take_preset_answer(question)?;
+        match find_choice_index(&preset_answer, choices) {
+            Some(choice_index) => {
+                println!(
+                    "{}\n{}\n",
+                    translate(question),
+                    translate(choices[choice_index])
+                );
+                Some(choice_index)
+            }
+            None => {
+                print_invalid_preset_answer(&preset_answer);
+                None
+            }
+        }
+    }
+
+    // Reads a line from stdin. If the installer worked on its own for a while since the last answer,
+    // the user has probably walked away, so a notification is sent first.
+    // Answers are read with the line editor on a terminal. Hidden answers (passwords) aren't edited or kept.
+    fn read_answer(&mut self, prompt: &str, is_hidden: bool) -> String {
+        if self.is_unattended {
+            fail_unanswered_question(prompt);
+        }
+        self.notify_if_idle();
+
+        let mut answer = String::new();
+        if !is_hidden && is_line_editing_available() {
+            // Only the last line of the prompt is printed again when completions are listed.
+            let prompt_line = prompt.rsplit('\n').next().unwrap_or_default();
+            answer =
+                edit_line(prompt_line, &self.history).unwrap_or_else(|_| fail_closed_input(prompt));
+        } else if io::stdin().read_line(&mut answer).unwrap() == 0 {
+            // Input ended, so asking again would never get an answer.
+            fail_closed_input(prompt);
+        }
+        self.last_answer_time = time::Instant::now();
+
+        let trimmed_answer = answer.trim();
+        if !is_hidden
+            && !trimmed_answer.is_empty()
+            && self.history.last().map(String::as_str) != Some(trimmed_answer)
+        {
+            self.history.push(trimmed_answer.to_string());
+        }
+
+        answer
+    }
+
+    fn notify_if_idle(&self) {
+        if self.last_answer_time.elapsed() > time::Duration::from_secs(60) {
+            send_notification(&format!(
+                "Installer is waiting for input: {}",
+                CURRENT_STEP_ID
+                    .lock()
+                    .map(|s| s.clone())
+                    .unwrap_or_default()
+            ));
         }
     }
 
     fn ask(&mut self, question: &str) {
+        if let Some(preset_answer) = self.take_preset_answer(question) {
+            println!("{}{preset_answer}", translate(question));
+            self.answer = preset_answer;
+            return;
+        }
+
+        self.ask_user(question);
+    }
+
+    // Like ask, but answers of the answers file aren't used.
+    fn ask_user(&mut self, question: &str) {
+        let question = translate(question);
         self.answer.clear();
         print!("{}", question);
         io::stdout().flush().unwrap();
-        io::stdin().read_line(&mut self.answer).unwrap();
-        self.answer = self.answer.trim().to_string();
+        self.answer = self.read_answer(&question, false).trim().to_string();
     }
 
     fn bool_ask(&mut self, question: &str) -> bool {
+        if let Some(preset_answer) = self.take_preset_answer(question) {
+            println!("{} (y/n): {preset_answer}", translate(question));
+            match preset_answer.as_str() {
+                "y" | "Y" => return true,
+                "n" | "N" => return false,
+                _ => print_invalid_preset_answer(&preset_answer),
+            }
+        }
+
         loop {
-            self.ask(format!("{question} (y/n): ").as_str());
+            self.ask_user(format!("{} (y/n): ", translate(question)).as_str());
             match self.answer.as_str() {
                 "y" | "Y" => return true,
                 "n" | "N" => return false,
@@ -45,1423 +483,10314 @@ impl Question {
         }
     }
 
+    // Like ask, but the question is a template whose "{}" is filled with the default answer, which an empty answer
+    // selects. The template is translated before it's filled, so it matches the "{}" entry of the catalog.
+    fn ask_with_default(&mut self, question_template: &str, default_answer: &str) {
+        let translated_question = translate(question_template).replacen("{}", default_answer, 1);
+        match self.take_preset_answer(&question_template.replacen("{}", default_answer, 1)) {
+            Some(preset_answer) => {
+                println!("{translated_question}{preset_answer}");
+                self.answer = preset_answer;
+            }
+            None => self.ask_user(&translated_question),
+        }
+
+        if self.answer.is_empty() {
+            self.answer = default_answer.to_string();
+        }
+    }
+
     fn selecting_ask(&mut self, question: &str, choices: &[&str]) {
+        if let Some(choice_index) = self.take_preset_choice(question, choices) {
+            self.answer = (choice_index + 1).to_string();
+            return;
+        }
+
+        self.read_choice(&translate(question), choices, None);
+    }
+
+    // Like selecting_ask, but the question is a template whose "{}" is filled with the name of the default choice,
+    // which an empty answer selects.
+    fn selecting_ask_with_default(
+        &mut self,
+        question_template: &str,
+        choices: &[&str],
+        default_index: usize,
+        default_name: &str,
+    ) {
+        let question = question_template.replacen("{}", default_name, 1);
+        if let Some(choice_index) = self.take_preset_choice(&question, choices) {
+            self.answer = (choice_index + 1).to_string();
+            return;
+        }
+
+        self.read_choice(
+            &translate(question_template).replacen("{}", default_name, 1),
+            choices,
+            Some(default_index),
+        );
+    }
+
+    // Shows the translated question with numbered choices until a valid number is entered.
+    fn read_choice(
+        &mut self,
+        translated_question: &str,
+        choices: &[&str],
+        default_index: Option<usize>,
+    ) {
         loop {
             self.answer.clear();
-            println!("{}\n", question);
-            for (index, choice) in choices.into_iter().enumerate() {
-                println!("{}. {choice}", index + 1);
+            println!("{translated_question}\n");
+            for (index, choice) in choices.iter().enumerate() {
+                println!("{}. {}", index + 1, translate(choice));
             }
-            print!("\nEnter number: ");
+            let prompt = translate("Enter number: ");
+            print!("\n{prompt}");
             io::stdout().flush().unwrap();
-            io::stdin().read_line(&mut self.answer).unwrap();
-            self.answer = self.answer.trim().to_string();
-            if let Ok(num) = self.answer.parse::<u8>() {
-                if num <= choices.len() as u8 && num > 0 {
+            self.answer = self.read_answer(&prompt, false).trim().to_string();
+            if let (true, Some(default_index)) = (self.answer.is_empty(), default_index) {
+                self.answer = (default_index + 1).to_string();
+                break;
+            }
+            if let Ok(num) = self.answer.parse::<usize>() {
+                if num <= choices.len() && num > 0 {
                     break;
                 }
             } else {
-                println!("\nError: Enter only the number!\n");
+                println!("\n{}\n", translate("Error: Enter only the number!"));
             }
         }
     }
-}
-
-#[derive(Debug)]
-enum AppError {
-    InternalError(String),
-    ExternalError(String),
-}
 
-impl error::Error for AppError {}
+    // Asks for a search term first and then lets the user select one of the matching choices.
+    fn searching_ask<'a>(
+        &mut self,
+        search_question: &str,
+        question: &str,
+        choices: &[&'a str],
+    ) -> &'a str {
+        if let Some(choice_index) = self.take_preset_choice(question, choices) {
+            return choices[choice_index];
+        }
 
-impl fmt::Display for AppError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::InternalError(internal_error) => {
-                write!(f, "{}", internal_error)
-            }
-            Self::ExternalError(external_error) => {
-                write!(f, "{}", external_error)
+        loop {
+            self.ask(search_question);
+            let search_term = self.answer.to_lowercase();
+            let found_choices = choices
+                .iter()
+                .copied()
+                .filter(|c| c.to_lowercase().contains(&search_term))
+                .collect::<Vec<&str>>();
+
+            if found_choices.is_empty() {
+                print_operation_result(OperationResult::Error);
+                println!(
+                    "{}\n",
+                    translate(&format!("Nothing matched '{}'. Try again.", self.answer))
+                );
+                continue;
             }
+
+            self.selecting_ask(question, &found_choices);
+            return found_choices[self.selected_index()];
         }
     }
-}
 
-impl From<io::Error> for AppError {
-    fn from(value: io::Error) -> Self {
-        Self::InternalError(value.to_string())
+    // Lets the user narrow long choice lists by typing and pick a choice with the arrow keys.
+    // Without line editing, the search question and a numbered list are asked instead.
+    fn picking_ask<'a>(
+        &mut self,
+        search_question: &str,
+        question: &str,
+        choices: &[&'a str],
+    ) -> &'a str {
+        if !is_line_editing_available() {
+            return self.searching_ask(search_question, question, choices);
+        }
+        if let Some(choice_index) = self.take_preset_choice(question, choices) {
+            return choices[choice_index];
+        }
+
+        if self.is_unattended {
+            fail_unanswered_question(question);
+        }
+        self.notify_if_idle();
+        match pick(question, choices, false) {
+            Ok(picked_indexes) => {
+                self.last_answer_time = time::Instant::now();
+                choices[picked_indexes[0]]
+            }
+            // Terminal couldn't be read as keys (e.g. input ended), so whole lines are tried instead.
+            Err(_) => self.searching_ask(search_question, question, choices),
+        }
     }
-}
 
-struct AppConfig {
-    uefi_install: bool,
-    uefi_partition: Option<String>,
-    boot_partition: Option<String>,
-    root_partition: String,
-    home_partition: Option<String>,
-    username: String,
-    encrypted_partitons: bool,
-    swap_partition: Option<String>,
-    current_installation_step: u8,
-    total_installation_steps: u8,
-}
+    // Like picking_ask, but choices are marked with Tab and Enter finishes. Nothing marked selects nothing.
+    fn multi_picking_ask(&mut self, question: &str, choices: &[&str]) -> Vec<usize> {
+        if !is_line_editing_available() {
+            return self.multi_selecting_ask(question, choices);
+        }
+        if let Some(choice_indexes) = self.take_preset_choices(question, choices) {
+            return choice_indexes;
+        }
 
-impl AppConfig {
-    fn new(total_installation_steps: u8) -> Self {
-        Self {
-            uefi_install: false,
-            uefi_partition: None,
-            boot_partition: None,
-            root_partition: String::new(),
-            home_partition: None,
-            username: String::new(),
-            encrypted_partitons: false,
-            swap_partition: None,
-            current_installation_step: 1,
-            total_installation_steps,
+        if self.is_unattended {
+            fail_unanswered_question(question);
+        }
+        self.notify_if_idle();
+        match pick(question, choices, true) {
+            Ok(picked_indexes) => {
+                self.last_answer_time = time::Instant::now();
+                picked_indexes
+            }
+            // Terminal couldn't be read as keys (e.g. input ended), so whole lines are tried instead.
+            Err(_) => self.multi_selecting_ask(question, choices),
         }
     }
 
-    fn print_installation_status_and_save_config(&mut self, text: &str) {
-        TextManager::set_color(TextColor::Cyan);
-        let mut remaining_line_length = MAX_LINE_LENGTH - text.len() as u8;
-        let mut individual_remaining_space = (remaining_line_length - 1) / 2;
+    // Returns indexes of the selected choices. An empty answer selects nothing.
+    fn multi_selecting_ask(&mut self, question: &str, choices: &[&str]) -> Vec<usize> {
+        if let Some(choice_indexes) = self.take_preset_choices(question, choices) {
+            return choice_indexes;
+        }
 
-        let mut format_string = (0..individual_remaining_space - 1)
-            .map(|_i| "-")
-            .collect::<String>();
+        'asking: loop {
+            println!("{}\n", translate(question));
+            for (index, choice) in choices.iter().enumerate() {
+                println!("{}. {}", index + 1, translate(choice));
+            }
+            self.ask_user("\nEnter numbers separated by spaces (Leave empty to select nothing): ");
+
+            let mut selected_indexes = Vec::new();
+            for number in self.answer.split([' ', ',']).filter(|n| !n.is_empty()) {
+                match number.parse::<usize>() {
+                    Ok(num) if num <= choices.len() && num > 0 => {
+                        if !selected_indexes.contains(&(num - 1)) {
+                            selected_indexes.push(num - 1);
+                        }
+                    }
+                    _ => {
+                        println!(
+                            "\n{}\n",
+                            translate(&format!("Error: '{number}' is not a valid choice!"))
+                        );
+                        continue 'asking;
+                    }
+                }
+            }
 
-        if remaining_line_length % 2 == 0 {
-            println!("\n-{} {text} {}-", format_string, format_string);
-        } else {
-            println!("\n{} {text} {}-", format_string, format_string);
+            return selected_indexes;
         }
-        let empty_bordered_line = (0..MAX_LINE_LENGTH - 2).map(|_i| " ").collect::<String>();
-        println!("|{}|", empty_bordered_line);
+    }
 
-        let percentage = format!(
-            "{}/{} | {}",
-            self.current_installation_step,
-            self.total_installation_steps,
-            ((self.current_installation_step as f32 / self.total_installation_steps as f32) * 100.0)
-                as u8
-        );
-        remaining_line_length = MAX_LINE_LENGTH - percentage.len() as u8;
-        individual_remaining_space = (remaining_line_length - 1) / 2;
+    // Like take_preset_choice, but the answer lists choices separated by commas. An empty answer selects nothing.
+    fn take_preset_choices(&mut self, question: &str, choices: &[&str]) -> Option<Vec<usize>> {
+        let preset_answer = self.take_preset_answer(question)?;
+        let choice_indexes = preset_answer
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(|c| find_choice_index(c, choices))
+            .collect::<Option<Vec<usize>>>();
+        match choice_indexes {
+            Some(choice_indexes) => {
+                println!("{}\n{}\n", translate(question), preset_answer);
+                Some(choice_indexes)
+            }
+            None => {
+                print_invalid_preset_answer(&preset_answer);
+                None
+            }
+        }
+    }
 
-        format_string = (0..individual_remaining_space - 3)
-            .map(|_i| "-")
-            .collect::<String>();
+    // Reads a line with terminal echo disabled. The answer field is left untouched.
+    fn hidden_ask(&mut self, question: &str) -> String {
+        if let Some(preset_answer) = self.take_preset_answer(question) {
+            println!("{}", translate(question));
+            return preset_answer;
+        }
 
-        if remaining_line_length % 2 == 0 {
-            println!("{}> [{percentage}%] <{}-\n", format_string, format_string);
-        } else {
-            println!("{}> [{percentage}%] <{}\n", format_string, format_string);
+        let question = translate(question);
+        print!("{}", question);
+        io::stdout().flush().unwrap();
+
+        let _ = process::Command::new("stty").arg("-echo").status();
+        let hidden_answer = self.read_answer(&question, true);
+        let _ = process::Command::new("stty").arg("echo").status();
+        println!();
+
+        hidden_answer.trim_end_matches(['\n', '\r']).to_string()
+    }
+
+    // Checks without waiting whether the given key was typed since the last question. Other typed keys are discarded.
+    fn was_key_pressed(&mut self, key: char) -> bool {
+        self.read_pressed_keys()
+            .iter()
+            .any(|b| b.eq_ignore_ascii_case(&(key as u8)))
+    }
+
+    // Returns the keys typed since the last question without waiting. (Nothing if stdin isn't a terminal)
+    fn read_pressed_keys(&mut self) -> Vec<u8> {
+        let Ok(status) = process::Command::new("stty")
+            .args(["-icanon", "min", "0", "time", "0"])
+            .stderr(process::Stdio::null())
+            .status()
+        else {
+            return Vec::new();
+        };
+        if !status.success() {
+            return Vec::new();
         }
-        TextManager::reset_color_and_graphics();
 
-        self.save_config();
+        let mut pressed_keys = Vec::new();
+        let mut buffer = [0; 64];
+        while let Ok(read_bytes_count @ 1..) = io::stdin().read(&mut buffer) {
+            pressed_keys.extend_from_slice(&buffer[..read_bytes_count]);
+        }
+        let _ = process::Command::new("stty").arg("icanon").status();
+
+        pressed_keys
     }
 
-    fn save_config(&mut self) {
-        let app_config_string = format!(
-            "{}\n{:?}\n{:?}\n{}\n{:?}\n{}\n{}\n{:?}\n{}\n{}",
-            self.uefi_install,
-            self.uefi_partition,
-            self.boot_partition,
-            self.root_partition,
-            self.home_partition,
-            self.username,
-            self.encrypted_partitons,
-            self.swap_partition,
-            self.current_installation_step,
-            self.total_installation_steps
-        );
+    // Asks for a password twice with hidden input and shows its strength.
+    // A password of the answers file is used without confirmation.
+    fn password_ask(&mut self, question: &str) -> String {
+        if let Some(preset_answer) = self.take_preset_answer(question).filter(|a| !a.is_empty()) {
+            println!("{}", translate(question));
+            return preset_answer;
+        }
+
+        loop {
+            let password = self.hidden_ask(question);
+            if password.is_empty() {
+                println!("\n{}\n", translate("Error: Password can't be empty!"));
+                continue;
+            }
+
+            let password_strength = estimate_password_strength(&password);
+            println!(
+                "{}",
+                translate(&format!("Password strength: {password_strength}"))
+            );
+            if let PasswordStrength::Weak = password_strength {
+                if !self.bool_ask("This password is weak. Do you want to use it anyway?") {
+                    continue;
+                }
+            }
+
+            if password != self.hidden_ask("Enter the password again: ") {
+                println!("\n{}\n", translate("Error: Passwords don't match!"));
+                continue;
+            }
 
-        fs::write("./arch_linux_installer.conf", app_config_string)
-            .expect("Error writing to ./arch_linux_installer.conf");
+            return password;
+        }
     }
 
-    fn load_config(&mut self) -> Result<(), AppError> {
-        let app_config_string = String::from_utf8(fs::read("./arch_linux_installer.conf")?).expect(
-            "Error converting ./arch_linux_installer.conf contents to a valid UTF-8 string.",
-        );
+    // Index of the choice selected by the last selecting_ask call.
+    fn selected_index(&self) -> usize {
+        self.answer.parse::<usize>().unwrap() - 1
+    }
+}
 
-        let app_config_elements = app_config_string.split("\n").collect::<Vec<_>>();
+// Answers of an answers file after the profiles it extends were applied.
+struct AnswersFile {
+    // (Part of the question, Answer)
+    answers: Vec<(String, String)>,
+    // None if neither the file nor its profiles have a skip line.
+    skipped_steps: Option<Vec<String>>,
+}
 
-        self.uefi_install = if app_config_elements[0] == "true" {
-            true
-        } else {
-            false
-        };
-        self.uefi_partition = if app_config_elements[1] == "None" {
-            None
-        } else {
-            Some(Self::extract_some_value(app_config_elements[1]))
-        };
-        self.boot_partition = if app_config_elements[2] == "None" {
-            None
-        } else {
-            Some(Self::extract_some_value(app_config_elements[2]))
-        };
-        self.root_partition = app_config_elements[3].to_string();
-        self.home_partition = if app_config_elements[4] == "None" {
-            None
-        } else {
-            Some(Self::extract_some_value(app_config_elements[4]))
-        };
-        self.username = app_config_elements[5].to_string();
-        self.encrypted_partitons = if app_config_elements[6] == "true" {
-            true
-        } else {
-            false
+// Parses "<part of the question> => <answer>" lines and the "extends = <profile>" and "skip = <step IDs>" lines.
+// Lines starting with # are comments. A profile is a built-in one of ANSWER_PROFILES or an answers file relative to
+// the location of the extending file, which may be a URL. Answers of the extending file replace all answers of the profile to the same
+// question, and its skip line replaces the one of the profile.
+fn parse_answers_file(
+    answers_content: &str,
+    source_name: &str,
+    extended_profiles: &mut Vec<String>,
+) -> Result<AnswersFile, AppError> {
+    let mut answers_file = AnswersFile {
+        answers: Vec::new(),
+        skipped_steps: None,
+    };
+    let mut profile_name = None;
+    for (line_number, line) in answers_content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid_line_error = |reason: &str| {
+            AppError::InternalError(format!(
+                "Error! Invalid line {} in {}: {}",
+                line_number + 1,
+                source_name,
+                reason
+            ))
         };
-        self.swap_partition = if app_config_elements[7] == "None" {
-            None
-        } else {
-            Some(Self::extract_some_value(app_config_elements[7]))
+
+        if let Some((question_part, answer)) = line.split_once("=>") {
+            answers_file
+                .answers
+                .push((question_part.trim().to_string(), answer.trim().to_string()));
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(invalid_line_error(
+                "Expected '<part of the question> => <answer>'",
+            ));
         };
-        self.current_installation_step = app_config_elements[8]
-            .parse()
-            .expect("Error parsing string to u8");
-        self.total_installation_steps = app_config_elements[9]
-            .parse()
-            .expect("Error parsing string to u8");
+        match key.trim() {
+            "extends" => profile_name = Some(value.trim().trim_matches('"').to_string()),
+            "skip" => {
+                answers_file.skipped_steps = Some(
+                    value
+                        .split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty())
+                        .collect(),
+                )
+            }
+            _ => {
+                return Err(invalid_line_error(
+                    format!("Unknown key '{}'", key.trim()).as_str(),
+                ))
+            }
+        }
+    }
 
-        Ok(())
+    let Some(profile_name) = profile_name else {
+        return Ok(answers_file);
+    };
+    if extended_profiles.contains(&profile_name) {
+        return Err(AppError::InternalError(format!(
+            "Error! Profile '{profile_name}' extends itself through {source_name}."
+        )));
     }
+    extended_profiles.push(profile_name.clone());
+
+    let profile = match ANSWER_PROFILES.iter().find(|(n, _)| *n == profile_name) {
+        Some((_, profile_content)) => parse_answers_file(
+            profile_content,
+            &format!("profile {profile_name}"),
+            extended_profiles,
+        )?,
+        None => {
+            // Built-in profiles have no location, so their user profiles are relative to the working directory.
+            let profile_location = if source_name.starts_with("profile ") {
+                profile_name.clone()
+            } else {
+                resolve_answers_location(source_name, &profile_name)
+            };
+            let profile_content = read_answers_file(&profile_location)?;
+            parse_answers_file(&profile_content, &profile_location, extended_profiles)?
+        }
+    };
+    let profile_answers = profile
+        .answers
+        .into_iter()
+        .filter(|(question_part, _)| {
+            !answers_file
+                .answers
+                .iter()
+                .any(|(overriding_question_part, _)| overriding_question_part == question_part)
+        })
+        .collect::<Vec<_>>();
+    answers_file.answers.extend(profile_answers);
+    answers_file.skipped_steps = answers_file.skipped_steps.or(profile.skipped_steps);
+
+    Ok(answers_file)
+}
 
-    fn remove_config(&self) {
-        fs::remove_file("./arch_linux_installer.conf")
-            .expect("Error removing ./arch_linux_installer.conf")
+// Reads an answers file from a path or downloads it from an HTTP(S) URL. The network may still be starting in a
+// netbooted live environment, so downloads are retried.
+fn read_answers_file(answers_location: &str) -> Result<String, AppError> {
+    if !answers_location.starts_with("http://") && !answers_location.starts_with("https://") {
+        return fs::read_to_string(answers_location).map_err(|error| {
+            AppError::InternalError(format!("Error! {answers_location} can't be read: {error}"))
+        });
     }
 
-    fn extract_some_value(some: &str) -> String {
-        some.split("\"").collect::<Vec<_>>()[1].to_string()
+    println!("Downloading answers file: {answers_location}\n");
+    run_command_with_retries(
+        "curl",
+        Some(&[
+            "--fail",
+            "--silent",
+            "--show-error",
+            "--location",
+            "--max-time",
+            "30",
+            "--output",
+            DOWNLOADED_ANSWERS_FILE_PATH,
+            answers_location,
+        ]),
+        NETWORK_RETRY_POLICY,
+    )
+    .map_err(|_| {
+        AppError::InternalError(format!(
+            "Error! Answers file {answers_location} couldn't be downloaded."
+        ))
+    })?;
+
+    Ok(fs::read_to_string(DOWNLOADED_ANSWERS_FILE_PATH)?)
+}
+
+// Location of a profile relative to the answers file which extends it. Absolute paths and URLs are kept.
+fn resolve_answers_location(answers_location: &str, profile_name: &str) -> String {
+    if profile_name.starts_with('/') || profile_name.contains("://") {
+        return profile_name.to_string();
     }
 
-    fn reset(&mut self) {
-        self.uefi_install = false;
-        self.uefi_partition = None;
-        self.boot_partition = None;
-        self.root_partition = String::new();
-        self.home_partition = None;
-        self.username = String::new();
-        self.encrypted_partitons = false;
-        self.swap_partition = None;
-        self.current_installation_step = 1;
+    // URL without a path like "https://example.com" is a directory itself.
+    let parent_location = match answers_location.split_once("://") {
+        Some((_, address)) if !address.contains('/') => Some(answers_location),
+        _ => answers_location.rsplit_once('/').map(|(p, _)| p),
+    };
+    match parent_location {
+        Some(parent_location) => format!("{parent_location}/{profile_name}"),
+        None => profile_name.to_string(),
     }
 }
 
-// Colors encoded in ANSI escape code
-#[derive(Clone, Copy)]
-enum TextColor {
-    Reset,
-    Black = 30,
-    Red,
-    Green,
-    Yellow,
-    Blue,
-    Magenta,
-    Cyan,
-    White,
-    Default = 39,
+// Returns the value of a "<name>=<value>" parameter of the kernel command line, or an empty string for a "<name>"
+// flag, so a netbooted live environment can be configured by its boot loader. (e.g. ali.answers=<path or URL>)
+fn find_kernel_parameter(name: &str) -> Option<String> {
+    fs::read_to_string("/proc/cmdline")
+        .ok()?
+        .split_whitespace()
+        .find_map(|p| match p.split_once('=') {
+            Some((parameter_name, value)) => (parameter_name == name).then_some(value),
+            None => (p == name).then_some(""),
+        })
+        .map(String::from)
 }
 
-impl fmt::Display for TextColor {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", *self as u8)
-    }
+// Finds the choice given as "choice:<text>", as the text of the choice or as its number.
+fn find_choice_index(answer: &str, choices: &[&str]) -> Option<usize> {
+    let choice_text = answer.strip_prefix("choice:").unwrap_or(answer).trim();
+    choices.iter().position(|c| *c == choice_text).or_else(|| {
+        choice_text
+            .parse::<usize>()
+            .ok()
+            .filter(|n| (1..=choices.len()).contains(n))
+            .map(|n| n - 1)
+    })
 }
 
-#[derive(Clone, Copy)]
-enum TextGraphics {
-    Bold = 1,
-    Dim,
-    Italic,
-    Underline,
-    Blinking,
-    Inverse = 7,
-    Hidden,
-    Strikethrough,
+// Stops the hands-off installation like a failed step, because waiting for an answer would never end.
+fn fail_unanswered_question(question: &str) -> ! {
+    stop_waiting_for_answer(&format!(
+        "Installation failed: '{}' has no answer in the answers file.",
+        question.trim()
+    ))
 }
 
-impl fmt::Display for TextGraphics {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", *self as u8)
-    }
+// Stops the installation if stdin is closed or a redirected file ended, because no answer can be read anymore.
+fn fail_closed_input(question: &str) -> ! {
+    stop_waiting_for_answer(&format!(
+        "Installation failed: Input ended before '{}' was answered.",
+        question.trim()
+    ))
 }
 
-struct TextManager;
-
-impl TextManager {
-    fn set_color(color: TextColor) {
-        print!("\x1b[{color}m");
+fn stop_waiting_for_answer(message: &str) -> ! {
+    println!();
+    print_operation_result(OperationResult::Error);
+    println!("{message}\n");
+    append_to_log(message);
+    send_notification(message);
+    if let Err(error) = cleanup_installation() {
+        println!("Cleanup failed: {error}\n");
     }
 
-    fn set_graphics(graphics: TextGraphics) {
-        print!("\x1b[{graphics}m");
-    }
+    process::exit(1);
+}
 
-    fn reset_color_and_graphics() {
-        print!("\x1b[{}m", TextColor::Reset);
-    }
+fn print_invalid_preset_answer(preset_answer: &str) {
+    println!(
+        "\n{}\n",
+        translate(&format!(
+            "Error: Answer '{preset_answer}' of the answers file isn't valid here."
+        ))
+    );
 }
 
-enum OperationResult {
-    Done,
-    Error,
+// Line editing needs a terminal for both input and output. Plain output is for serial consoles and logs,
+// so answers are read as whole lines there.
+fn is_line_editing_available() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal() && !TextManager::is_plain()
 }
 
-fn main() -> Result<(), AppError> {
-    // Initializing question struct to use it in various parts of the program.
-    let mut question = Question::new();
+// Reads a line with these keys: Left and right (Ctrl-B, Ctrl-F), Home and End (Ctrl-A, Ctrl-E), Backspace, Delete,
+// Ctrl-U and Ctrl-K (delete before or after the cursor), Ctrl-W (delete the previous word), up and down
+// (earlier answers) and Tab (complete file system paths and device names).
+// Terminal stays in signal generating mode, so Ctrl-C interrupts the installer as usual.
+fn edit_line(prompt_line: &str, history: &[String]) -> io::Result<String> {
+    let _ = process::Command::new("stty")
+        .args(["-icanon", "-echo", "min", "1", "time", "0"])
+        .status();
+    let result = read_edited_line(prompt_line, history);
+    let _ = process::Command::new("stty")
+        .args(["icanon", "echo"])
+        .status();
+    println!();
+
+    result
+}
 
-    print_welcome_message();
+fn read_edited_line(prompt_line: &str, history: &[String]) -> io::Result<String> {
+    let mut stdin = io::stdin();
+    let mut line: Vec<char> = Vec::new();
+    let mut cursor = 0;
+    // Index of the shown history entry. history.len() is the typed line, which is kept while browsing the history.
+    let mut history_index = history.len();
+    let mut typed_line = Vec::new();
+    // Columns between the start of the line and the cursor on the screen.
+    let mut shown_cursor_width = 0;
 
-    if !question.bool_ask("Do you want to continue?") {
-        return Ok(());
+    loop {
+        let Some(byte) = read_byte(&mut stdin)? else {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        };
+        match byte {
+            b'\r' | b'\n' => break,
+            // Ctrl-D
+            0x04 if line.is_empty() => break,
+            0x01 => cursor = 0,
+            0x05 => cursor = line.len(),
+            0x02 => cursor = cursor.saturating_sub(1),
+            0x06 => cursor = (cursor + 1).min(line.len()),
+            0x7F | 0x08 if cursor > 0 => {
+                cursor -= 1;
+                line.remove(cursor);
+            }
+            0x15 => {
+                line.drain(..cursor);
+                cursor = 0;
+            }
+            0x0B => line.truncate(cursor),
+            0x17 => {
+                let mut word_start = cursor;
+                while word_start > 0 && line[word_start - 1].is_whitespace() {
+                    word_start -= 1;
+                }
+                while word_start > 0 && !line[word_start - 1].is_whitespace() {
+                    word_start -= 1;
+                }
+                line.drain(word_start..cursor);
+                cursor = word_start;
+            }
+            b'\t' => {
+                let word_start = line[..cursor]
+                    .iter()
+                    .rposition(|c| c.is_whitespace())
+                    .map_or(0, |i| i + 1);
+                let word = line[word_start..cursor].iter().collect::<String>();
+                let completions = find_completions(&word);
+                let common_prefix = find_common_prefix(&completions);
+
+                if completions.is_empty() {
+                    print!("\x07");
+                } else if common_prefix.chars().count() > word.chars().count() {
+                    line.splice(word_start..cursor, common_prefix.chars());
+                    cursor = word_start + common_prefix.chars().count();
+                } else if completions.len() > 1 {
+                    // Only the last part of the paths is listed, like shells do.
+                    let directory_length = word.rfind('/').map_or(0, |i| i + 1);
+                    let completion_names = completions
+                        .iter()
+                        .map(|c| &c[directory_length..])
+                        .collect::<Vec<&str>>();
+                    print!("\n{}\n{prompt_line}", completion_names.join("  "));
+                    shown_cursor_width = 0;
+                }
+            }
+            0x1B => {
+                let Some(b'[' | b'O') = read_byte(&mut stdin)? else {
+                    continue;
+                };
+                let mut sequence = Vec::new();
+                while let Some(byte) = read_byte(&mut stdin)? {
+                    sequence.push(byte);
+                    if !byte.is_ascii_digit() && byte != b';' {
+                        break;
+                    }
+                }
+                match sequence.as_slice() {
+                    b"A" if history_index > 0 => {
+                        if history_index == history.len() {
+                            typed_line = line.clone();
+                        }
+                        history_index -= 1;
+                        line = history[history_index].chars().collect();
+                        cursor = line.len();
+                    }
+                    b"B" if history_index < history.len() => {
+                        history_index += 1;
+                        line = match history.get(history_index) {
+                            Some(history_entry) => history_entry.chars().collect(),
+                            None => typed_line.clone(),
+                        };
+                        cursor = line.len();
+                    }
+                    b"C" => cursor = (cursor + 1).min(line.len()),
+                    b"D" => cursor = cursor.saturating_sub(1),
+                    b"H" | b"1~" | b"7~" => cursor = 0,
+                    b"F" | b"4~" | b"8~" => cursor = line.len(),
+                    b"3~" if cursor < line.len() => {
+                        line.remove(cursor);
+                    }
+                    _ => {}
+                }
+            }
+            byte if byte >= 0x20 && byte != 0x7F => {
+                // Rest of a multibyte UTF-8 character is read, so it's inserted as one character.
+                let character_length = match byte {
+                    0xF0.. => 4,
+                    0xE0.. => 3,
+                    0xC0.. => 2,
+                    _ => 1,
+                };
+                let mut character_bytes = vec![byte];
+                for _ in 1..character_length {
+                    if let Some(byte) = read_byte(&mut stdin)? {
+                        character_bytes.push(byte);
+                    }
+                }
+                for character in String::from_utf8_lossy(&character_bytes).chars() {
+                    line.insert(cursor, character);
+                    cursor += 1;
+                }
+            }
+            _ => {}
+        }
+
+        shown_cursor_width = redraw_line(&line, cursor, shown_cursor_width);
     }
 
-    // Initializing app_config struct to use it in various parts of the program.
-    let mut app_config = AppConfig::new(INSTALLATION_STEPS_COUNT);
+    Ok(line.into_iter().collect())
+}
 
-    if let Ok(()) = app_config.load_config() {
-        TextManager::set_color(TextColor::Yellow);
-        formatted_print(
-            "Aborted installation was detected",
-            PrintFormat::DoubleDashedLine,
-        );
-        TextManager::reset_color_and_graphics();
-        if !question.bool_ask(
-            format!(
-                "Do you want to continue installation from step ({}/{})?",
-                app_config.current_installation_step, app_config.total_installation_steps
-            )
-            .as_str(),
-        ) {
-            app_config.reset();
+fn read_byte(stdin: &mut io::Stdin) -> io::Result<Option<u8>> {
+    let mut buffer = [0; 1];
+    loop {
+        match stdin.read(&mut buffer) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(buffer[0])),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
         }
     }
+}
 
-    loop {
-        match app_config.current_installation_step {
-            1 => {
-                app_config
-                    .print_installation_status_and_save_config("BIOS / UEFI Installation mode");
-
-                question.selecting_ask("Which installation mode do you want?", &["BIOS", "UEFI"]);
-                if question.answer == "2" {
-                    app_config.uefi_install = true;
-                }
-
-                print_operation_result(OperationResult::Done);
-            }
-            2 => {
-                app_config.print_installation_status_and_save_config("Encrypted partitoins");
+// Moves to the start of the line, prints it again and puts the cursor back. Returns the new cursor column.
+fn redraw_line(line: &[char], cursor: usize, shown_cursor_width: usize) -> usize {
+    let mut output = String::new();
+    if shown_cursor_width > 0 {
+        output.push_str(&format!("\x1b[{shown_cursor_width}D"));
+    }
+    output.extend(line);
+    output.push_str("\x1b[K");
+    let after_cursor_width = display_width(&line[cursor..].iter().collect::<String>());
+    if after_cursor_width > 0 {
+        output.push_str(&format!("\x1b[{after_cursor_width}D"));
+    }
+    print!("{output}");
+    let _ = io::stdout().flush();
 
-                if question.bool_ask("Do you want to encrypt your root and home partitions?") {
-                    app_config.encrypted_partitons = true;
-                }
-            }
-            3 => {
-                app_config.print_installation_status_and_save_config("Configuring timedatectl");
+    display_width(&line[..cursor].iter().collect::<String>())
+}
 
-                run_command("timedatectl", Some(&["set-ntp", "true"]))?;
-                run_command("timedatectl", Some(&["status"]))?;
+// Uses the language given with --language or else the one of the LANG environment variables. English needs no catalog.
+fn select_language(language: Option<&str>) -> Result<(), AppError> {
+    let language_code = match language {
+        Some(language) => language.to_string(),
+        None => ["LANGUAGE", "LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|v| env::var(v).ok())
+            .find(|v| !v.is_empty())
+            .unwrap_or_default()
+            .split(['_', '.', ':'])
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+    };
+
+    match TRANSLATIONS.iter().find(|(code, _)| *code == language_code) {
+        Some((_, catalog)) => {
+            let messages = catalog
+                .lines()
+                .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+                .filter_map(|l| l.split_once("=>"))
+                .map(|(english_text, translation)| (english_text.trim(), translation.trim()))
+                .collect::<Vec<(&str, &str)>>();
+            let _ = MESSAGE_CATALOG.set(messages);
+            Ok(())
+        }
+        // Unknown languages of the environment (C, POSIX, ...) fall back to English. Only a wrong flag is an error.
+        None if language.is_some() && language_code != "en" => {
+            Err(AppError::InternalError(format!(
+                "Error! Unknown language: {language_code}. Available languages: en, {}",
+                TRANSLATIONS
+                    .iter()
+                    .map(|(code, _)| *code)
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            )))
+        }
+        None => Ok(()),
+    }
+}
 
-                print_operation_result(OperationResult::Done);
-            }
-            4 => {
-                app_config.print_installation_status_and_save_config("Configuring partitions");
+// Returns the translation of the text in the selected language, or the text itself if there is none.
+// Whitespace around the text is kept, so "\nEnter number: " and "Enter number:" share one translation.
+fn translate(text: &str) -> String {
+    let Some(messages) = MESSAGE_CATALOG.get() else {
+        return text.to_string();
+    };
+    let trimmed_text = text.trim();
+    if trimmed_text.is_empty() {
+        return text.to_string();
+    }
 
-                run_command("fdisk", Some(&["-l"]))?;
+    let translation = messages.iter().find_map(|(english_text, translation)| {
+        if *english_text == trimmed_text {
+            return Some(translation.to_string());
+        }
+        let values = match_message_template(english_text, trimmed_text)?;
+        let mut translation_parts = translation.split("{}");
+        let mut filled_translation = translation_parts.next().unwrap_or_default().to_string();
+        for (translation_part, value) in translation_parts.zip(values) {
+            filled_translation.push_str(value);
+            filled_translation.push_str(translation_part);
+        }
+        Some(filled_translation)
+    });
+
+    match translation {
+        Some(translation) => {
+            let leading_whitespace = &text[..text.len() - text.trim_start().len()];
+            let trailing_whitespace = &text[text.trim_end().len()..];
+            format!("{leading_whitespace}{translation}{trailing_whitespace}")
+        }
+        None => text.to_string(),
+    }
+}
 
-                question.ask("Enter the disk you want to partion. (sda, sdb, ...): ");
-                run_command(
-                    "fdisk",
-                    Some(&[format!("/dev/{}", question.answer).as_str()]),
-                )?;
+// Returns the values filling the "{}" placeholders if the text matches the template.
+fn match_message_template<'a>(template: &str, text: &'a str) -> Option<Vec<&'a str>> {
+    if !template.contains("{}") {
+        return None;
+    }
 
-                println!("Partitioning results:\n");
+    let mut template_parts = template.split("{}");
+    let mut remaining_text = text.strip_prefix(template_parts.next()?)?;
+    let template_parts = template_parts.collect::<Vec<&str>>();
+    let mut values = Vec::new();
+    for (index, template_part) in template_parts.iter().enumerate() {
+        let value_length = if index == template_parts.len() - 1 {
+            remaining_text.strip_suffix(template_part)?.len()
+        } else if template_part.is_empty() {
+            return None;
+        } else {
+            remaining_text.find(template_part)?
+        };
+        if value_length == 0 {
+            return None;
+        }
+        values.push(&remaining_text[..value_length]);
+        remaining_text = &remaining_text[value_length + template_part.len()..];
+    }
 
-                run_command("lsblk", None)?;
+    Some(values)
+}
 
-                print_operation_result(OperationResult::Done);
-            }
-            5 => {
-                app_config.print_installation_status_and_save_config("Getting partition names");
+// Picker keys: Typing narrows the choices, Backspace and Ctrl-U change the filter, up and down (Page Up, Page Down)
+// move the selection, Tab marks choices if more than one can be picked and Enter picks.
+fn pick(question: &str, choices: &[&str], is_multiple: bool) -> io::Result<Vec<usize>> {
+    println!("{}\n", translate(question));
+    let _ = process::Command::new("stty")
+        .args(["-icanon", "-echo", "min", "1", "time", "0"])
+        .status();
+    let result = run_picker(choices, is_multiple);
+    let _ = process::Command::new("stty")
+        .args(["icanon", "echo"])
+        .status();
+
+    if let Ok(picked_indexes) = &result {
+        let picked_choices = picked_indexes
+            .iter()
+            .map(|i| choices[*i])
+            .collect::<Vec<&str>>();
+        println!("> {}\n", picked_choices.join(", "));
+    }
 
-                question.ask("Enter the name of your root partition: ");
-                app_config.root_partition = question.answer.clone();
+    result
+}
 
-                if question.bool_ask("Do you have a separate boot partition?") {
-                    question.ask("Enter the name of your boot partition: ");
-                    app_config.boot_partition = Some(question.answer.clone());
-                }
+fn run_picker(choices: &[&str], is_multiple: bool) -> io::Result<Vec<usize>> {
+    let mut stdin = io::stdin();
+    let mut filter = String::new();
+    let mut matching_indexes = (0..choices.len()).collect::<Vec<usize>>();
+    let mut marked_indexes: Vec<usize> = Vec::new();
+    // Selected row and the first shown row are positions in matching_indexes.
+    let mut selected_row = 0;
+    let mut first_shown_row = 0;
+    let mut drawn_lines_count = 0;
 
-                if app_config.uefi_install {
-                    question.ask("Enter the name of your uefi partition: ");
-                    app_config.uefi_partition = Some(question.answer.clone());
-                }
+    loop {
+        if selected_row < first_shown_row {
+            first_shown_row = selected_row;
+        } else if selected_row >= first_shown_row + PICKER_ROWS_COUNT {
+            first_shown_row = selected_row + 1 - PICKER_ROWS_COUNT;
+        }
+        clear_drawn_lines(drawn_lines_count);
+        drawn_lines_count = draw_picker(
+            choices,
+            &filter,
+            &matching_indexes,
+            &marked_indexes,
+            selected_row,
+            first_shown_row,
+            is_multiple,
+        );
 
-                if question.bool_ask("Do you have a separate home partition?") {
-                    question.ask("Enter the name of your home partition: ");
-                    app_config.home_partition = Some(question.answer.clone());
+        let Some(byte) = read_byte(&mut stdin)? else {
+            clear_drawn_lines(drawn_lines_count);
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        };
+        let previous_filter = filter.clone();
+        match byte {
+            b'\r' | b'\n' if is_multiple => {
+                clear_drawn_lines(drawn_lines_count);
+                marked_indexes.sort();
+                return Ok(marked_indexes);
+            }
+            b'\r' | b'\n' => {
+                if let Some(matching_index) = matching_indexes.get(selected_row) {
+                    clear_drawn_lines(drawn_lines_count);
+                    return Ok(vec![*matching_index]);
                 }
-
-                print_operation_result(OperationResult::Done);
             }
-            6 => {
-                app_config.print_installation_status_and_save_config("Formatting partitions");
-
-                if question.bool_ask("Do you want to format your root partition?") {
-                    if app_config.encrypted_partitons {
-                        run_command(
-                            "cryptsetup",
-                            Some(&[
-                                "luksFormat",
-                                format!("/dev/{}", app_config.root_partition).as_str(),
-                            ]),
-                        )?;
-                        run_command(
-                            "cryptsetup",
-                            Some(&[
-                                "open",
-                                format!("/dev/{}", app_config.root_partition).as_str(),
-                                "cryptroot",
-                            ]),
-                        )?;
-                        run_command("mkfs.btrfs", Some(&["-f", "/dev/mapper/cryptroot"]))?;
-                    } else {
-                        run_command(
-                            "mkfs.btrfs",
-                            Some(&["-f", format!("/dev/{}", app_config.root_partition).as_str()]),
-                        )?;
+            b'\t' if is_multiple => {
+                if let Some(matching_index) = matching_indexes.get(selected_row) {
+                    match marked_indexes.iter().position(|i| i == matching_index) {
+                        Some(position) => {
+                            marked_indexes.remove(position);
+                        }
+                        None => marked_indexes.push(*matching_index),
                     }
-                } else if app_config.encrypted_partitons {
-                    run_command(
-                        "cryptsetup",
-                        Some(&[
-                            "open",
-                            format!("/dev/{}", app_config.root_partition).as_str(),
-                            "cryptroot",
-                        ]),
-                    )?;
+                    selected_row = (selected_row + 1).min(matching_indexes.len() - 1);
                 }
-
-                if let Some(boot_partition) = &app_config.boot_partition {
-                    if question.bool_ask("Do you want to format your boot partition?") {
-                        run_command(
-                            "mkfs.btrfs",
-                            Some(&["-f", format!("/dev/{}", boot_partition).as_str()]),
-                        )?;
+            }
+            0x7F | 0x08 => {
+                filter.pop();
+            }
+            0x15 => filter.clear(),
+            0x1B => {
+                let Some(b'[' | b'O') = read_byte(&mut stdin)? else {
+                    continue;
+                };
+                let mut sequence = Vec::new();
+                while let Some(byte) = read_byte(&mut stdin)? {
+                    sequence.push(byte);
+                    if !byte.is_ascii_digit() && byte != b';' {
+                        break;
                     }
                 }
-
-                if let Some(uefi_partition) = &app_config.uefi_partition {
-                    if question.bool_ask("Do you want to format your uefi partition?") {
-                        run_command(
-                            "mkfs.fat",
-                            Some(&["-F32", format!("/dev/{}", uefi_partition).as_str()]),
-                        )?;
+                let last_row = matching_indexes.len().saturating_sub(1);
+                selected_row = match sequence.as_slice() {
+                    b"A" => selected_row.saturating_sub(1),
+                    b"B" => (selected_row + 1).min(last_row),
+                    b"5~" => selected_row.saturating_sub(PICKER_ROWS_COUNT),
+                    b"6~" => (selected_row + PICKER_ROWS_COUNT).min(last_row),
+                    _ => selected_row,
+                };
+            }
+            byte if byte >= 0x20 => {
+                let character_length = match byte {
+                    0xF0.. => 4,
+                    0xE0.. => 3,
+                    0xC0.. => 2,
+                    _ => 1,
+                };
+                let mut character_bytes = vec![byte];
+                for _ in 1..character_length {
+                    if let Some(byte) = read_byte(&mut stdin)? {
+                        character_bytes.push(byte);
                     }
                 }
+                filter.push_str(&String::from_utf8_lossy(&character_bytes));
+            }
+            _ => {}
+        }
 
-                if let Some(home_partition) = &app_config.home_partition {
-                    if question.bool_ask("Do you want to format your home partition?") {
-                        if app_config.encrypted_partitons {
-                            run_command(
-                                "cryptsetup",
-                                Some(&["luksFormat", format!("/dev/{}", home_partition).as_str()]),
-                            )?;
-                            run_command(
-                                "cryptsetup",
-                                Some(&[
-                                    "open",
-                                    format!("/dev/{}", home_partition).as_str(),
-                                    "crypthome",
-                                ]),
-                            )?;
-                            run_command("mkfs.btrfs", Some(&["-f", "/dev/mapper/crypthome"]))?;
-                        } else {
-                            run_command(
-                                "mkfs.btrfs",
-                                Some(&["-f", format!("/dev/{}", home_partition).as_str()]),
-                            )?;
-                        }
-                    } else if app_config.encrypted_partitons {
-                        run_command(
-                            "cryptsetup",
-                            Some(&[
-                                "open",
-                                format!("/dev/{}", home_partition).as_str(),
-                                "crypthome",
-                            ]),
-                        )?;
-                    }
-                }
+        if filter != previous_filter {
+            matching_indexes = filter_choices(choices, &filter);
+            selected_row = 0;
+        }
+    }
+}
 
-                print_operation_result(OperationResult::Done);
-            }
-            7 => {
-                app_config.print_installation_status_and_save_config("Enabling swap");
+// Moves the cursor back to the first drawn line and clears everything below it.
+fn clear_drawn_lines(drawn_lines_count: usize) {
+    if drawn_lines_count == 0 {
+        return;
+    }
+    if drawn_lines_count > 1 {
+        print!("\x1b[{}A", drawn_lines_count - 1);
+    }
+    print!("\r\x1b[J");
+    let _ = io::stdout().flush();
+}
 
-                if question.bool_ask("Do you want to enable swap?") {
-                    question.ask("Enter name of the swap partition: ");
-                    app_config.swap_partition = Some(question.answer.clone());
+// Draws the filter, the shown choices and the number of matches. Returns the number of drawn lines.
+fn draw_picker(
+    choices: &[&str],
+    filter: &str,
+    matching_indexes: &[usize],
+    marked_indexes: &[usize],
+    selected_row: usize,
+    first_shown_row: usize,
+    is_multiple: bool,
+) -> usize {
+    // Lines are cut to the terminal width, because wrapped lines would break clearing them.
+    let line_length = terminal_width().unwrap_or(MAX_LINE_LENGTH);
+    let shown_rows = first_shown_row
+        ..matching_indexes
+            .len()
+            .min(first_shown_row + PICKER_ROWS_COUNT);
+    let shown_rows_count = shown_rows.len();
+
+    println!(
+        "{}",
+        truncate_to_width(&format!("Filter: {filter}"), line_length)
+    );
+    for row in shown_rows {
+        let choice_index = matching_indexes[row];
+        let mark = match (is_multiple, marked_indexes.contains(&choice_index)) {
+            (false, _) => "",
+            (true, true) => "[x] ",
+            (true, false) => "[ ] ",
+        };
+        let choice_line = truncate_to_width(
+            &format!(
+                "{} {mark}{}",
+                if row == selected_row { ">" } else { " " },
+                choices[choice_index]
+            ),
+            line_length,
+        );
 
-                    run_command(
-                        "mkswap",
-                        Some(&[format!("/dev/{}", question.answer).as_str()]),
-                    )?;
-                    run_command(
-                        "swapon",
-                        Some(&[format!("/dev/{}", question.answer).as_str()]),
-                    )?;
-                }
+        if row == selected_row {
+            TextManager::set_graphics(TextGraphics::Inverse);
+            print!("{choice_line}");
+            TextManager::reset_color_and_graphics();
+            println!();
+        } else {
+            println!("{choice_line}");
+        }
+    }
+    let status_line = format!(
+        "({}/{} matches{})",
+        matching_indexes.len(),
+        choices.len(),
+        if is_multiple {
+            ", Tab: Mark, Enter: Done"
+        } else {
+            ""
+        }
+    );
+    print!("{}", truncate_to_width(&status_line, line_length));
+    let _ = io::stdout().flush();
 
-                print_operation_result(OperationResult::Done);
-            }
-            8 => {
-                app_config.print_installation_status_and_save_config("Mounting partitions");
+    shown_rows_count + 2
+}
 
-                if app_config.encrypted_partitons {
-                    run_command("mount", Some(&["/dev/mapper/cryptroot", "/mnt"]))?;
-                } else {
-                    run_command(
-                        "mount",
-                        Some(&[
-                            format!("/dev/{}", app_config.root_partition).as_str(),
-                            "/mnt",
-                        ]),
-                    )?;
-                }
+// Returns indexes of the choices matching the filter, the best matches first.
+fn filter_choices(choices: &[&str], filter: &str) -> Vec<usize> {
+    let mut scored_indexes = choices
+        .iter()
+        .enumerate()
+        .filter_map(|(index, choice)| fuzzy_match_score(choice, filter).map(|score| (index, score)))
+        .collect::<Vec<(usize, i32)>>();
+    // Sorting is stable, so equally good matches keep their order.
+    scored_indexes.sort_by_key(|(_, score)| -score);
+
+    scored_indexes.into_iter().map(|(index, _)| index).collect()
+}
 
-                if let Some(boot_partition) = &app_config.boot_partition {
-                    run_command("mkdir", Some(&["-p", "/mnt/boot"]))?;
-                    run_command(
-                        "mount",
-                        Some(&[format!("/dev/{}", boot_partition).as_str(), "/mnt/boot"]),
-                    )?;
-                }
+// Characters of the filter have to appear in the choice in the same order, but not next to each other.
+// Adjacent characters, characters at the start of words and whole substrings score higher. (Case insensitive)
+fn fuzzy_match_score(choice: &str, filter: &str) -> Option<i32> {
+    let choice = choice.to_lowercase();
+    let filter = filter.to_lowercase();
+    let choice_characters = choice.chars().collect::<Vec<char>>();
+    let mut score = 0;
+    let mut search_start = 0;
+    let mut previous_match_index: Option<usize> = None;
+
+    for filter_character in filter.chars().filter(|c| !c.is_whitespace()) {
+        let match_index = (search_start..choice_characters.len())
+            .find(|i| choice_characters[*i] == filter_character)?;
+
+        score += 1;
+        if previous_match_index.is_some_and(|i| i + 1 == match_index) {
+            score += 5;
+        }
+        if match_index == 0 || !choice_characters[match_index - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous_match_index = Some(match_index);
+        search_start = match_index + 1;
+    }
+    if !filter.is_empty() && choice.contains(filter.trim()) {
+        score += 10;
+    }
 
-                if let Some(uefi_partition) = &app_config.uefi_partition {
-                    run_command("mkdir", Some(&["-p", "/mnt/boot/EFI"]))?;
-                    run_command(
-                        "mount",
-                        Some(&[format!("/dev/{}", uefi_partition).as_str(), "/mnt/boot/EFI"]),
-                    )?;
-                }
+    Some(score)
+}
 
-                if let Some(home_partition) = &app_config.home_partition {
-                    run_command("mkdir", Some(&["-p", "/mnt/home"]))?;
-                    if app_config.encrypted_partitons {
-                        run_command("mount", Some(&["/dev/mapper/crypthome", "/mnt/home"]))?;
-                    } else {
-                        run_command(
-                            "mount",
-                            Some(&[format!("/dev/{}", home_partition).as_str(), "/mnt/home"]),
-                        )?;
-                    }
-                }
+fn truncate_to_width(text: &str, width: usize) -> String {
+    let mut truncated_text = String::new();
+    let mut truncated_width = 0;
+    for character in text.chars() {
+        truncated_width += character_width(character);
+        if truncated_width > width {
+            break;
+        }
+        truncated_text.push(character);
+    }
 
-                print_operation_result(OperationResult::Done);
+    truncated_text
+}
+
+// Completes file system paths. Words without a slash are completed to block device names (sda, nvme0n1p2, ...),
+// because partitions and disks are asked by their names.
+fn find_completions(word: &str) -> Vec<String> {
+    let (directory, prefix) = match word.rfind('/') {
+        Some(separator_index) => word.split_at(separator_index + 1),
+        None => ("", word),
+    };
+    let read_directory = if directory.is_empty() {
+        "/sys/class/block"
+    } else {
+        directory
+    };
+    let Ok(entries) = fs::read_dir(read_directory) else {
+        return Vec::new();
+    };
+
+    let mut completions = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
             }
-            9 => {
-                app_config.print_installation_status_and_save_config("Updating mirrors");
+            let is_directory = !directory.is_empty() && entry.path().is_dir();
+            Some(format!(
+                "{directory}{name}{}",
+                if is_directory { "/" } else { "" }
+            ))
+        })
+        .collect::<Vec<String>>();
+    completions.sort();
+
+    completions
+}
+
+fn find_common_prefix(texts: &[String]) -> String {
+    let Some(first_text) = texts.first() else {
+        return String::new();
+    };
+
+    first_text
+        .chars()
+        .enumerate()
+        .take_while(|(index, character)| {
+            texts
+                .iter()
+                .all(|t| t.chars().nth(*index) == Some(*character))
+        })
+        .map(|(_, character)| character)
+        .collect()
+}
+
+#[derive(Debug)]
+enum AppError {
+    InternalError(String),
+    ExternalError(String),
+}
+
+impl error::Error for AppError {}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InternalError(internal_error) => {
+                write!(f, "{}", internal_error)
+            }
+            Self::ExternalError(external_error) => {
+                write!(f, "{}", external_error)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for AppError {
+    fn from(value: io::Error) -> Self {
+        Self::InternalError(value.to_string())
+    }
+}
+
+struct AppConfig {
+    uefi_install: bool,
+    uefi_partition: Option<String>,
+    boot_partition: Option<String>,
+    root_partition: String,
+    // Other devices of a root btrfs file system which spans several devices.
+    root_devices: Vec<String>,
+    // btrfs profile of a root file system with several devices. (raid1, raid10 or single)
+    btrfs_profile: String,
+    home_partition: Option<String>,
+    // Existing home partition is mounted without formatting and its home directory is given to the new user.
+    reuse_home: bool,
+    username: String,
+    encrypted_partitons: bool,
+    // Uses the systemd based initramfs hooks (sd-encrypt) instead of the busybox based ones (encrypt).
+    systemd_initramfs: bool,
+    swap_partition: Option<String>,
+    // IDs of the finished steps. Saved instead of a step number, so adding steps doesn't break resuming.
+    completed_steps: Vec<String>,
+    current_installation_step: u8,
+    total_installation_steps: u8,
+    hostname: String,
+    use_doas: bool,
+    lock_root: bool,
+    zram_size: Option<String>,
+    zram_compression_algorithm: String,
+    hibernation: bool,
+    allow_discards: bool,
+    mirror_country: String,
+    // IDs of the steps marked as skipped in the step menu.
+    skipped_steps: Vec<String>,
+    // Finished parts of unfinished steps. ("<step ID>/<sub-step>")
+    completed_sub_steps: Vec<String>,
+    cpu_brand: String,
+    // Console font of the installed system. (Empty for the default font)
+    console_font: String,
+    // Disk image file used as the installation target instead of a real disk.
+    image_path: Option<String>,
+    // Loop device (e.g. "loop0") which the disk image is attached to.
+    loop_device: Option<String>,
+    // Directory which is installed into instead of partitions, to make a root file system for containers.
+    container_path: Option<String>,
+    // Only the root partition is formatted. Partitions, UEFI and home partitions are kept.
+    reinstall: bool,
+    // Other installed systems are added to the GRUB menu by os-prober.
+    probe_other_systems: bool,
+    // PARTUUIDs of the chosen partitions. ("<partition name>:<PARTUUID>")
+    partition_uuids: Vec<String>,
+    time_zone: String,
+    // Enabled lines of locale.gen. ("en_US.UTF-8 UTF-8")
+    locales: Vec<String>,
+}
+
+impl AppConfig {
+    fn new(total_installation_steps: u8) -> Self {
+        Self {
+            uefi_install: false,
+            uefi_partition: None,
+            boot_partition: None,
+            root_partition: String::new(),
+            root_devices: Vec::new(),
+            btrfs_profile: String::new(),
+            home_partition: None,
+            reuse_home: false,
+            username: String::new(),
+            encrypted_partitons: false,
+            systemd_initramfs: false,
+            swap_partition: None,
+            completed_steps: Vec::new(),
+            current_installation_step: 1,
+            total_installation_steps,
+            hostname: String::new(),
+            use_doas: false,
+            lock_root: false,
+            zram_size: None,
+            zram_compression_algorithm: String::new(),
+            hibernation: false,
+            allow_discards: false,
+            mirror_country: String::new(),
+            skipped_steps: Vec::new(),
+            completed_sub_steps: Vec::new(),
+            cpu_brand: String::new(),
+            console_font: String::new(),
+            image_path: None,
+            loop_device: None,
+            container_path: None,
+            reinstall: false,
+            probe_other_systems: false,
+            partition_uuids: Vec::new(),
+            time_zone: String::new(),
+            locales: Vec::new(),
+        }
+    }
+
+    fn print_installation_status_and_save_config(&mut self, text: &str) {
+        let text = translate(text);
+        let text = text.as_str();
+        if TextManager::is_accessible() {
+            println!(
+                "\n{}\n",
+                translate(&format!(
+                    "Step {} of {}: {text}.",
+                    self.current_installation_step, self.total_installation_steps
+                ))
+            );
+            self.save_config();
+            return;
+        }
+
+        let line_length = banner_line_length();
+        if TextManager::is_plain() || line_length < MIN_BANNER_LINE_LENGTH {
+            println!(
+                "\n[Step {}/{}] {text}\n",
+                self.current_installation_step, self.total_installation_steps
+            );
+            self.save_config();
+            return;
+        }
+
+        TextManager::set_color(TextColor::Cyan);
+        println!();
+        for text_line in wrap_text(text, line_length - 4) {
+            println!(
+                "{}",
+                center_text(&format!(" {text_line} "), '-', line_length)
+            );
+        }
+        println!("|{}|", " ".repeat(line_length - 2));
+
+        let percentage = format!(
+            "{}/{} | {}",
+            self.current_installation_step,
+            self.total_installation_steps,
+            ((self.current_installation_step as f32 / self.total_installation_steps as f32) * 100.0)
+                as u8
+        );
+        println!(
+            "{}\n",
+            center_text(&format!("> [{percentage}%] <"), '-', line_length)
+        );
+        TextManager::reset_color_and_graphics();
+
+        self.save_config();
+    }
+
+    fn environment_variables(&self) -> Vec<(&str, String)> {
+        vec![
+            ("ALI_UEFI_INSTALL", self.uefi_install.to_string()),
+            (
+                "ALI_UEFI_PARTITION",
+                self.uefi_partition.clone().unwrap_or_default(),
+            ),
+            (
+                "ALI_BOOT_PARTITION",
+                self.boot_partition.clone().unwrap_or_default(),
+            ),
+            ("ALI_ROOT_PARTITION", self.root_partition.clone()),
+            (
+                "ALI_HOME_PARTITION",
+                self.home_partition.clone().unwrap_or_default(),
+            ),
+            ("ALI_USERNAME", self.username.clone()),
+            (
+                "ALI_ENCRYPTED_PARTITIONS",
+                self.encrypted_partitons.to_string(),
+            ),
+            ("ALI_SYSTEMD_INITRAMFS", self.systemd_initramfs.to_string()),
+            (
+                "ALI_SWAP_PARTITION",
+                self.swap_partition.clone().unwrap_or_default(),
+            ),
+            ("ALI_HOSTNAME", self.hostname.clone()),
+            ("ALI_USE_DOAS", self.use_doas.to_string()),
+            ("ALI_LOCK_ROOT", self.lock_root.to_string()),
+            ("ALI_ZRAM_SIZE", self.zram_size.clone().unwrap_or_default()),
+            (
+                "ALI_ZRAM_COMPRESSION_ALGORITHM",
+                self.zram_compression_algorithm.clone(),
+            ),
+            ("ALI_HIBERNATION", self.hibernation.to_string()),
+            ("ALI_ALLOW_DISCARDS", self.allow_discards.to_string()),
+            ("ALI_MIRROR_COUNTRY", self.mirror_country.clone()),
+            ("ALI_CPU_BRAND", self.cpu_brand.clone()),
+        ]
+    }
+
+    fn save_config(&mut self) {
+        let config_entries = [
+            ("uefi_install", Some(self.uefi_install.to_string())),
+            ("uefi_partition", self.uefi_partition.clone()),
+            ("boot_partition", self.boot_partition.clone()),
+            ("root_partition", Some(self.root_partition.clone())),
+            ("root_devices", Some(self.root_devices.join(","))),
+            ("btrfs_profile", Some(self.btrfs_profile.clone())),
+            ("home_partition", self.home_partition.clone()),
+            ("reuse_home", Some(self.reuse_home.to_string())),
+            ("username", Some(self.username.clone())),
+            (
+                "encrypted_partitions",
+                Some(self.encrypted_partitons.to_string()),
+            ),
+            (
+                "systemd_initramfs",
+                Some(self.systemd_initramfs.to_string()),
+            ),
+            ("swap_partition", self.swap_partition.clone()),
+            ("completed_steps", Some(self.completed_steps.join(","))),
+            (
+                "total_installation_steps",
+                Some(self.total_installation_steps.to_string()),
+            ),
+            ("hostname", Some(self.hostname.clone())),
+            ("use_doas", Some(self.use_doas.to_string())),
+            ("lock_root", Some(self.lock_root.to_string())),
+            ("zram_size", self.zram_size.clone()),
+            (
+                "zram_compression_algorithm",
+                Some(self.zram_compression_algorithm.clone()),
+            ),
+            ("hibernation", Some(self.hibernation.to_string())),
+            ("allow_discards", Some(self.allow_discards.to_string())),
+            ("mirror_country", Some(self.mirror_country.clone())),
+            ("skipped_steps", Some(self.skipped_steps.join(","))),
+            (
+                "completed_sub_steps",
+                Some(self.completed_sub_steps.join(",")),
+            ),
+            ("cpu_brand", Some(self.cpu_brand.clone())),
+            ("console_font", Some(self.console_font.clone())),
+            ("image_path", self.image_path.clone()),
+            ("loop_device", self.loop_device.clone()),
+            ("container_path", self.container_path.clone()),
+            ("reinstall", Some(self.reinstall.to_string())),
+            (
+                "probe_other_systems",
+                Some(self.probe_other_systems.to_string()),
+            ),
+            ("partition_uuids", Some(self.partition_uuids.join(","))),
+            ("time_zone", Some(self.time_zone.clone())),
+            ("locales", Some(self.locales.join(","))),
+        ];
+
+        // Fields which are None are left out. The "end" line shows that the file wasn't truncated.
+        let mut app_config_string = format!("version={CONFIG_VERSION}\n");
+        for (key, value) in config_entries {
+            if let Some(value) = value {
+                app_config_string.push_str(&format!("{key}={value}\n"));
+            }
+        }
+        app_config_string.push_str("end\n");
+
+        if Verbosity::current() == Verbosity::Debug {
+            print_config_changes(&app_config_string);
+        }
+
+        // Written to a temporary file first, so an interruption can't leave a half written config behind.
+        let temporary_config_file_path = format!("{CONFIG_FILE_PATH}.tmp");
+        fs::write(&temporary_config_file_path, app_config_string)
+            .unwrap_or_else(|_| panic!("Error writing to {temporary_config_file_path}"));
+        fs::rename(&temporary_config_file_path, CONFIG_FILE_PATH)
+            .unwrap_or_else(|_| panic!("Error writing to {CONFIG_FILE_PATH}"));
+    }
+
+    // Reads a "key=value" config file. Older versions are migrated and newer versions are refused.
+    fn load_config(&mut self) -> Result<(), AppError> {
+        self.load_config_file(CONFIG_FILE_PATH)
+    }
+
+    fn load_config_file(&mut self, config_file_path: &str) -> Result<(), AppError> {
+        let app_config_string = fs::read_to_string(config_file_path).map_err(|error| {
+            AppError::InternalError(format!("Error! {config_file_path} can't be read: {error}"))
+        })?;
+
+        if app_config_string.trim().is_empty() {
+            return Err(AppError::InternalError(format!(
+                "Error! {config_file_path} is empty."
+            )));
+        }
+
+        let Some(version) = app_config_string
+            .lines()
+            .next()
+            .and_then(|l| l.strip_prefix("version="))
+        else {
+            let app_config_elements = app_config_string.lines().collect::<Vec<_>>();
+            self.load_legacy_config(&app_config_elements)?;
+            println!("{config_file_path} was migrated from version 1 to version {CONFIG_VERSION}.");
+            self.save_config();
+            return Ok(());
+        };
+        match version.parse::<u32>() {
+            Ok(CONFIG_VERSION) => {}
+            Ok(version) if version > CONFIG_VERSION => {
+                return Err(AppError::InternalError(format!(
+                    "Error! {config_file_path} was created by a newer version of the installer (config version {version}). Use the same version of the installer to continue."
+                )));
+            }
+            _ => {
+                return Err(AppError::InternalError(format!(
+                    "Error! {config_file_path} has an unsupported config version: {version}"
+                )));
+            }
+        }
+
+        let mut config_values = HashMap::new();
+        let mut end_found = false;
+        for (line_number, line) in app_config_string.lines().enumerate().skip(1) {
+            if end_found {
+                return Err(AppError::InternalError(format!(
+                    "Error! Line {} of {config_file_path} is after the end line.",
+                    line_number + 1
+                )));
+            }
+            if line == "end" {
+                end_found = true;
+            } else if let Some((key, value)) = line.split_once('=') {
+                config_values.insert(key, value);
+            } else {
+                return Err(AppError::InternalError(format!(
+                    "Error! Line {} of {config_file_path} is invalid: {line}",
+                    line_number + 1
+                )));
+            }
+        }
+        if !end_found {
+            return Err(AppError::InternalError(format!(
+                "Error! {config_file_path} is truncated. (The end line is missing)"
+            )));
+        }
+        let text = |key: &str| config_values.get(key).map(|v| v.to_string());
+        let boolean = |key: &str| match config_values.get(key) {
+            Some(&"true") => Ok(true),
+            Some(&"false") | None => Ok(false),
+            Some(value) => Err(AppError::InternalError(format!(
+                "Error! Invalid value for {key} in {config_file_path}: {value}"
+            ))),
+        };
+        let list = |key: &str| {
+            config_values
+                .get(key)
+                .map(|v| {
+                    v.split(',')
+                        .filter(|s| !s.is_empty())
+                        .map(|s| s.to_string())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        self.uefi_install = boolean("uefi_install")?;
+        self.uefi_partition = text("uefi_partition");
+        self.boot_partition = text("boot_partition");
+        self.root_partition = text("root_partition").unwrap_or_default();
+        self.root_devices = list("root_devices");
+        self.btrfs_profile = text("btrfs_profile").unwrap_or_default();
+        self.home_partition = text("home_partition");
+        self.reuse_home = boolean("reuse_home")?;
+        self.username = text("username").unwrap_or_default();
+        self.encrypted_partitons = boolean("encrypted_partitions")?;
+        self.systemd_initramfs = boolean("systemd_initramfs")?;
+        self.swap_partition = text("swap_partition");
+        self.completed_steps = list("completed_steps");
+        if let Some(total_installation_steps) = config_values.get("total_installation_steps") {
+            self.total_installation_steps = total_installation_steps.parse().map_err(|_| {
+                AppError::InternalError(format!(
+                    "Error! Invalid value for total_installation_steps in {config_file_path}: {total_installation_steps}"
+                ))
+            })?;
+        }
+        self.hostname = text("hostname").unwrap_or_default();
+        self.use_doas = boolean("use_doas")?;
+        self.lock_root = boolean("lock_root")?;
+        self.zram_size = text("zram_size");
+        self.zram_compression_algorithm = text("zram_compression_algorithm").unwrap_or_default();
+        self.hibernation = boolean("hibernation")?;
+        self.allow_discards = boolean("allow_discards")?;
+        self.mirror_country = text("mirror_country").unwrap_or_default();
+        self.skipped_steps = list("skipped_steps");
+        self.completed_sub_steps = list("completed_sub_steps");
+        self.cpu_brand = text("cpu_brand").unwrap_or_default();
+        self.console_font = text("console_font").unwrap_or_default();
+        self.image_path = text("image_path");
+        self.loop_device = text("loop_device");
+        self.container_path = text("container_path");
+        self.reinstall = boolean("reinstall")?;
+        self.probe_other_systems = boolean("probe_other_systems")?;
+        self.partition_uuids = list("partition_uuids");
+        self.time_zone = text("time_zone").unwrap_or_default();
+        self.locales = list("locales");
+
+        Ok(())
+    }
+
+    // Version 1 config files had one value per line at fixed positions, ending with the number of the step which
+    // was running and the total steps count. Steps were numbered in the order below, so the steps before the running
+    // one are the completed ones.
+    fn load_legacy_config(&mut self, app_config_elements: &[&str]) -> Result<(), AppError> {
+        const LEGACY_STEP_IDS: &[&str] = &[
+            "installation_mode",
+            "encryption",
+            "timedatectl",
+            "partitioning",
+            "partition_names",
+            "format_partitions",
+            "swap",
+            "mount_partitions",
+            "mirrors",
+            "pacman",
+            "pacstrap",
+            "fstab",
+            "zram_and_swap_encryption",
+            "target_pacman",
+            "time_zone",
+            "hardware_clock",
+            "locale",
+            "hostname",
+            "hosts",
+            "root_password",
+            "create_user",
+            "user_password",
+            "user_groups",
+            "privilege_escalation",
+            "grub_install",
+            "grub_config",
+            "mkinitcpio",
+            "grub_mkconfig",
+            "crypttab",
+            "network_manager",
+            "kde",
+            "sddm",
+            "paru",
+            "unmount_partitions",
+        ];
+
+        let invalid_format_error = || {
+            AppError::InternalError(format!(
+                "Error! {CONFIG_FILE_PATH} has an old format which can't be migrated. Remove it to start a new installation."
+            ))
+        };
+        if app_config_elements.len() != 10 {
+            return Err(invalid_format_error());
+        }
+        let current_installation_step = app_config_elements[8]
+            .parse::<usize>()
+            .ok()
+            .filter(|s| (1..=LEGACY_STEP_IDS.len()).contains(s))
+            .ok_or_else(invalid_format_error)?;
+        app_config_elements[9]
+            .parse::<u8>()
+            .map_err(|_| invalid_format_error())?;
+
+        self.uefi_install = app_config_elements[0] == "true";
+        self.uefi_partition = if app_config_elements[1] == "None" {
+            None
+        } else {
+            Some(Self::extract_some_value(app_config_elements[1]))
+        };
+        self.boot_partition = if app_config_elements[2] == "None" {
+            None
+        } else {
+            Some(Self::extract_some_value(app_config_elements[2]))
+        };
+        self.root_partition = app_config_elements[3].to_string();
+        self.home_partition = if app_config_elements[4] == "None" {
+            None
+        } else {
+            Some(Self::extract_some_value(app_config_elements[4]))
+        };
+        self.username = app_config_elements[5].to_string();
+        self.encrypted_partitons = app_config_elements[6] == "true";
+        self.swap_partition = if app_config_elements[7] == "None" {
+            None
+        } else {
+            Some(Self::extract_some_value(app_config_elements[7]))
+        };
+        self.completed_steps = LEGACY_STEP_IDS[..current_installation_step - 1]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        Ok(())
+    }
+
+    fn remove_config(&self) {
+        fs::remove_file(CONFIG_FILE_PATH)
+            .unwrap_or_else(|_| panic!("Error removing {CONFIG_FILE_PATH}"))
+    }
+
+    fn extract_some_value(some: &str) -> String {
+        some.split("\"").collect::<Vec<_>>()[1].to_string()
+    }
+
+    fn reset(&mut self) {
+        self.uefi_install = false;
+        self.uefi_partition = None;
+        self.boot_partition = None;
+        self.root_partition = String::new();
+        self.root_devices.clear();
+        self.btrfs_profile = String::new();
+        self.home_partition = None;
+        self.reuse_home = false;
+        self.username = String::new();
+        self.encrypted_partitons = false;
+        self.systemd_initramfs = false;
+        self.swap_partition = None;
+        self.completed_steps.clear();
+        self.current_installation_step = 1;
+        self.hostname = String::new();
+        self.use_doas = false;
+        self.lock_root = false;
+        self.zram_size = None;
+        self.zram_compression_algorithm = String::new();
+        self.hibernation = false;
+        self.allow_discards = false;
+        self.mirror_country = String::new();
+        self.skipped_steps.clear();
+        self.completed_sub_steps.clear();
+        self.cpu_brand = String::new();
+        self.console_font = String::new();
+        self.image_path = None;
+        self.loop_device = None;
+        self.container_path = None;
+        self.reinstall = false;
+        self.probe_other_systems = false;
+        self.partition_uuids.clear();
+        self.time_zone = String::new();
+        self.locales.clear();
+    }
+
+    fn is_sub_step_completed(&self, sub_step_id: &str) -> bool {
+        let sub_step_completed = self.completed_sub_steps.iter().any(|s| s == sub_step_id);
+        if sub_step_completed {
+            println!("{sub_step_id}: Already done, skipping.");
+        }
+
+        sub_step_completed
+    }
+
+    fn complete_sub_step(&mut self, sub_step_id: &str) {
+        self.completed_sub_steps.push(sub_step_id.to_string());
+        self.save_config();
+    }
+
+    // Saves the PARTUUIDs of the chosen partitions which don't have one saved yet. Saved ones aren't looked up
+    // again, because the kernel names may point to other partitions after a reboot.
+    fn save_partition_uuids(&mut self) {
+        let partitions = [
+            Some(&self.root_partition),
+            self.boot_partition.as_ref(),
+            self.uefi_partition.as_ref(),
+            self.home_partition.as_ref(),
+            self.swap_partition.as_ref(),
+        ];
+        let new_partition_uuids = partitions
+            .into_iter()
+            .flatten()
+            .chain(self.root_devices.iter())
+            .filter(|p| self.saved_partition_uuid(p).is_none())
+            .filter_map(|p| {
+                query_block_device(&format!("/dev/{p}"))
+                    .partuuid
+                    .map(|partuuid| format!("{p}:{partuuid}"))
+            })
+            .collect::<Vec<String>>();
+
+        self.partition_uuids.extend(new_partition_uuids);
+        self.save_config();
+    }
+
+    fn saved_partition_uuid(&self, partition: &str) -> Option<&str> {
+        self.partition_uuids
+            .iter()
+            .find_map(|e| e.strip_prefix(partition)?.strip_prefix(':'))
+    }
+
+    // Finds the current device of a partition by its saved PARTUUID. Partitions without one (e.g. a disk without
+    // a partition table) are used by their kernel name.
+    fn partition_path(&self, partition: &str) -> String {
+        self.saved_partition_uuid(partition)
+            .and_then(|partuuid| fs::canonicalize(format!("/dev/disk/by-partuuid/{partuuid}")).ok())
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|| format!("/dev/{partition}"))
+    }
+
+    // Swap of an encrypted installation normally gets a random key on every boot. With hibernation it's a LUKS
+    // partition instead, which is unlocked in the initramfs before resuming, so the hibernation image can be read back.
+    fn has_persistent_swap_encryption(&self) -> bool {
+        self.encrypted_partitons && self.hibernation && self.swap_partition.is_some()
+    }
+
+    // Device which is used as swap in the live environment.
+    fn swap_path(&self) -> Option<String> {
+        let swap_partition = self.swap_partition.as_ref()?;
+        Some(if self.has_persistent_swap_encryption() {
+            String::from("/dev/mapper/swap")
+        } else {
+            self.partition_path(swap_partition)
+        })
+    }
+}
+
+// Prints and logs the lines of the new config which differ from the saved config.
+fn print_config_changes(new_app_config_string: &str) {
+    let old_app_config_string = fs::read_to_string(CONFIG_FILE_PATH).unwrap_or_default();
+    let old_values = old_app_config_string
+        .lines()
+        .filter_map(|l| l.split_once('='))
+        .collect::<HashMap<&str, &str>>();
+
+    let new_values = new_app_config_string
+        .lines()
+        .filter_map(|l| l.split_once('='))
+        .collect::<HashMap<&str, &str>>();
+
+    // Fields which are None aren't saved, so a removed key means the value was cleared.
+    let mut changed_keys = old_values
+        .keys()
+        .chain(new_values.keys())
+        .filter(|k| old_values.get(*k) != new_values.get(*k))
+        .copied()
+        .collect::<Vec<&str>>();
+    changed_keys.sort();
+    changed_keys.dedup();
+
+    for key in changed_keys {
+        let change = format!(
+            "Installer state: {key}: '{}' -> '{}'",
+            old_values.get(key).copied().unwrap_or_default(),
+            new_values.get(key).copied().unwrap_or_default()
+        );
+        TextManager::set_graphics(TextGraphics::Dim);
+        println!("{change}");
+        TextManager::reset_color_and_graphics();
+        append_to_log(&change);
+    }
+}
+
+// Colors encoded in ANSI escape code
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+enum TextColor {
+    Reset,
+    Black = 30,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    Default = 39,
+}
+
+impl fmt::Display for TextColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", *self as u8)
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+enum TextGraphics {
+    Bold = 1,
+    Dim,
+    Italic,
+    Underline,
+    Blinking,
+    Inverse = 7,
+    Hidden,
+    Strikethrough,
+}
+
+impl fmt::Display for TextGraphics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", *self as u8)
+    }
+}
+
+struct TextManager;
+
+impl TextManager {
+    fn is_plain() -> bool {
+        PLAIN_OUTPUT.load(Ordering::Relaxed)
+    }
+
+    fn is_accessible() -> bool {
+        ACCESSIBLE_OUTPUT.load(Ordering::Relaxed)
+    }
+
+    fn is_colorless() -> bool {
+        Self::is_plain() || COLORLESS_OUTPUT.load(Ordering::Relaxed)
+    }
+
+    fn set_color(color: TextColor) {
+        if !Self::is_colorless() {
+            print!("\x1b[{color}m");
+        }
+    }
+
+    fn set_graphics(graphics: TextGraphics) {
+        if !Self::is_colorless() {
+            print!("\x1b[{graphics}m");
+        }
+    }
+
+    fn reset_color_and_graphics() {
+        if !Self::is_colorless() {
+            print!("\x1b[{}m", TextColor::Reset);
+        }
+    }
+}
+
+// How much is printed besides the questions. The log gets everything regardless.
+// Quiet: Step banners and errors. Verbose: Every command line too. Debug: Every change of the saved state too.
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+    Debug,
+}
+
+impl Verbosity {
+    fn current() -> Self {
+        match VERBOSITY.load(Ordering::Relaxed) {
+            0 => Self::Quiet,
+            2 => Self::Verbose,
+            3 => Self::Debug,
+            _ => Self::Normal,
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum HookTime {
+    Before,
+    After,
+}
+
+struct StepHook {
+    hook_time: HookTime,
+    step_name: String,
+    command: String,
+}
+
+struct CustomStep {
+    name: String,
+    title: String,
+    position: Option<(HookTime, String)>,
+    chroot_context: bool,
+    // (Environment variable, Question)
+    prompts: Vec<(String, String)>,
+    commands: Vec<String>,
+}
+
+struct Customization {
+    step_hooks: Vec<StepHook>,
+    custom_steps: Vec<CustomStep>,
+    notification_url: Option<String>,
+    command_timeout: Option<time::Duration>,
+    // Extra luksFormat arguments built from the luks_* keys.
+    luks_format_arguments: Vec<String>,
+    // Directory of the live system whose files are added to /etc/skel of the installed system.
+    skel_directory: Option<String>,
+    // Extra lines of /etc/hosts. ("<address> <names>")
+    extra_hosts: Vec<String>,
+}
+
+// Subcommand and step selection given on the command line.
+struct CommandLineArguments {
+    cleanup: bool,
+    no_cleanup: bool,
+    plain: bool,
+    no_color: bool,
+    from_step: Option<String>,
+    only_steps: Vec<String>,
+    skip_steps: Vec<String>,
+    image_path: Option<String>,
+    image_size: Option<String>,
+    container_path: Option<String>,
+    reinstall: bool,
+    verbosity: Verbosity,
+    language: Option<String>,
+    accessible: bool,
+    large_font: bool,
+    validate: bool,
+    plan: bool,
+    // Plan is printed as JSON instead of text.
+    json: bool,
+    // Config file read by the validate and plan subcommands instead of the saved installation.
+    config_path: Option<String>,
+    // Answers file whose answers are used instead of asking.
+    answers_path: Option<String>,
+    // Hands-off mode. (--auto or ali.auto on the kernel command line)
+    auto: bool,
+    clone: bool,
+    // Root directory of the system inspected by the clone subcommand. ("/" if not given)
+    cloned_root_path: Option<String>,
+}
+
+impl CommandLineArguments {
+    fn has_step_selection(&self) -> bool {
+        self.from_step.is_some() || !self.only_steps.is_empty()
+    }
+}
+
+enum StepMenuAction {
+    Continue,
+    JumpTo(usize),
+    Abort,
+}
+
+enum StepEntry {
+    BuiltIn(&'static str),
+    Custom(usize),
+}
+
+struct FstabMountOptions {
+    noatime: bool,
+    btrfs_compression: bool,
+    btrfs_async_discard: bool,
+    commit_interval: Option<u32>,
+}
+
+// mkinitcpio.conf is a bash file. Only its arrays (MODULES, BINARIES, FILES, HOOKS) are edited, other lines are kept as they are.
+struct MkinitcpioConfig {
+    lines: Vec<String>,
+    is_changed: bool,
+}
+
+impl MkinitcpioConfig {
+    fn parse(content: &str) -> Self {
+        Self {
+            lines: content.lines().map(String::from).collect(),
+            is_changed: false,
+        }
+    }
+
+    // Returns the first and last line of the array, which may span several lines.
+    fn find_array_lines(&self, name: &str) -> Option<(usize, usize)> {
+        let prefix = format!("{name}=(");
+        let first_line = self
+            .lines
+            .iter()
+            .position(|l| l.trim_start().starts_with(&prefix))?;
+        let last_line = (first_line..self.lines.len())
+            .find(|i| self.lines[*i].split('#').next().unwrap_or("").contains(')'))
+            .unwrap_or(first_line);
+
+        Some((first_line, last_line))
+    }
+
+    fn array(&self, name: &str) -> Vec<String> {
+        let Some((first_line, last_line)) = self.find_array_lines(name) else {
+            return Vec::new();
+        };
+
+        self.lines[first_line..=last_line]
+            .iter()
+            .map(|l| l.split('#').next().unwrap_or(""))
+            .collect::<Vec<&str>>()
+            .join(" ")
+            .split_once('(')
+            .and_then(|(_, values)| values.split(')').next())
+            .unwrap_or("")
+            .split_whitespace()
+            .map(|v| v.trim_matches(['"', '\'']).to_string())
+            .collect()
+    }
+
+    fn set_array(&mut self, name: &str, values: &[String]) {
+        if self.array(name) == values {
+            return;
+        }
+
+        let array_line = format!("{name}=({})", values.join(" "));
+        match self.find_array_lines(name) {
+            Some((first_line, last_line)) => {
+                self.lines.splice(first_line..=last_line, [array_line]);
+            }
+            None => self.lines.push(array_line),
+        }
+        self.is_changed = true;
+    }
+}
+
+impl fmt::Display for MkinitcpioConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+// pacman.conf is an INI style file. Options are set whether they are commented out, already set or missing,
+// and comments and the order of other lines are kept.
+struct PacmanConfig {
+    lines: Vec<String>,
+}
+
+impl PacmanConfig {
+    fn parse(content: &str) -> Self {
+        Self {
+            lines: content.lines().map(String::from).collect(),
+        }
+    }
+
+    // Returns the index of the section header and the index after the last line of the section.
+    fn find_section(&self, section: &str) -> Option<(usize, usize)> {
+        let header = format!("[{section}]");
+        let header_line = self.lines.iter().position(|l| l.trim() == header)?;
+        let end_line = (header_line + 1..self.lines.len())
+            .find(|i| self.lines[*i].trim_start().starts_with('['))
+            .unwrap_or(self.lines.len());
+
+        Some((header_line, end_line))
+    }
+
+    fn option_key(line: &str) -> &str {
+        line.split('=').next().unwrap_or("").trim()
+    }
+
+    // Sets "<key> = <value>" (or only "<key>" for flags) in the section.
+    fn set_option(&mut self, section: &str, key: &str, value: Option<&str>) {
+        let option_line = match value {
+            Some(value) => format!("{key} = {value}"),
+            None => key.to_string(),
+        };
+        let Some((header_line, end_line)) = self.find_section(section) else {
+            self.lines.push(format!("[{section}]"));
+            self.lines.push(option_line);
+            return;
+        };
+
+        let section_lines = header_line + 1..end_line;
+        let set_line = section_lines
+            .clone()
+            .find(|i| Self::option_key(&self.lines[*i]) == key);
+        let commented_line = section_lines.clone().find(|i| {
+            self.lines[*i]
+                .trim_start()
+                .strip_prefix('#')
+                .is_some_and(|l| Self::option_key(l) == key)
+        });
+
+        match set_line.or(commented_line) {
+            Some(line) => self.lines[line] = option_line,
+            None => {
+                // Added after the last uncommented option of the section. Comments after it may belong to the
+                // next section, like the commented out repositories after [options] in the default pacman.conf.
+                let insert_line = section_lines
+                    .rev()
+                    .find(|i| {
+                        let line = self.lines[*i].trim();
+                        !line.is_empty() && !line.starts_with('#')
+                    })
+                    .map(|i| i + 1)
+                    .unwrap_or(header_line + 1);
+                self.lines.insert(insert_line, option_line);
+            }
+        }
+    }
+
+    // Enables a repository. A commented out section (like the default "#[multilib]") is uncommented.
+    fn add_repository(&mut self, name: &str, options: &[(&str, &str)]) {
+        let commented_header = format!("#[{name}]");
+        if let Some(header_line) = self
+            .lines
+            .iter()
+            .position(|l| l.trim().replace("# ", "#") == commented_header)
+        {
+            self.lines[header_line] = format!("[{name}]");
+            let mut line = header_line + 1;
+            while line < self.lines.len() && self.lines[line].trim_start().starts_with('#') {
+                let uncommented_line = self.lines[line].trim_start().trim_start_matches('#').trim();
+                if uncommented_line.starts_with('[') {
+                    break;
+                }
+                self.lines[line] = uncommented_line.to_string();
+                line += 1;
+            }
+        }
+
+        if self.find_section(name).is_none() {
+            if self.lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                self.lines.push(String::new());
+            }
+            self.lines.push(format!("[{name}]"));
+        }
+        for (key, value) in options {
+            self.set_option(name, key, Some(value));
+        }
+    }
+}
+
+impl fmt::Display for PacmanConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+// How many times a command is tried and how long to wait before the first retry. The delay doubles after every retry.
+#[derive(Clone, Copy)]
+struct RetryPolicy {
+    attempts: u32,
+    delay: time::Duration,
+}
+
+// Identifiers and type of a block device reported by blkid.
+#[derive(Default)]
+struct BlockDeviceInfo {
+    uuid: Option<String>,
+    partuuid: Option<String>,
+    file_system_type: Option<String>,
+    label: Option<String>,
+}
+
+// Partition of a disk read from /sys/block. Start and size are in 512 byte sectors.
+struct DiskPartition {
+    name: String,
+    start: u64,
+    size: u64,
+}
+
+enum PasswordStrength {
+    Weak,
+    Medium,
+    Strong,
+}
+
+impl fmt::Display for PasswordStrength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Weak => write!(f, "Weak"),
+            Self::Medium => write!(f, "Medium"),
+            Self::Strong => write!(f, "Strong"),
+        }
+    }
+}
+
+enum OperationResult {
+    Done,
+    Error,
+}
+
+fn main() -> Result<(), AppError> {
+    install_panic_hook();
+
+    let Some(command_line_arguments) = parse_command_line_arguments()? else {
+        print_usage();
+        return Ok(());
+    };
+
+    // Banners and escape codes only make sense on a terminal, so output piped to a file is plain.
+    PLAIN_OUTPUT.store(
+        command_line_arguments.plain
+            || command_line_arguments.accessible
+            || !io::stdout().is_terminal(),
+        Ordering::Relaxed,
+    );
+    ACCESSIBLE_OUTPUT.store(command_line_arguments.accessible, Ordering::Relaxed);
+    // https://no-color.org: NO_COLOR disables colors if it is set and not empty.
+    COLORLESS_OUTPUT.store(
+        command_line_arguments.no_color || env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()),
+        Ordering::Relaxed,
+    );
+    VERBOSITY.store(command_line_arguments.verbosity as u8, Ordering::Relaxed);
+    select_language(command_line_arguments.language.as_deref())?;
+
+    if command_line_arguments.accessible {
+        start_screen_reader();
+    }
+
+    if command_line_arguments.cleanup {
+        check_root_privileges()?;
+        return cleanup_installation();
+    }
+    if command_line_arguments.validate {
+        return validate_config(
+            command_line_arguments
+                .config_path
+                .as_deref()
+                .unwrap_or(CONFIG_FILE_PATH),
+        );
+    }
+    if command_line_arguments.plan {
+        return print_plan(&command_line_arguments);
+    }
+    if command_line_arguments.clone {
+        return clone_machine(
+            command_line_arguments
+                .cloned_root_path
+                .as_deref()
+                .unwrap_or("/"),
+        );
+    }
+
+    // Checked before the config and the log are created, so a failed run as a normal user leaves nothing behind.
+    check_root_privileges()?;
+    watch_signals(command_line_arguments.no_cleanup);
+
+    let no_cleanup = command_line_arguments.no_cleanup;
+    let installation_result = run_installation(command_line_arguments);
+    if let Err(error) = &installation_result {
+        append_to_log(&format!("Installation failed: {error}"));
+        send_notification(&format!("Installation failed: {error}"));
+    }
+    if installation_result.is_err() && !no_cleanup {
+        TextManager::set_color(TextColor::Yellow);
+        formatted_print("Cleaning up", PrintFormat::DoubleDashedLine);
+        TextManager::reset_color_and_graphics();
+        if let Err(error) = cleanup_installation() {
+            println!("Cleanup failed: {error}\n");
+        }
+    }
+
+    installation_result
+}
+
+fn run_installation(mut command_line_arguments: CommandLineArguments) -> Result<(), AppError> {
+    // Callback URL of the kernel command line comes first, so a provisioning server hears from every machine.
+    if let Some(callback_url) = find_kernel_parameter("ali.callback").filter(|u| !u.is_empty()) {
+        let _ = NOTIFICATION_URL.set(callback_url);
+    }
+
+    // Initializing question struct to use it in various parts of the program.
+    let mut question = Question::new();
+    let answers_location = command_line_arguments
+        .answers_path
+        .clone()
+        .or_else(|| find_kernel_parameter("ali.answers").filter(|l| !l.is_empty()));
+    if let Some(answers_location) = answers_location {
+        let skipped_steps = question.load_preset_answers(&answers_location)?;
+        command_line_arguments.skip_steps.extend(skipped_steps);
+    } else if command_line_arguments.auto {
+        return Err(AppError::InternalError(String::from(
+            "Error! Hands-off mode needs an answers file. (ali.answers=<path or URL> or --answers <path>)",
+        )));
+    }
+    // Nobody is there to answer, so the start and the end of the installation are answered unless the answers file
+    // does it, and a missing answer fails the installation instead of waiting forever.
+    if command_line_arguments.auto {
+        question.preset_answers.extend([
+            (String::from("Do you want to continue?"), String::from("y")),
+            (
+                String::from("What do you want to do now?"),
+                String::from("choice:Reboot"),
+            ),
+        ]);
+        question.is_unattended = true;
+    }
+
+    // Default console font is tiny on large screens, so a large one is offered before anything else is printed.
+    let use_large_font = command_line_arguments.large_font
+        || (!question.is_unattended
+            && has_high_resolution_screen()
+            && question.bool_ask(
+                "The screen has a high resolution. Do you want to use a large console font?",
+            ));
+    if use_large_font && run_command("setfont", Some(&[LARGE_CONSOLE_FONT])).is_err() {
+        println!("Console font {LARGE_CONSOLE_FONT} couldn't be loaded.\n");
+    }
+
+    print_welcome_message();
+
+    if !question.bool_ask("Do you want to continue?") {
+        return Ok(());
+    }
+
+    let customization = load_customization()?;
+    if let Some(notification_url) = &customization.notification_url {
+        let _ = NOTIFICATION_URL.set(notification_url.clone());
+    }
+    if let Some(command_timeout) = customization.command_timeout {
+        let _ = COMMAND_TIMEOUT.set(command_timeout);
+    }
+    let step_order = create_step_order(&customization.custom_steps)?;
+    let mut from_step_index =
+        find_from_step_index(&command_line_arguments, &step_order, &customization)?;
+
+    // Initializing app_config struct to use it in various parts of the program.
+    let mut app_config = AppConfig::new(step_order.len() as u8);
+
+    let mut step_menu_requested = false;
+    // A config file which can't be loaded is reported instead of being overwritten by a new installation.
+    let mut config_loaded = false;
+    while fs::metadata(CONFIG_FILE_PATH).is_ok() {
+        let Err(error) = app_config.load_config() else {
+            config_loaded = true;
+            break;
+        };
+
+        print_operation_result(OperationResult::Error);
+        println!("{error}\n");
+        question.selecting_ask(
+            "The saved installation can't be continued. What do you want to do?",
+            &[
+                "Edit the config file and try again",
+                "Start a new installation",
+                "Exit",
+            ],
+        );
+        match question.selected_index() {
+            0 => {
+                let editor = env::var("EDITOR").unwrap_or(String::from("nano"));
+                run_interactive_command(editor.as_str(), Some(&[CONFIG_FILE_PATH]))?;
+            }
+            1 => {
+                let backup_file_path = format!("{CONFIG_FILE_PATH}.invalid");
+                fs::rename(CONFIG_FILE_PATH, &backup_file_path)?;
+                println!("Invalid config file was moved to {backup_file_path}.\n");
+                app_config.reset();
+            }
+            _ => return Ok(()),
+        }
+    }
+
+    if config_loaded {
+        TextManager::set_color(TextColor::Yellow);
+        formatted_print(
+            "Aborted installation was detected",
+            PrintFormat::DoubleDashedLine,
+        );
+        TextManager::reset_color_and_graphics();
+        if !question.bool_ask(
+            format!(
+                "Do you want to continue installation from step ({}/{})?",
+                app_config.completed_steps.len() + 1,
+                app_config.total_installation_steps
+            )
+            .as_str(),
+        ) {
+            app_config.reset();
+        } else {
+            step_menu_requested = question.bool_ask("Do you want to open the step menu?");
+        }
+    } else if command_line_arguments.has_step_selection() {
+        TextManager::set_color(TextColor::Yellow);
+        formatted_print(
+            "No saved installation was found. Selected steps will run with default settings.",
+            PrintFormat::DoubleDashedLine,
+        );
+        TextManager::reset_color_and_graphics();
+    }
+
+    // Total steps count may have changed since the aborted installation if custom steps were changed.
+    app_config.total_installation_steps = step_order.len() as u8;
+
+    if command_line_arguments.reinstall {
+        app_config.reinstall = true;
+        app_config.save_config();
+    }
+    if let Some(image_path) = command_line_arguments.image_path.take() {
+        app_config.image_path = Some(image_path);
+    }
+    if let Some(image_path) = &app_config.image_path {
+        let image_size = command_line_arguments
+            .image_size
+            .as_deref()
+            .unwrap_or(DEFAULT_IMAGE_SIZE);
+        app_config.loop_device = Some(attach_disk_image(image_path, image_size)?);
+        app_config.save_config();
+    }
+
+    if use_large_font && app_config.console_font.is_empty() {
+        app_config.console_font = LARGE_CONSOLE_FONT.to_string();
+        app_config.save_config();
+    }
+    if let Some(container_path) = command_line_arguments.container_path.take() {
+        if app_config.image_path.is_some() {
+            return Err(AppError::InternalError(String::from(
+                "Error! --container and --image can't be used together.",
+            )));
+        }
+        app_config.container_path = Some(container_path);
+        app_config.save_config();
+    }
+    if let Some(container_path) = &app_config.container_path {
+        mount_container_directory(container_path)?;
+        command_line_arguments
+            .skip_steps
+            .extend(CONTAINER_SKIPPED_STEPS.iter().map(|s| s.to_string()));
+    }
+
+    check_host_tools(&mut question)?;
+    check_power_supply(&mut question)?;
+    if is_step_selected("kde", &command_line_arguments, &app_config)
+        && !app_config.completed_steps.iter().any(|s| s == "kde")
+    {
+        check_desktop_requirements(&mut question, &mut app_config);
+    }
+
+    // Durations of the steps run in this session, for the installation summary.
+    let installation_start_time = time::Instant::now();
+    let mut step_durations: Vec<(String, time::Duration)> = Vec::new();
+    let mut installed_packages_count = None;
+    // Passphrase of the encrypted partitions, if it was entered in this session.
+    let mut luks_passphrase: Option<String> = None;
+
+    let mut next_step_index = 0;
+    while next_step_index < step_order.len() {
+        if step_menu_requested {
+            step_menu_requested = false;
+            match run_step_menu(
+                &mut question,
+                &mut app_config,
+                &step_order,
+                &customization,
+                &command_line_arguments,
+            ) {
+                StepMenuAction::Continue => {}
+                StepMenuAction::JumpTo(step_index) => {
+                    next_step_index = step_index;
+                    from_step_index = Some(step_index);
+                    command_line_arguments.only_steps.clear();
+                }
+                StepMenuAction::Abort => {
+                    app_config.save_config();
+                    TextManager::set_color(TextColor::Yellow);
+                    formatted_print(
+                        "Installation aborted. Run the installer again to continue.",
+                        PrintFormat::Bordered,
+                    );
+                    TextManager::reset_color_and_graphics();
+                    return Ok(());
+                }
+            }
+        }
+
+        let step_index = next_step_index;
+        let step_entry = &step_order[step_index];
+        next_step_index += 1;
+
+        let step_name = step_entry_name(step_entry, &customization.custom_steps);
+        if command_line_arguments
+            .skip_steps
+            .iter()
+            .chain(app_config.skipped_steps.iter())
+            .any(|s| s == step_name)
+        {
+            continue;
+        }
+        // Selected steps are run again even if they were completed before.
+        if !command_line_arguments.only_steps.is_empty() {
+            if !command_line_arguments
+                .only_steps
+                .iter()
+                .any(|s| s == step_name)
+            {
+                continue;
+            }
+        } else if let Some(from_step_index) = from_step_index {
+            if step_index < from_step_index {
+                continue;
+            }
+        } else if app_config.completed_steps.iter().any(|s| s == step_name) {
+            continue;
+        }
+        app_config.current_installation_step = step_index as u8 + 1;
+        *CURRENT_STEP_ID.lock().unwrap() = step_name.to_string();
+        append_to_log(&format!("Step started: {step_name}"));
+        // Hands-off installations report their progress, because nobody watches their screen.
+        if command_line_arguments.auto {
+            send_notification(&format!(
+                "Step {} of {} started: {step_name}",
+                step_index + 1,
+                step_order.len()
+            ));
+        }
+
+        let step_start_time = time::Instant::now();
+        run_step_hooks(
+            &customization.step_hooks,
+            HookTime::Before,
+            step_name,
+            &app_config,
+        )?;
+        check_pacman_locks(&mut question)?;
+
+        match step_entry {
+            StepEntry::Custom(custom_step_index) => {
+                let custom_step = &customization.custom_steps[*custom_step_index];
+                app_config.print_installation_status_and_save_config(&custom_step.title);
+
+                run_custom_step(custom_step, &mut question, &app_config)?;
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("installation_mode") => {
+                app_config
+                    .print_installation_status_and_save_config("BIOS / UEFI Installation mode");
+
+                question.selecting_ask("Which installation mode do you want?", &["BIOS", "UEFI"]);
+                if question.answer == "2" {
+                    app_config.uefi_install = true;
+                    app_config.save_config();
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("encryption") => {
+                app_config.print_installation_status_and_save_config("Encrypted partitoins");
+
+                if question.bool_ask("Do you want to encrypt your root and home partitions?") {
+                    app_config.encrypted_partitons = true;
+                    app_config.save_config();
+
+                    // systemd unlocks the partitions in the initramfs, which is needed for TPM unlock.
+                    app_config.systemd_initramfs = question.bool_ask("Do you want to use the systemd based initramfs hooks (sd-encrypt) to unlock the partitions? (Needed for TPM unlock)");
+                    app_config.save_config();
+                }
+            }
+            StepEntry::BuiltIn("timedatectl") => {
+                app_config.print_installation_status_and_save_config("Configuring timedatectl");
+
+                run_command("timedatectl", Some(&["set-ntp", "true"]))?;
+                check_clock(&mut question)?;
+                run_command("timedatectl", Some(&["status"]))?;
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("partitioning") => {
+                app_config.print_installation_status_and_save_config("Configuring partitions");
+
+                if app_config.reinstall {
+                    println!("Reinstalling, so the existing partitions are kept.\n");
+                    println!("Partitioning results:\n");
+                    run_command("lsblk", None)?;
+                } else {
+                    let disk_name = if let Some(loop_device) = &app_config.loop_device {
+                        println!(
+                            "Partitioning the disk image ({}). Its partitions will be named {}p1, {}p2, ...\n",
+                            loop_device, loop_device, loop_device
+                        );
+                        loop_device.clone()
+                    } else {
+                        run_command("fdisk", Some(&["-l"]))?;
+
+                        question.ask("Enter the disk you want to partion. (sda, sdb, ...): ");
+                        let disk_name = question.answer.clone();
+                        check_disk_health(&mut question, &disk_name)?;
+                        scan_for_bad_blocks(&mut question, &disk_name)?;
+                        disk_name
+                    };
+
+                    println!("Current layout:\n");
+                    print_disk_layout(&disk_name, &[]);
+                    run_interactive_command(
+                        "fdisk",
+                        Some(&[format!("/dev/{disk_name}").as_str()]),
+                    )?;
+
+                    println!("Partitioning results:\n");
+                    print_disk_layout(&disk_name, &[]);
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("partition_names") => {
+                app_config.print_installation_status_and_save_config("Getting partition names");
+
+                question.ask("Enter the name of your root partition: ");
+                app_config.root_partition = question.answer.clone();
+                app_config.save_config();
+
+                // Each device of an encrypted root would need its own LUKS container, so it's left out then.
+                app_config.root_devices.clear();
+                app_config.btrfs_profile = String::new();
+                if !app_config.encrypted_partitons
+                    && question.bool_ask(
+                        "Do you want the root file system to span several devices? (btrfs RAID)",
+                    )
+                {
+                    loop {
+                        question.ask("Enter the name of another device for the root file system. (Leave empty if there are no more): ");
+                        if question.answer.is_empty() {
+                            break;
+                        }
+                        app_config.root_devices.push(question.answer.clone());
+                    }
+                    if !app_config.root_devices.is_empty() {
+                        let devices_count = app_config.root_devices.len() + 1;
+                        loop {
+                            question.selecting_ask(
+                                "Which btrfs profile do you want to use for the root file system?",
+                                BTRFS_PROFILES,
+                            );
+                            let (profile, min_devices_count) =
+                                find_btrfs_profile(BTRFS_PROFILES[question.selected_index()]);
+                            if devices_count >= min_devices_count {
+                                app_config.btrfs_profile = profile.to_string();
+                                break;
+                            }
+                            println!("\nError: {profile} needs at least {min_devices_count} devices, but there are {devices_count}.\n");
+                        }
+                    }
+                    app_config.save_config();
+                }
+
+                if question.bool_ask("Do you have a separate boot partition?") {
+                    question.ask("Enter the name of your boot partition: ");
+                    app_config.boot_partition = Some(question.answer.clone());
+                    app_config.save_config();
+                }
+
+                if app_config.uefi_install {
+                    // Windows can only have one UEFI partition per disk, so sharing it is the usual dual boot setup.
+                    app_config.uefi_partition = None;
+                    if let Some(windows_esp) = find_windows_esp() {
+                        if question.bool_ask(
+                            format!("The UEFI partition of Windows (/dev/{windows_esp}) was found. Do you want to share it with Arch Linux?")
+                                .as_str(),
+                        ) {
+                            app_config.uefi_partition = Some(windows_esp);
+                        }
+                    }
+                    if app_config.uefi_partition.is_none() {
+                        question.ask("Enter the name of your uefi partition: ");
+                        app_config.uefi_partition = Some(question.answer.clone());
+                    }
+                    app_config.save_config();
+                }
+
+                if question.bool_ask("Do you have a separate home partition?") {
+                    question.ask("Enter the name of your home partition: ");
+                    app_config.home_partition = Some(question.answer.clone());
+                    app_config.save_config();
+                }
+
+                app_config.save_partition_uuids();
+
+                // Chosen partitions are shown on their disks, so mistakes are noticed before anything is formatted.
+                let encryption_note = if app_config.encrypted_partitons {
+                    ", encrypted"
+                } else {
+                    ""
+                };
+                let kept_or_asked = if app_config.reinstall {
+                    "kept"
+                } else {
+                    "format asked"
+                };
+                let mut partition_roles = vec![(
+                    app_config.root_partition.clone(),
+                    "root",
+                    format!("format asked{encryption_note}"),
+                )];
+                for root_device in &app_config.root_devices {
+                    partition_roles.push((
+                        root_device.clone(),
+                        "root",
+                        format!("btrfs {}", app_config.btrfs_profile),
+                    ));
+                }
+                if let Some(boot_partition) = &app_config.boot_partition {
+                    partition_roles.push((
+                        boot_partition.clone(),
+                        "boot",
+                        String::from("format asked"),
+                    ));
+                }
+                if let Some(uefi_partition) = &app_config.uefi_partition {
+                    let uefi_plan =
+                        if has_windows_boot_manager(&app_config.partition_path(uefi_partition)) {
+                            "kept, shared with Windows"
+                        } else {
+                            kept_or_asked
+                        };
+                    partition_roles.push((uefi_partition.clone(), "ESP", uefi_plan.to_string()));
+                }
+                if let Some(home_partition) = &app_config.home_partition {
+                    partition_roles.push((
+                        home_partition.clone(),
+                        "home",
+                        format!("{kept_or_asked}{encryption_note}"),
+                    ));
+                }
+                let partition_roles = partition_roles
+                    .iter()
+                    .map(|(partition, role, plan)| {
+                        let partition_path = app_config.partition_path(partition);
+                        let partition_name = partition_path.trim_start_matches("/dev/").to_string();
+                        (partition_name, *role, plan.as_str())
+                    })
+                    .collect::<Vec<(String, &str, &str)>>();
+                let mut disk_names: Vec<String> = Vec::new();
+                for (partition_name, _, _) in &partition_roles {
+                    if let Some(disk_name) = find_parent_disk(&format!("/dev/{partition_name}")) {
+                        if !disk_names.contains(&disk_name) {
+                            disk_names.push(disk_name);
+                        }
+                    }
+                }
+                for disk_name in &disk_names {
+                    print_disk_layout(disk_name, &partition_roles);
+                }
+
+                // Other Linux installations are listed before anything is formatted, so one isn't overwritten by
+                // mistake and the others can be kept in the boot menu.
+                let linux_installations = find_linux_installations();
+                if !linux_installations.is_empty() {
+                    println!("Linux installations were found:");
+                    let mut has_kept_installations = false;
+                    for (partition_path, system_name) in &linux_installations {
+                        let role = partition_roles
+                            .iter()
+                            .find(|(partition_name, _, _)| {
+                                format!("/dev/{partition_name}") == *partition_path
+                            })
+                            .map(|(_, role, plan)| format!(" ({role} partition, {plan})"));
+                        has_kept_installations |= role.is_none();
+                        println!(
+                            "- {system_name} on {partition_path}{}",
+                            role.unwrap_or_default()
+                        );
+                    }
+                    println!();
+                    if has_kept_installations {
+                        app_config.probe_other_systems = question.bool_ask(
+                            "Do you want to add the other Linux installations to the GRUB menu?",
+                        );
+                        app_config.save_config();
+                    }
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("format_partitions") => {
+                app_config.print_installation_status_and_save_config("Formatting partitions");
+
+                // Each partition is a sub-step, so continuing after a failure doesn't ask about the finished ones again.
+                let root_path = app_config.partition_path(&app_config.root_partition);
+                if !app_config.is_sub_step_completed("format_partitions/root") {
+                    // Reinstalling keeps the UUID of the root partition, so other boot entries pointing to it keep working.
+                    let previous_root_uuid = if app_config.reinstall {
+                        query_block_device(&root_path).uuid
+                    } else {
+                        None
+                    };
+                    if question.bool_ask(&create_format_question("root", &root_path)) {
+                        ensure_not_mounted(&root_path)?;
+                        if app_config.encrypted_partitons {
+                            ensure_not_mounted("/dev/mapper/cryptroot")?;
+                            close_encrypted_partition("cryptroot")?;
+                            let passphrase =
+                                ask_luks_passphrase(&mut question, &mut luks_passphrase);
+                            let mut luks_format_arguments =
+                                customization.luks_format_arguments.clone();
+                            if let Some(previous_uuid) = &previous_root_uuid {
+                                luks_format_arguments
+                                    .extend([String::from("--uuid"), previous_uuid.clone()]);
+                            }
+                            format_encrypted_partition(
+                                &root_path,
+                                &passphrase,
+                                &luks_format_arguments,
+                            )?;
+                            open_encrypted_partition(&root_path, "cryptroot", Some(&passphrase))?;
+                            run_command("mkfs.btrfs", Some(&["-f", "/dev/mapper/cryptroot"]))?;
+                        } else {
+                            let mut mkfs_arguments = vec!["-f", root_path.as_str()];
+                            if let Some(previous_uuid) = &previous_root_uuid {
+                                mkfs_arguments.extend(["-U", previous_uuid.as_str()]);
+                            }
+                            let root_device_paths = app_config
+                                .root_devices
+                                .iter()
+                                .map(|d| app_config.partition_path(d))
+                                .collect::<Vec<String>>();
+                            if !root_device_paths.is_empty() {
+                                // Metadata is mirrored even with the single profile, so a failed device doesn't
+                                // lose the whole file system.
+                                let metadata_profile = if app_config.btrfs_profile == "single" {
+                                    "raid1"
+                                } else {
+                                    app_config.btrfs_profile.as_str()
+                                };
+                                mkfs_arguments.extend([
+                                    "-d",
+                                    app_config.btrfs_profile.as_str(),
+                                    "-m",
+                                    metadata_profile,
+                                ]);
+                                for root_device_path in &root_device_paths {
+                                    ensure_not_mounted(root_device_path)?;
+                                    mkfs_arguments.push(root_device_path.as_str());
+                                }
+                            }
+                            run_command("mkfs.btrfs", Some(&mkfs_arguments))?;
+                            if !root_device_paths.is_empty() {
+                                println!("\nIf a device of the root file system fails, the system only boots if 'rootflags=degraded' is added to the kernel parameters in the GRUB menu (press e). Replace the device with 'btrfs replace' then.\n");
+                            }
+                        }
+                    } else if app_config.encrypted_partitons {
+                        open_encrypted_partition(
+                            &root_path,
+                            "cryptroot",
+                            luks_passphrase.as_deref(),
+                        )?;
+                    }
+                    app_config.complete_sub_step("format_partitions/root");
+                }
+
+                if let Some(boot_partition) = app_config.boot_partition.clone() {
+                    let boot_path = app_config.partition_path(&boot_partition);
+                    if !app_config.is_sub_step_completed("format_partitions/boot") {
+                        if question.bool_ask(&create_format_question("boot", &boot_path)) {
+                            ensure_not_mounted(&boot_path)?;
+                            run_command("mkfs.btrfs", Some(&["-f", boot_path.as_str()]))?;
+                        }
+                        app_config.complete_sub_step("format_partitions/boot");
+                    }
+                }
+
+                if let Some(uefi_partition) = app_config.uefi_partition.clone() {
+                    let uefi_path = app_config.partition_path(&uefi_partition);
+                    if !app_config.is_sub_step_completed("format_partitions/uefi") {
+                        if app_config.reinstall {
+                            println!("Reinstalling, so the UEFI partition ({uefi_path}) is kept.");
+                        } else if has_windows_boot_manager(&uefi_path) {
+                            // Formatting it would leave Windows without its boot manager.
+                            println!("The UEFI partition ({uefi_path}) holds the boot manager of Windows, so it is kept.");
+                        } else if question.bool_ask(&create_format_question("uefi", &uefi_path))
+                            && confirm_boot_loaders_removal(&mut question, &uefi_path)
+                        {
+                            ensure_not_mounted(&uefi_path)?;
+                            run_command("mkfs.fat", Some(&["-F32", uefi_path.as_str()]))?;
+                        }
+                        app_config.complete_sub_step("format_partitions/uefi");
+                    }
+                }
+
+                if let Some(home_partition) = app_config.home_partition.clone() {
+                    let home_path = app_config.partition_path(&home_partition);
+                    if !app_config.is_sub_step_completed("format_partitions/home") {
+                        // An existing home partition can be kept, so reinstalling doesn't lose user data.
+                        if app_config.reinstall {
+                            println!("Reinstalling, so the home partition ({home_path}) is kept.");
+                            app_config.reuse_home = true;
+                            app_config.save_config();
+                        } else if let Some(file_system_type) = find_file_system_type(&home_path) {
+                            app_config.reuse_home = question.bool_ask(
+                                format!("Your home partition ({home_path}) already contains {file_system_type}. Do you want to keep its data and reuse it?").as_str(),
+                            );
+                            app_config.save_config();
+                        }
+
+                        if !app_config.reuse_home
+                            && question.bool_ask(&create_format_question("home", &home_path))
+                        {
+                            ensure_not_mounted(&home_path)?;
+                            if app_config.encrypted_partitons {
+                                ensure_not_mounted("/dev/mapper/crypthome")?;
+                                close_encrypted_partition("crypthome")?;
+                                let passphrase =
+                                    ask_luks_passphrase(&mut question, &mut luks_passphrase);
+                                format_encrypted_partition(
+                                    &home_path,
+                                    &passphrase,
+                                    &customization.luks_format_arguments,
+                                )?;
+                                open_encrypted_partition(
+                                    &home_path,
+                                    "crypthome",
+                                    Some(&passphrase),
+                                )?;
+                                run_command("mkfs.btrfs", Some(&["-f", "/dev/mapper/crypthome"]))?;
+                            } else {
+                                run_command("mkfs.btrfs", Some(&["-f", home_path.as_str()]))?;
+                            }
+                        } else if app_config.encrypted_partitons {
+                            open_encrypted_partition(
+                                &home_path,
+                                "crypthome",
+                                luks_passphrase.as_deref(),
+                            )?;
+                        }
+                        app_config.complete_sub_step("format_partitions/home");
+                    }
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("swap") => {
+                app_config.print_installation_status_and_save_config("Enabling swap");
+
+                question.selecting_ask(
+                    "Which kind of swap do you want to use?",
+                    &[
+                        "No swap",
+                        "Swap partition",
+                        "zram",
+                        "Swap partition and zram",
+                    ],
+                );
+                let use_swap_partition = question.answer == "2" || question.answer == "4";
+                let use_zram = question.answer == "3" || question.answer == "4";
+
+                if use_zram {
+                    question.ask("Enter the zram size expression. (Leave empty to use 'min(ram / 2, 4096)'): ");
+                    app_config.zram_size = Some(if question.answer.is_empty() {
+                        String::from("min(ram / 2, 4096)")
+                    } else {
+                        question.answer.clone()
+                    });
+                    app_config.save_config();
+
+                    question.selecting_ask(
+                        "Which compression algorithm do you want zram to use?",
+                        ZRAM_COMPRESSION_ALGORITHMS,
+                    );
+                    app_config.zram_compression_algorithm =
+                        ZRAM_COMPRESSION_ALGORITHMS[question.selected_index()].to_string();
+                    app_config.save_config();
+                }
+
+                if use_swap_partition {
+                    question.ask("Enter name of the swap partition: ");
+                    let swap_partition = question.answer.clone();
+                    app_config.swap_partition = Some(swap_partition.clone());
+                    app_config.save_partition_uuids();
+
+                    app_config.hibernation = false;
+                    if question.bool_ask("Do you want to enable hibernation to the swap partition?")
+                    {
+                        // Encrypted swap with a new random key on every boot can't read a hibernation image back, so
+                        // it's kept only if hibernation isn't wanted after all.
+                        if app_config.encrypted_partitons {
+                            question.selecting_ask(
+                                "How do you want to encrypt the swap partition?",
+                                &[
+                                    "With the passphrase of the encrypted partitions (Hibernation works)",
+                                    "With a new random key on every boot (No hibernation)",
+                                ],
+                            );
+                            app_config.hibernation = question.selected_index() == 0;
+                        } else {
+                            app_config.hibernation = true;
+                        }
+                    }
+                    // Only the systemd based initramfs can unlock the swap partition besides root before resuming.
+                    if app_config.has_persistent_swap_encryption() && !app_config.systemd_initramfs
+                    {
+                        println!("The systemd based initramfs hooks (sd-encrypt) will be used, because they can unlock the swap partition before resuming from hibernation.\n");
+                        app_config.systemd_initramfs = true;
+                    }
+                    app_config.save_config();
+
+                    if app_config.has_persistent_swap_encryption() {
+                        let partition_path = app_config.partition_path(&swap_partition);
+                        if find_file_system_type(&partition_path).as_deref() != Some("crypto_LUKS")
+                        {
+                            if is_swap_active(&partition_path) {
+                                run_command("swapoff", Some(&[partition_path.as_str()]))?;
+                            }
+                            let passphrase =
+                                ask_luks_passphrase(&mut question, &mut luks_passphrase);
+                            format_encrypted_partition(
+                                &partition_path,
+                                &passphrase,
+                                &customization.luks_format_arguments,
+                            )?;
+                        }
+                        open_encrypted_partition(
+                            &partition_path,
+                            "swap",
+                            luks_passphrase.as_deref(),
+                        )?;
+                    }
+
+                    let swap_path = app_config.swap_path().unwrap_or_default();
+                    if is_swap_active(&swap_path) {
+                        println!(
+                            "{swap_path} is already used as swap, skipping mkswap and swapon."
+                        );
+                    } else {
+                        run_command("mkswap", Some(&[swap_path.as_str()]))?;
+                        run_command("swapon", Some(&[swap_path.as_str()]))?;
+                    }
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("mount_partitions") => {
+                app_config.print_installation_status_and_save_config("Mounting partitions");
+
+                // Encrypted partitions and swap are closed by cleanup, so they are opened again when continuing after it.
+                if app_config.encrypted_partitons {
+                    open_encrypted_partition(
+                        &app_config.partition_path(&app_config.root_partition),
+                        "cryptroot",
+                        luks_passphrase.as_deref(),
+                    )?;
+                    if let Some(home_partition) = &app_config.home_partition {
+                        open_encrypted_partition(
+                            &app_config.partition_path(home_partition),
+                            "crypthome",
+                            luks_passphrase.as_deref(),
+                        )?;
+                    }
+                }
+                if app_config.has_persistent_swap_encryption() {
+                    if let Some(swap_partition) = &app_config.swap_partition {
+                        open_encrypted_partition(
+                            &app_config.partition_path(swap_partition),
+                            "swap",
+                            luks_passphrase.as_deref(),
+                        )?;
+                    }
+                }
+                if let Some(swap_path) = app_config.swap_path() {
+                    if !is_swap_active(&swap_path) {
+                        run_command("swapon", Some(&[swap_path.as_str()]))?;
+                    }
+                }
+
+                let root_source = if app_config.encrypted_partitons {
+                    String::from("/dev/mapper/cryptroot")
+                } else {
+                    app_config.partition_path(&app_config.root_partition)
+                };
+                // A file system with several devices can only be mounted after btrfs knows all of them.
+                if !app_config.root_devices.is_empty() {
+                    run_command("btrfs", Some(&["device", "scan"]))?;
+                }
+                mount_partition(&root_source, "/mnt")?;
+
+                if let Some(boot_partition) = &app_config.boot_partition {
+                    run_command("mkdir", Some(&["-p", "/mnt/boot"]))?;
+                    mount_partition(&app_config.partition_path(boot_partition), "/mnt/boot")?;
+                }
+
+                if let Some(uefi_partition) = &app_config.uefi_partition {
+                    run_command("mkdir", Some(&["-p", "/mnt/boot/EFI"]))?;
+                    mount_partition(&app_config.partition_path(uefi_partition), "/mnt/boot/EFI")?;
+                }
+
+                if let Some(home_partition) = &app_config.home_partition {
+                    run_command("mkdir", Some(&["-p", "/mnt/home"]))?;
+                    if app_config.encrypted_partitons {
+                        mount_partition("/dev/mapper/crypthome", "/mnt/home")?;
+                    } else {
+                        mount_partition(&app_config.partition_path(home_partition), "/mnt/home")?;
+                    }
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("mirrors") => {
+                app_config.print_installation_status_and_save_config("Updating mirrors");
+
+                // Countries are listed by reflector, which needs the network. Typing them is the fallback.
+                let available_countries =
+                    run_command_with_output("reflector", &["--list-countries"])
+                        .map(|o| find_reflector_countries(&o))
+                        .unwrap_or_default();
+                if available_countries.is_empty() {
+                    question.ask("Enter the name of your prefered country for mirrors. (For example: France,Germany,...): ");
+                    app_config.mirror_country = question.answer.clone();
+                } else {
+                    let country_names = available_countries
+                        .iter()
+                        .map(|(name, _)| name.as_str())
+                        .collect::<Vec<&str>>();
+                    let mut selected_countries: Vec<&str> = Vec::new();
+                    loop {
+                        let country = question.picking_ask(
+                            "Enter part of the name of your prefered country for mirrors. (For example: France, Germany,...): ",
+                            "Which country do you want to get mirrors from?",
+                            &country_names,
+                        );
+                        if !selected_countries.contains(&country) {
+                            selected_countries.push(country);
+                        }
+
+                        println!("\nSelected countries: {}\n", selected_countries.join(", "));
+                        if !question.bool_ask("Do you want to add another country?") {
+                            break;
+                        }
+                    }
+                    // Country codes are saved, because some country names contain commas.
+                    app_config.mirror_country = available_countries
+                        .iter()
+                        .filter(|(name, _)| selected_countries.contains(&name.as_str()))
+                        .map(|(_, code)| code.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(",");
+                }
+                app_config.save_config();
+
+                run_command_with_retries(
+                    "reflector",
+                    Some(&[
+                        "--latest",
+                        "10",
+                        "--country",
+                        app_config.mirror_country.as_str(),
+                        "--protocol",
+                        "http,https",
+                        "--sort",
+                        "rate",
+                        "--save",
+                        "/etc/pacman.d/mirrorlist",
+                    ]),
+                    NETWORK_RETRY_POLICY,
+                )?;
+                // reflector sorts by the rates measured by archlinux.org, which are often wrong from here.
+                if question.bool_ask(
+                    "Do you want to test the download speed of the mirrors and sort them by it?",
+                ) {
+                    sort_mirrors_by_speed(&mut question)?;
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("pacman") => {
+                app_config.print_installation_status_and_save_config("Configuring pacman");
+
+                let mut pacman_config = PacmanConfig::parse(
+                    &fs::read_to_string("/etc/pacman.conf")
+                        .expect("Error reading from /etc/pacman.conf"),
+                );
+                set_pacman_options(&mut pacman_config);
+                write_target_file(&mut question, "/etc/pacman.conf", pacman_config.to_string())?;
+
+                // Mirrors and pacman are ready, so packages are downloaded while the partitions are prepared.
+                if is_step_selected("pacstrap", &command_line_arguments, &app_config)
+                    && !app_config.completed_steps.iter().any(|s| s == "pacstrap")
+                    && app_config.container_path.is_none()
+                {
+                    start_downloading_packages(&app_config.cpu_brand);
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("pacstrap") => {
+                app_config.print_installation_status_and_save_config(
+                    "Starting to install base system and some softwares",
+                );
+
+                // Answer is kept, so it isn't asked again if pacstrap fails and the installation is continued.
+                // Containers use the kernel of their host, so they don't need microcode.
+                if app_config.cpu_brand.is_empty() && app_config.container_path.is_none() {
+                    question.ask("What is your system's CPU brand? (Enter 'amd' or 'intel'): ");
+                    app_config.cpu_brand = question.answer.clone();
+                    app_config.save_config();
+                }
+                let microcode_package = format!("{}-ucode", app_config.cpu_brand);
+                let mut packages = BASE_PACKAGES.to_vec();
+                if app_config.container_path.is_some() {
+                    packages.retain(|p| !CONTAINER_EXCLUDED_PACKAGES.contains(p));
+                } else {
+                    packages.push(microcode_package.as_str());
+                }
+                // If pacstrap failed before, packages which were installed are left out. Downloaded packages are
+                // kept in the cache of /mnt, so they aren't downloaded again either.
+                let installed_packages = find_installed_packages("/mnt", &packages);
+                packages.retain(|p| !installed_packages.iter().any(|i| i == p));
+                if packages.is_empty() {
+                    println!("All packages are already installed.\n");
+                } else {
+                    if !installed_packages.is_empty() {
+                        println!(
+                            "Already installed packages are skipped: {}\n",
+                            installed_packages.join(" ")
+                        );
+                    }
+
+                    // Packages of the desktop step are counted too, because they go to the same partition later.
+                    let mut estimated_packages = packages.clone();
+                    if is_step_selected("kde", &command_line_arguments, &app_config) {
+                        estimated_packages.extend(KDE_PACKAGES);
+                    }
+                    check_disk_space(&mut question, &estimated_packages)?;
+                    // Clock may have been wrong since the timedatectl step, e.g. if it was skipped or ran in another boot.
+                    if current_year() < MIN_CLOCK_YEAR {
+                        check_clock(&mut question)?;
+                    }
+
+                    move_downloaded_packages();
+
+                    // Keyring of /mnt is initialized only once, so a retry doesn't replace it.
+                    let mut pacstrap_arguments = if Path::new("/mnt/etc/pacman.d/gnupg").exists() {
+                        vec!["/mnt", "--needed"]
+                    } else {
+                        vec!["-K", "/mnt", "--needed"]
+                    };
+                    pacstrap_arguments.extend(packages);
+                    run_command_with_retries(
+                        "pacstrap",
+                        Some(&pacstrap_arguments),
+                        NETWORK_RETRY_POLICY,
+                    )?;
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("fstab") => {
+                app_config
+                    .print_installation_status_and_save_config("Generating file system table");
+
+                let output = run_command_with_output("genfstab", &["-U", "/mnt"])?;
+
+                let fstab_mount_options = FstabMountOptions {
+                    noatime: question
+                        .bool_ask("Do you want to use noatime instead of relatime on all file systems?"),
+                    btrfs_compression: question
+                        .bool_ask("Do you want to enable zstd compression on btrfs file systems?"),
+                    btrfs_async_discard: question.bool_ask(
+                        "Do you want to enable continuous TRIM (discard=async) on btrfs file systems? (Otherwise periodic TRIM with fstrim can be used)",
+                    ),
+                    commit_interval: loop {
+                        question.ask("Enter the commit interval of btrfs and ext4 file systems in seconds. (Leave empty to keep the default): ");
+                        if question.answer.is_empty() {
+                            break None;
+                        }
+                        match question.answer.parse::<u32>() {
+                            Ok(commit_interval) if commit_interval > 0 => break Some(commit_interval),
+                            _ => println!("\nError: Enter only a positive number!\n"),
+                        }
+                    },
+                };
+
+                write_target_file(
+                    &mut question,
+                    "/mnt/etc/fstab",
+                    apply_fstab_mount_options(&output, &fstab_mount_options),
+                )?;
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("zram_and_swap_encryption") => {
+                app_config.print_installation_status_and_save_config(
+                    "Configuring zram and swap encryption if necessary",
+                );
+
+                if let Some(zram_size) = &app_config.zram_size {
+                    run_command_with_retries(
+                        "arch-chroot",
+                        Some(&["/mnt", "pacman", "-Sy", "zram-generator", "--noconfirm"]),
+                        NETWORK_RETRY_POLICY,
+                    )?;
+
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/systemd/zram-generator.conf",
+                        format!(
+                            "[zram0]\nzram-size = {}\ncompression-algorithm = {}\n",
+                            zram_size, app_config.zram_compression_algorithm
+                        ),
+                    )?;
+                }
+
+                if app_config.encrypted_partitons {
+                    if let Some(swap_partition) = &app_config.swap_partition {
+                        // A swap partition with a random key is found by the label of a small ext2 file system at
+                        // its start, because its UUID changes on every boot. A LUKS swap partition keeps its UUID.
+                        if !app_config.has_persistent_swap_encryption() {
+                            let swap_path = app_config.partition_path(swap_partition);
+                            run_command("swapoff", Some(&[swap_path.as_str()]))?;
+                            run_command(
+                                "mkfs.ext2",
+                                Some(&["-L", "cryptswap", swap_path.as_str(), "1M"]),
+                            )?;
+                        }
+
+                        let fstab_content = fs::read_to_string("/mnt/etc/fstab")
+                            .expect("Error reading from /mnt/etc/fstab");
+                        let found_swap_line = fstab_content
+                            .lines()
+                            .filter(|l| l.contains("swap"))
+                            .collect::<Vec<&str>>()[0];
+                        let swap_uuid =
+                            found_swap_line.split_whitespace().collect::<Vec<&str>>()[0];
+
+                        write_target_file(
+                            &mut question,
+                            "/mnt/etc/fstab",
+                            fstab_content.replace(swap_uuid, "/dev/mapper/swap"),
+                        )?;
+                    }
+                }
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("fstab_review") => {
+                app_config.print_installation_status_and_save_config("Reviewing file system table");
+
+                loop {
+                    println!(
+                        "{}",
+                        fs::read_to_string("/mnt/etc/fstab")
+                            .expect("Error reading from /mnt/etc/fstab")
+                    );
+
+                    if question.bool_ask("Do you want to edit the file system table?") {
+                        let editor = env::var("EDITOR").unwrap_or(String::from("nano"));
+                        run_interactive_command(editor.as_str(), Some(&["/mnt/etc/fstab"]))?;
+                    }
+
+                    // An invalid fstab is the most common reason of an unbootable system.
+                    if let Err(error) = run_command(
+                        "arch-chroot",
+                        Some(&["/mnt", "findmnt", "--verify", "--verbose"]),
+                    ) {
+                        print_operation_result(OperationResult::Error);
+                        if !question.bool_ask(
+                            "The file system table is invalid. Do you want to review it again?",
+                        ) {
+                            TextManager::set_color(TextColor::Red);
+                            formatted_print("Installation failed.", PrintFormat::Bordered);
+                            return Err(error);
+                        }
+                    } else {
+                        break;
+                    }
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("target_pacman") => {
+                app_config.print_installation_status_and_save_config(
+                    "Configuring pacman for installed system",
+                );
+
+                let mut pacman_config = PacmanConfig::parse(
+                    &fs::read_to_string("/mnt/etc/pacman.conf")
+                        .expect("Error reading from /mnt/etc/pacman.conf"),
+                );
+                set_pacman_options(&mut pacman_config);
+                if question.bool_ask(
+                    "Do you want to enable the multilib repository? (Needed for 32-bit software like Steam and Wine)",
+                ) {
+                    pacman_config
+                        .add_repository("multilib", &[("Include", "/etc/pacman.d/mirrorlist")]);
+                }
+                write_target_file(
+                    &mut question,
+                    "/mnt/etc/pacman.conf",
+                    pacman_config.to_string(),
+                )?;
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("time_zone") => {
+                app_config.print_installation_status_and_save_config("Setting time zone");
+
+                let time_zones_output =
+                    run_command_with_output("timedatectl", &["list-timezones"])?;
+                let available_time_zones = time_zones_output.lines().collect::<Vec<&str>>();
+
+                let time_zone = question.picking_ask(
+                    "Enter part of your time zone. (For example: London, Tehran, New_York,...): ",
+                    "Which time zone do you want to use?",
+                    &available_time_zones,
+                );
+                app_config.time_zone = time_zone.to_string();
+                app_config.save_config();
+                run_command(
+                    "arch-chroot",
+                    Some(&[
+                        "/mnt",
+                        "ln",
+                        "-sf",
+                        format!("/usr/share/zoneinfo/{}", time_zone).as_str(),
+                        "/etc/localtime",
+                    ]),
+                )?;
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("hardware_clock") => {
+                app_config.print_installation_status_and_save_config("Setting hardware clock");
+
+                run_command("arch-chroot", Some(&["/mnt", "hwclock", "--systohc"]))?;
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("time_sync") => {
+                app_config
+                    .print_installation_status_and_save_config("Configuring time synchronization");
+
+                question.selecting_ask(
+                    "Which service do you want to use to keep the clock in sync?",
+                    &[
+                        "systemd-timesyncd (Simple client which comes with systemd)",
+                        "chrony (Keeps the time more exactly and suits machines which are often offline)",
+                        "None (The clock is set by hand or by another tool)",
+                    ],
+                );
+                let time_sync_service = question.selected_index();
+
+                if time_sync_service != 2 {
+                    question.ask("Enter the NTP servers you want to use separated by spaces. (Leave empty to use the Arch Linux pool): ");
+                    let ntp_servers = question
+                        .answer
+                        .split_whitespace()
+                        .map(String::from)
+                        .collect::<Vec<String>>();
+
+                    if time_sync_service == 0 {
+                        if !ntp_servers.is_empty() {
+                            fs::create_dir_all("/mnt/etc/systemd/timesyncd.conf.d")
+                                .expect("Error creating /mnt/etc/systemd/timesyncd.conf.d");
+                            write_target_file(
+                                &mut question,
+                                "/mnt/etc/systemd/timesyncd.conf.d/ntp_servers.conf",
+                                format!("[Time]\nNTP={}\n", ntp_servers.join(" ")),
+                            )?;
+                        }
+                        run_command(
+                            "arch-chroot",
+                            Some(&["/mnt", "systemctl", "enable", "systemd-timesyncd"]),
+                        )?;
+                    } else {
+                        run_command_with_retries(
+                            "arch-chroot",
+                            Some(&["/mnt", "pacman", "-S", "--needed", "--noconfirm", "chrony"]),
+                            NETWORK_RETRY_POLICY,
+                        )?;
+                        if !ntp_servers.is_empty() {
+                            // Servers of the default config are commented out, so only the entered ones are used.
+                            let mut chrony_config = fs::read_to_string("/mnt/etc/chrony.conf")
+                                .expect("Error reading from /mnt/etc/chrony.conf")
+                                .lines()
+                                .map(|l| {
+                                    if l.starts_with("pool ") || l.starts_with("server ") {
+                                        format!("#{l}\n")
+                                    } else {
+                                        format!("{l}\n")
+                                    }
+                                })
+                                .collect::<String>();
+                            for ntp_server in &ntp_servers {
+                                chrony_config.push_str(&format!("server {ntp_server} iburst\n"));
+                            }
+                            write_target_file(
+                                &mut question,
+                                "/mnt/etc/chrony.conf",
+                                chrony_config,
+                            )?;
+                        }
+                        run_command(
+                            "arch-chroot",
+                            Some(&["/mnt", "systemctl", "enable", "chronyd"]),
+                        )?;
+                    }
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("locale") => {
+                app_config.print_installation_status_and_save_config("Setting locale");
+
+                let locale_gen_content = fs::read_to_string("/mnt/etc/locale.gen")
+                    .expect("Error reading from /mnt/etc/locale.gen");
+                let available_locales = find_available_locales(&locale_gen_content);
+
+                let mut selected_locales: Vec<&str> = Vec::new();
+                loop {
+                    let selected_locale = question.picking_ask(
+                        "Enter part of the locale you want to enable. (For example: en_US, de_DE, fa_IR,...): ",
+                        "Which locale do you want to enable?",
+                        &available_locales,
+                    );
+                    if !selected_locales.contains(&selected_locale) {
+                        selected_locales.push(selected_locale);
+                    }
+
+                    println!("\nEnabled locales: {}\n", selected_locales.join(", "));
+                    if !question.bool_ask("Do you want to enable another locale?") {
+                        break;
+                    }
+                }
+                app_config.locales = selected_locales.iter().map(|l| l.to_string()).collect();
+                app_config.save_config();
+
+                write_target_file(
+                    &mut question,
+                    "/mnt/etc/locale.gen",
+                    locale_gen_content
+                        .lines()
+                        .map(|l| match l.strip_prefix('#') {
+                            Some(locale) if selected_locales.contains(&locale) => locale,
+                            _ => l,
+                        })
+                        .collect::<Vec<&str>>()
+                        .join("\n")
+                        + "\n",
+                )?;
+
+                run_command("arch-chroot", Some(&["/mnt", "locale-gen"]))?;
+
+                // Only the locale name is used in locale.conf, not its charset. (en_US.UTF-8 UTF-8 -> en_US.UTF-8)
+                let locale_names = selected_locales
+                    .iter()
+                    .map(|l| l.split_whitespace().next().unwrap_or(l))
+                    .collect::<Vec<&str>>();
+
+                question.selecting_ask(
+                    "Which locale do you want to use as the system language (LANG)?",
+                    &locale_names,
+                );
+                let mut locale_conf_content =
+                    format!("LANG={}\n", locale_names[question.selected_index()]);
+
+                if locale_names.len() > 1
+                    && question.bool_ask(
+                        "Do you want to use other locales for specific categories? (LC_TIME, LC_MONETARY, ...)",
+                    )
+                {
+                    loop {
+                        question.selecting_ask("Which category do you want to set?", LOCALE_CATEGORIES);
+                        let locale_category = LOCALE_CATEGORIES[question.selected_index()];
+
+                        question.selecting_ask(
+                            format!("Which locale do you want to use for {locale_category}?").as_str(),
+                            &locale_names,
+                        );
+                        locale_conf_content.push_str(
+                            format!("{}={}\n", locale_category, locale_names[question.selected_index()])
+                                .as_str(),
+                        );
+
+                        if !question.bool_ask("Do you want to set another category?") {
+                            break;
+                        }
+                    }
+                }
+
+                write_target_file(&mut question, "/mnt/etc/locale.conf", locale_conf_content)?;
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("console_keymap") => {
+                app_config.print_installation_status_and_save_config("Setting console keymap");
+
+                let keymaps_output = run_command_with_output("localectl", &["list-keymaps"])?;
+                let available_keymaps = keymaps_output.lines().collect::<Vec<&str>>();
+
+                let keymap = question.picking_ask(
+                    "Enter part of the console keymap you want to use. (For example: us, de, fr,...): ",
+                    "Which console keymap do you want to use?",
+                    &available_keymaps,
+                );
+                run_command("loadkeys", Some(&[keymap]))?;
+                let mut vconsole_conf_content = format!("KEYMAP={keymap}\n");
+
+                // Font chosen at the start of the installation is kept instead of being asked.
+                if app_config.console_font.is_empty() {
+                    question.ask("Enter the console font you want to use. (Leave empty to use the default font): ");
+                    app_config.console_font = question.answer.clone();
+                    app_config.save_config();
+                }
+                if !app_config.console_font.is_empty() {
+                    // Terminus fonts are on the live ISO, but not in the base system.
+                    if app_config.console_font.starts_with("ter-") {
+                        run_command_with_retries(
+                            "arch-chroot",
+                            Some(&[
+                                "/mnt",
+                                "pacman",
+                                "-S",
+                                "--needed",
+                                "--noconfirm",
+                                "terminus-font",
+                            ]),
+                            NETWORK_RETRY_POLICY,
+                        )?;
+                    }
+                    run_command("setfont", Some(&[app_config.console_font.as_str()]))?;
+                    vconsole_conf_content
+                        .push_str(format!("FONT={}\n", app_config.console_font).as_str());
+                }
+
+                write_target_file(
+                    &mut question,
+                    "/mnt/etc/vconsole.conf",
+                    vconsole_conf_content,
+                )?;
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("keyboard_layout") => {
+                app_config
+                    .print_installation_status_and_save_config("Setting desktop keyboard layout");
+
+                let layouts_output =
+                    run_command_with_output("localectl", &["list-x11-keymap-layouts"])?;
+                let available_layouts = layouts_output.lines().collect::<Vec<&str>>();
+
+                let mut selected_layouts: Vec<&str> = Vec::new();
+                let mut selected_variants: Vec<String> = Vec::new();
+                loop {
+                    let layout = question.picking_ask(
+                        "Enter part of the keyboard layout you want to add. (For example: us, de, ir,...): ",
+                        "Which keyboard layout do you want to add?",
+                        &available_layouts,
+                    );
+
+                    let variants_output = run_command_with_output(
+                        "localectl",
+                        &["list-x11-keymap-variants", layout],
+                    )?;
+                    let available_variants = variants_output.lines().collect::<Vec<&str>>();
+
+                    let mut variant = "";
+                    if !available_variants.is_empty()
+                        && question.bool_ask(
+                            format!("Do you want to use a variant of the '{layout}' layout?")
+                                .as_str(),
+                        )
+                    {
+                        variant = question.picking_ask(
+                            "Enter part of the variant you want to use: ",
+                            "Which variant do you want to use?",
+                            &available_variants,
+                        );
+                    }
+
+                    selected_layouts.push(layout);
+                    selected_variants.push(variant.to_string());
+
+                    println!(
+                        "\nAdded keyboard layouts: {}\n",
+                        selected_layouts.join(", ")
+                    );
+                    if !question.bool_ask("Do you want to add another keyboard layout?") {
+                        break;
+                    }
+                }
+
+                let mut keyboard_conf_content = format!(
+                    "Section \"InputClass\"\n\tIdentifier \"system-keyboard\"\n\tMatchIsKeyboard \"on\"\n\tOption \"XkbLayout\" \"{}\"\n",
+                    selected_layouts.join(",")
+                );
+                if selected_variants.iter().any(|v| !v.is_empty()) {
+                    keyboard_conf_content.push_str(
+                        format!(
+                            "\tOption \"XkbVariant\" \"{}\"\n",
+                            selected_variants.join(",")
+                        )
+                        .as_str(),
+                    );
+                }
+                // Alt+Shift switches between layouts when more than one layout is used.
+                if selected_layouts.len() > 1 {
+                    keyboard_conf_content
+                        .push_str("\tOption \"XkbOptions\" \"grp:alt_shift_toggle\"\n");
+                }
+                keyboard_conf_content.push_str("EndSection\n");
+
+                fs::create_dir_all("/mnt/etc/X11/xorg.conf.d")
+                    .expect("Error creating /mnt/etc/X11/xorg.conf.d");
+                write_target_file(
+                    &mut question,
+                    "/mnt/etc/X11/xorg.conf.d/00-keyboard.conf",
+                    keyboard_conf_content,
+                )?;
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("hostname") => {
+                app_config.print_installation_status_and_save_config("Setting host name");
+
+                let mut previous_hostname = if app_config.reinstall {
+                    find_previous_install_report_value("Host name")
+                } else {
+                    None
+                };
+                loop {
+                    match previous_hostname.take().filter(|h| {
+                        question.bool_ask(
+                            format!("Do you want to use the previous host name ({h})?").as_str(),
+                        )
+                    }) {
+                        Some(hostname) => question.answer = hostname,
+                        None => question.ask("Enter your host name: "),
+                    }
+                    if is_valid_hostname(&question.answer) {
+                        break;
+                    }
+                    print_operation_result(OperationResult::Error);
+                    println!("Host name must be 1 to 63 characters long, contain only letters, digits and hyphens and must not start or end with a hyphen.\n");
+                }
+                app_config.hostname = question.answer.clone();
+                app_config.save_config();
+
+                write_target_file(
+                    &mut question,
+                    "/mnt/etc/hostname",
+                    format!("{}\n", app_config.hostname),
+                )?;
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("hosts") => {
+                app_config
+                    .print_installation_status_and_save_config("Setting hosts configuaration");
+
+                let mut hosts_content = String::from("127.0.0.1\tlocalhost\n::1\t\tlocalhost\n");
+                // nss-myhostname already resolves the host name, so the line is only needed by programs which read
+                // /etc/hosts by themselves.
+                if !app_config.hostname.is_empty()
+                    && question.bool_ask("Do you want to add your host name to /etc/hosts with the address 127.0.1.1? (Some programs which don't use nss-myhostname need it)")
+                {
+                    hosts_content.push_str(&format!(
+                        "127.0.1.1\t{0}.localdomain\t{0}\n",
+                        app_config.hostname
+                    ));
+                }
+                for extra_host in &customization.extra_hosts {
+                    hosts_content.push_str(&format!("{extra_host}\n"));
+                }
+                write_target_file(&mut question, "/mnt/etc/hosts", hosts_content)?;
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("root_password") => {
+                app_config.print_installation_status_and_save_config("Setting root pasword");
+
+                app_config.lock_root = question.bool_ask("Do you want to lock the root account and only use your user with sudo/doas? (Root password won't be set)");
+                app_config.save_config();
+
+                if !app_config.lock_root {
+                    set_password(&mut question, "root")?;
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("create_user") => {
+                app_config.print_installation_status_and_save_config("Creating user");
+
+                let mut previous_username = if app_config.reinstall {
+                    find_previous_install_report_value("User")
+                } else {
+                    None
+                };
+                loop {
+                    match previous_username.take().filter(|u| {
+                        question.bool_ask(
+                            format!("Do you want to create the previous user ({u}) again?")
+                                .as_str(),
+                        )
+                    }) {
+                        Some(username) => question.answer = username,
+                        None => question.ask("Enter your username: "),
+                    }
+                    if !is_valid_username(&question.answer) {
+                        print_operation_result(OperationResult::Error);
+                        println!("Username must be at most 32 characters long, start with a lowercase letter or an underscore and contain only lowercase letters, digits, underscores and hyphens. (It may end with a $)\n");
+                        continue;
+                    }
+                    app_config.username = question.answer.clone();
+                    app_config.save_config();
+
+                    // Login screens and desktops show the full name (GECOS field) instead of the username.
+                    let full_name = loop {
+                        question.ask("Enter your full name. (Leave empty to skip): ");
+                        // Fields of /etc/passwd are separated by ':' and parts of the GECOS field by ','.
+                        if question.answer.contains([':', ',']) {
+                            println!("\nError: Full name can't contain ':' or ','.\n");
+                            continue;
+                        }
+                        break question.answer.clone();
+                    };
+                    let mut useradd_arguments = vec!["/mnt", "useradd"];
+                    if !full_name.is_empty() {
+                        useradd_arguments.extend(["-c", full_name.as_str()]);
+                    }
+
+                    let existing_home_directory = if app_config.reuse_home {
+                        select_existing_home_directory(&mut question, &app_config.username)
+                    } else {
+                        None
+                    };
+                    match &existing_home_directory {
+                        Some(home_directory) => useradd_arguments.extend([
+                            "-M",
+                            "-d",
+                            home_directory.as_str(),
+                            app_config.username.as_str(),
+                        ]),
+                        None => useradd_arguments.extend(["-m", app_config.username.as_str()]),
+                    }
+                    let useradd_result = run_command("arch-chroot", Some(&useradd_arguments))
+                        .and_then(|_| match &existing_home_directory {
+                            // Files of the old user have another UID, so they are given to the new user.
+                            Some(home_directory) => run_command(
+                                "arch-chroot",
+                                Some(&[
+                                    "/mnt",
+                                    "chown",
+                                    "-R",
+                                    format!("{0}:{0}", app_config.username).as_str(),
+                                    home_directory.as_str(),
+                                ]),
+                            ),
+                            None => Ok(()),
+                        });
+
+                    if let Err(error) = useradd_result {
+                        print_operation_result(OperationResult::Error);
+                        if question.bool_ask("Do you want to enter the username again?") {
+                            continue;
+                        } else {
+                            TextManager::set_color(TextColor::Red);
+                            formatted_print("Installation failed.", PrintFormat::Bordered);
+                            return Err(error);
+                        }
+                    } else {
+                        break;
+                    }
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("user_password") => {
+                app_config.print_installation_status_and_save_config("Setting your user pasword");
+
+                set_password(&mut question, &app_config.username)?;
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("user_groups") => {
+                app_config.print_installation_status_and_save_config("Adding user to groups");
+
+                // Groups like docker or libvirt only exist when their packages have been installed.
+                let group_content = fs::read_to_string("/mnt/etc/group")
+                    .expect("Error reading from /mnt/etc/group");
+                let available_groups = SUPPLEMENTARY_GROUPS
+                    .iter()
+                    .copied()
+                    .filter(|g| {
+                        group_content
+                            .lines()
+                            .any(|l| l.split(':').next() == Some(*g))
+                    })
+                    .collect::<Vec<&str>>();
+
+                let mut groups = vec!["wheel"];
+                if !available_groups.is_empty() {
+                    for index in question.multi_selecting_ask(
+                        "Which other groups do you want to add your user to?",
+                        &available_groups,
+                    ) {
+                        groups.push(available_groups[index]);
+                    }
+                }
+
+                run_command(
+                    "arch-chroot",
+                    Some(&[
+                        "/mnt",
+                        "usermod",
+                        "-aG",
+                        groups.join(",").as_str(),
+                        app_config.username.as_str(),
+                    ]),
+                )?;
+                println!(
+                    "User {} added to: {}",
+                    app_config.username,
+                    groups.join(", ")
+                );
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("privilege_escalation") => {
+                app_config
+                    .print_installation_status_and_save_config("Configuring privilege escalation");
+
+                question.selecting_ask(
+                    "Which privilege escalation tool do you want to use?",
+                    &["sudo", "doas"],
+                );
+                app_config.use_doas = question.answer == "2";
+                app_config.save_config();
+
+                if app_config.use_doas {
+                    run_command_with_retries(
+                        "arch-chroot",
+                        Some(&["/mnt", "pacman", "-Sy", "opendoas", "--noconfirm"]),
+                        NETWORK_RETRY_POLICY,
+                    )?;
+
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/doas.conf",
+                        "permit persist :wheel\n",
+                    )?;
+                    run_command("chmod", Some(&["0400", "/mnt/etc/doas.conf"]))?;
+
+                    // Makes makepkg use doas instead of trying sudo and su.
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/makepkg.conf",
+                        fs::read_to_string("/mnt/etc/makepkg.conf")
+                            .expect("Error reading from /mnt/etc/makepkg.conf")
+                            .replace("#PACMAN_AUTH=()", "PACMAN_AUTH=(doas)"),
+                    )?;
+                } else {
+                    // A drop-in file keeps /etc/sudoers untouched and can be validated on its own.
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/sudoers.d/10-wheel",
+                        "%wheel ALL=(ALL:ALL) ALL\n",
+                    )?;
+                    run_command("chmod", Some(&["0440", "/mnt/etc/sudoers.d/10-wheel"]))?;
+
+                    if let Err(error) = run_command(
+                        "arch-chroot",
+                        Some(&["/mnt", "visudo", "-cf", "/etc/sudoers.d/10-wheel"]),
+                    ) {
+                        fs::remove_file("/mnt/etc/sudoers.d/10-wheel")
+                            .expect("Error removing /mnt/etc/sudoers.d/10-wheel");
+                        record_in_install_script("rm -f /mnt/etc/sudoers.d/10-wheel");
+                        print_operation_result(OperationResult::Error);
+                        TextManager::set_color(TextColor::Red);
+                        formatted_print("Installation failed.", PrintFormat::Bordered);
+                        return Err(error);
+                    }
+                }
+
+                if app_config.lock_root {
+                    // Root is only locked when the user is able to gain root privileges.
+                    let verification_result = if app_config.use_doas {
+                        let group_content = fs::read_to_string("/mnt/etc/group")
+                            .expect("Error reading from /mnt/etc/group");
+                        let is_wheel_member = group_content
+                            .lines()
+                            .find(|l| l.starts_with("wheel:"))
+                            .and_then(|l| l.split(':').nth(3))
+                            .is_some_and(|members| {
+                                members.split(',').any(|m| m == app_config.username)
+                            });
+
+                        if is_wheel_member {
+                            run_command(
+                                "arch-chroot",
+                                Some(&["/mnt", "doas", "-C", "/etc/doas.conf"]),
+                            )
+                        } else {
+                            Err(AppError::InternalError(format!(
+                                "Error! User {} is not a member of the wheel group.",
+                                app_config.username
+                            )))
+                        }
+                    } else {
+                        run_command(
+                            "arch-chroot",
+                            Some(&[
+                                "/mnt",
+                                "sudo",
+                                "-l",
+                                "-U",
+                                app_config.username.as_str(),
+                                "true",
+                            ]),
+                        )
+                    };
+
+                    if let Err(error) = verification_result {
+                        print_operation_result(OperationResult::Error);
+                        println!("{error}\nPrivilege escalation couldn't be verified, so the root account won't be locked. Set a root password instead.\n");
+                        set_password(&mut question, "root")?;
+                        app_config.lock_root = false;
+                        app_config.save_config();
+                    } else {
+                        run_command("arch-chroot", Some(&["/mnt", "passwd", "-l", "root"]))?;
+                        println!("Root account: Locked");
+                    }
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("grub_install") => {
+                app_config.print_installation_status_and_save_config("Installing grub");
+
+                if app_config.uefi_install {
+                    run_command_with_retries(
+                        "arch-chroot",
+                        Some(&["/mnt", "pacman", "-Sy", "efibootmgr", "--noconfirm"]),
+                        NETWORK_RETRY_POLICY,
+                    )?;
+                    let mut grub_install_arguments = vec![
+                        "/mnt",
+                        "grub-install",
+                        "--target=x86_64-efi",
+                        "--bootloader-id=grub_uefi",
+                        "--recheck",
+                    ];
+                    // A disk image is booted on another machine, so grub is installed to the fallback path and
+                    // the firmware variables of this machine are left alone.
+                    if app_config.loop_device.is_some() {
+                        grub_install_arguments.extend(["--removable", "--no-nvram"]);
+                    }
+                    run_command("arch-chroot", Some(&grub_install_arguments))?;
+                } else {
+                    // Grub goes to the disk which holds the root partition.
+                    let disk = match app_config.loop_device.clone().or_else(|| {
+                        find_parent_disk(&app_config.partition_path(&app_config.root_partition))
+                    }) {
+                        Some(disk) => {
+                            println!(
+                                "Installing grub to /dev/{disk}, the disk of the root partition."
+                            );
+                            disk
+                        }
+                        None => {
+                            question.ask("Enter your disk's name the Arch Linux has been installed to. (sda or sdb or ...): ");
+                            question.answer.clone()
+                        }
+                    };
+                    run_command(
+                        "arch-chroot",
+                        Some(&[
+                            "/mnt",
+                            "grub-install",
+                            "--target=i386-pc",
+                            format!("/dev/{}", disk).as_str(),
+                        ]),
+                    )?;
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("trim") => {
+                app_config
+                    .print_installation_status_and_save_config("Configuring TRIM if necessary");
+
+                let mut target_partitions = vec![&app_config.root_partition];
+                target_partitions.extend(app_config.boot_partition.iter());
+                target_partitions.extend(app_config.uefi_partition.iter());
+                target_partitions.extend(app_config.home_partition.iter());
+                target_partitions.extend(app_config.swap_partition.iter());
+
+                let non_rotational_partition_paths = target_partitions
+                    .into_iter()
+                    .map(|p| app_config.partition_path(p))
+                    .filter(|p| is_non_rotational(p.trim_start_matches("/dev/")))
+                    .collect::<Vec<String>>();
+
+                if non_rotational_partition_paths.is_empty() {
+                    println!("No SSD was detected among the target partitions.");
+                } else {
+                    for partition_path in &non_rotational_partition_paths {
+                        println!("{}: SSD", partition_path);
+                    }
+
+                    run_command(
+                        "arch-chroot",
+                        Some(&["/mnt", "systemctl", "enable", "fstrim.timer"]),
+                    )?;
+
+                    if app_config.encrypted_partitons {
+                        app_config.allow_discards = question.bool_ask("Do you want to allow TRIM on encrypted partitions? (Improves SSD performance but reveals which blocks are unused)");
+                        app_config.save_config();
+                    }
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("grub_config") => {
+                app_config.print_installation_status_and_save_config("Configuring grub");
+
+                let is_dual_boot =
+                    question.bool_ask("Are you installing Arch Linux alongside Windows?");
+                if is_dual_boot || app_config.probe_other_systems {
+                    let mut pacman_arguments = vec!["/mnt", "pacman", "-Sy", "os-prober"];
+                    // Windows partitions are read by os-prober through ntfs-3g.
+                    if is_dual_boot {
+                        pacman_arguments.push("ntfs-3g");
+                    }
+                    pacman_arguments.push("--noconfirm");
+                    run_command_with_retries(
+                        "arch-chroot",
+                        Some(&pacman_arguments),
+                        NETWORK_RETRY_POLICY,
+                    )?;
+
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/default/grub",
+                        fs::read_to_string("/mnt/etc/default/grub")
+                            .expect("Error reading from /mnt/etc/default/grub")
+                            .replace(
+                                "GRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=3 quiet\"",
+                                "GRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=3\"",
+                            )
+                            .replace(
+                                "#GRUB_DISABLE_OS_PROBER=false",
+                                "GRUB_DISABLE_OS_PROBER=false",
+                            ),
+                    )?;
+                } else {
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/default/grub",
+                        fs::read_to_string("/mnt/etc/default/grub")
+                            .expect("Error reading from /mnt/etc/default/grub")
+                            .replace(
+                                "GRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=3 quiet\"",
+                                "GRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=3\"",
+                            ),
+                    )?;
+                }
+
+                // Menu is shown with dual boot, so the other system can be picked. Otherwise it's hidden, but
+                // pressing Esc during the timeout still shows it.
+                let (default_timeout, default_timeout_style) = if is_dual_boot {
+                    (5, "menu")
+                } else {
+                    (3, "hidden")
+                };
+                let grub_timeout = loop {
+                    question.ask_with_default(
+                        "Enter the time in seconds until GRUB boots the default entry. (Leave empty to use {}): ",
+                        &default_timeout.to_string(),
+                    );
+                    match question.answer.parse::<u32>() {
+                        Ok(grub_timeout) => break grub_timeout,
+                        _ => println!("\nError: Enter only a number!\n"),
+                    }
+                };
+                let default_timeout_style_index = GRUB_TIMEOUT_STYLES
+                    .iter()
+                    .position(|s| s.split(' ').next() == Some(default_timeout_style))
+                    .unwrap_or_default();
+                question.selecting_ask_with_default(
+                    "How do you want the GRUB menu to be shown? (Recommended: {})",
+                    GRUB_TIMEOUT_STYLES,
+                    default_timeout_style_index,
+                    default_timeout_style,
+                );
+                let grub_timeout_style = GRUB_TIMEOUT_STYLES[question.selected_index()]
+                    .split(' ')
+                    .next()
+                    .unwrap_or(default_timeout_style);
+                let mut grub_defaults = fs::read_to_string("/mnt/etc/default/grub")
+                    .expect("Error reading from /mnt/etc/default/grub");
+                grub_defaults =
+                    set_shell_variable(&grub_defaults, "GRUB_TIMEOUT", &grub_timeout.to_string());
+                grub_defaults =
+                    set_shell_variable(&grub_defaults, "GRUB_TIMEOUT_STYLE", grub_timeout_style);
+                write_target_file(&mut question, "/mnt/etc/default/grub", grub_defaults)?;
+
+                if app_config.encrypted_partitons {
+                    let root_uuid =
+                        find_uuid(&app_config.partition_path(&app_config.root_partition))?;
+                    let cryptroot_uuid = find_uuid("/dev/mapper/cryptroot")?;
+
+                    // sd-encrypt reads rd.luks.* parameters and encrypt reads cryptdevice.
+                    let unlock_parameters = if app_config.systemd_initramfs {
+                        let mut unlocked_partitions = vec![(root_uuid.clone(), "cryptroot")];
+                        if let (true, Some(swap_partition)) = (
+                            app_config.has_persistent_swap_encryption(),
+                            &app_config.swap_partition,
+                        ) {
+                            let swap_uuid = find_uuid(&app_config.partition_path(swap_partition))?;
+                            unlocked_partitions.push((swap_uuid, "swap"));
+                        }
+                        unlocked_partitions
+                            .iter()
+                            .map(|(uuid, mapping_name)| {
+                                format!(
+                                    "rd.luks.name={}={}{}",
+                                    uuid,
+                                    mapping_name,
+                                    if app_config.allow_discards {
+                                        format!(" rd.luks.options={}=discard", uuid)
+                                    } else {
+                                        String::new()
+                                    }
+                                )
+                            })
+                            .collect::<Vec<String>>()
+                            .join(" ")
+                    } else {
+                        format!(
+                            "cryptdevice=UUID={}:cryptroot{}",
+                            root_uuid,
+                            if app_config.allow_discards {
+                                ":allow-discards"
+                            } else {
+                                ""
+                            }
+                        )
+                    };
+
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/default/grub",
+                        fs::read_to_string("/mnt/etc/default/grub")
+                            .expect("Error reading from /mnt/etc/default/grub")
+                            .replace(
+                                "GRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=3\"",
+                                format!(
+                                    "GRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=3 {} root=UUID={}\"",
+                                    unlock_parameters, cryptroot_uuid
+                                )
+                                .as_str(),
+                            ),
+                    )?;
+                }
+
+                if app_config.hibernation {
+                    if let Some(swap_partition) = &app_config.swap_partition {
+                        // Unlocked LUKS swap is resumed from its mapping, which the initramfs opens first.
+                        let resume_device = if app_config.has_persistent_swap_encryption() {
+                            String::from("/dev/mapper/swap")
+                        } else {
+                            format!(
+                                "UUID={}",
+                                find_uuid(&app_config.partition_path(swap_partition))?
+                            )
+                        };
+
+                        write_target_file(
+                            &mut question,
+                            "/mnt/etc/default/grub",
+                            fs::read_to_string("/mnt/etc/default/grub")
+                                .expect("Error reading from /mnt/etc/default/grub")
+                                .replacen(
+                                    "GRUB_CMDLINE_LINUX_DEFAULT=\"",
+                                    format!("GRUB_CMDLINE_LINUX_DEFAULT=\"resume={resume_device} ")
+                                        .as_str(),
+                                    1,
+                                ),
+                        )?;
+                    }
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("mkinitcpio") => {
+                app_config.print_installation_status_and_save_config(
+                    "Configuring and running mkinitcpio if necessary",
+                );
+
+                let has_nvidia_gpu = question.bool_ask("Do you have Nvidia GPU?");
+                let has_intel_gpu = question.bool_ask("Do you have Intel GPU?");
+
+                let mut mkinitcpio_config = MkinitcpioConfig::parse(
+                    &fs::read_to_string("/mnt/etc/mkinitcpio.conf")
+                        .expect("Error reading from /mnt/etc/mkinitcpio.conf"),
+                );
+
+                let mut modules = mkinitcpio_config.array("MODULES");
+                if has_intel_gpu {
+                    add_array_value(&mut modules, "i915", None);
+                }
+                if has_nvidia_gpu {
+                    run_command_with_retries(
+                        "arch-chroot",
+                        Some(&["/mnt", "pacman", "-Sy", "nvidia", "--noconfirm"]),
+                        NETWORK_RETRY_POLICY,
+                    )?;
+                    add_array_value(&mut modules, "nvidia", None);
+                }
+                mkinitcpio_config.set_array("MODULES", &modules);
+
+                let mut hooks = mkinitcpio_config.array("HOOKS");
+                if app_config.encrypted_partitons {
+                    hooks = create_encryption_hooks(&hooks, app_config.systemd_initramfs);
+                }
+                // The resume hook has to come after encrypt and before filesystems.
+                // (systemd based initramfs resumes without a hook)
+                if app_config.hibernation && !app_config.systemd_initramfs {
+                    add_array_value(&mut hooks, "resume", Some("filesystems"));
+                }
+                mkinitcpio_config.set_array("HOOKS", &hooks);
+
+                if mkinitcpio_config.is_changed {
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/mkinitcpio.conf",
+                        mkinitcpio_config.to_string(),
+                    )?;
+                    println!(
+                        "MODULES=({})\nHOOKS=({})\n",
+                        modules.join(" "),
+                        hooks.join(" ")
+                    );
+
+                    if let Err(error) =
+                        run_command("arch-chroot", Some(&["/mnt", "mkinitcpio", "-p", "linux"]))
+                    {
+                        if !question.bool_ask(format!("{error}. This error occured in 'mkiniticpio -p linux' command which can be expected. Given this inforamtion, do you want to continue?").as_str()) {
+                    TextManager::set_color(TextColor::Red);
+                    formatted_print("Installation failed.", PrintFormat::Bordered);
+                    return Err(error);
+                }
+                    }
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("grub_mkconfig") => {
+                app_config.print_installation_status_and_save_config("Making grub config");
 
-                question.ask("Enter the name of your prefered country for mirrors. (For example: France,Germany,...): ");
                 run_command(
-                    "reflector",
-                    Some(&[
-                        "--latest",
-                        "10",
-                        "--country",
-                        question.answer.as_str(),
-                        "--protocol",
-                        "http,https",
-                        "--sort",
-                        "rate",
-                        "--save",
-                        "/etc/pacman.d/mirrorlist",
-                    ]),
+                    "arch-chroot",
+                    Some(&["/mnt", "grub-mkconfig", "-o", "/boot/grub/grub.cfg"]),
+                )?;
+
+                // os-prober is installed only to find other systems, so missing entries mean it didn't find them.
+                if Path::new("/mnt/usr/bin/os-prober").exists() {
+                    let grub_config =
+                        fs::read_to_string("/mnt/boot/grub/grub.cfg").unwrap_or_default();
+                    let other_system_entries = find_os_prober_entries(&grub_config);
+                    let has_windows_entry =
+                        other_system_entries.iter().any(|e| e.contains("Windows"));
+                    let mut problems = Vec::new();
+                    if other_system_entries.is_empty() {
+                        problems.push("The boot menu has no entries of other systems.");
+                    } else if !has_windows_entry && find_windows_esp().is_some() {
+                        problems.push("The boot menu has no entry for Windows.");
+                    }
+                    for other_system_entry in &other_system_entries {
+                        println!("Boot menu entry was added: {other_system_entry}");
+                    }
+                    if problems.is_empty() {
+                        println!();
+                    } else {
+                        TextManager::set_color(TextColor::Yellow);
+                        formatted_print(
+                            "Warning: os-prober didn't find every system",
+                            PrintFormat::DoubleDashedLine,
+                        );
+                        TextManager::reset_color_and_graphics();
+                        for problem in problems {
+                            println!("- {problem}");
+                        }
+                        println!("- Windows may be hibernated (Fast Startup), or the partitions of a system may be encrypted or on a disk which isn't connected.");
+                        println!("- Run 'grub-mkconfig -o /boot/grub/grub.cfg' in the installed system after fixing it.\n");
+                    }
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("crypttab") => {
+                app_config
+                    .print_installation_status_and_save_config("Configuring crypttab if necessary");
+
+                if app_config.encrypted_partitons {
+                    let mut crypttab_content = fs::read_to_string("/mnt/etc/crypttab")
+                        .expect("Error reading from /mnt/etc/crypttab");
+
+                    // LUKS swap is unlocked by the initramfs, so only swap with a random key needs a line.
+                    if let (false, Some(swap_partition)) = (
+                        app_config.has_persistent_swap_encryption(),
+                        &app_config.swap_partition,
+                    ) {
+                        // The swap mapping has to start right after the small ext2 file system which holds the cryptswap label.
+                        let swap_offset =
+                            find_cryptswap_offset(&app_config.partition_path(swap_partition))?;
+
+                        crypttab_content.push_str(&format!(
+                            "swap LABEL=cryptswap /dev/urandom swap,offset={},cipher=aes-xts-plain64,size=512\n",
+                            swap_offset
+                        ));
+                    }
+
+                    if let Some(home_partition) = &app_config.home_partition {
+                        let home_path = app_config.partition_path(home_partition);
+                        let home_uuid = find_uuid(&home_path)?;
+                        // Home is unlocked with a keyfile stored on the encrypted root, so only one passphrase is asked on boot.
+                        let home_keyfile_path =
+                            create_home_keyfile(&home_path, luks_passphrase.as_deref())?;
+
+                        crypttab_content.push_str(&format!(
+                            "home UUID={} {}{}\n",
+                            home_uuid,
+                            home_keyfile_path,
+                            if app_config.allow_discards {
+                                " discard"
+                            } else {
+                                ""
+                            }
+                        ));
+                    }
+
+                    write_target_file(&mut question, "/mnt/etc/crypttab", crypttab_content)?;
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("network_manager") => {
+                app_config
+                    .print_installation_status_and_save_config("Enabling network manager service");
+
+                run_command(
+                    "arch-chroot",
+                    Some(&["/mnt", "systemctl", "enable", "NetworkManager"]),
+                )?;
+
+                let selected_options = question.multi_selecting_ask(
+                    "Which NetworkManager options do you want to use?",
+                    &[
+                        "Wi-Fi MAC address randomization (Each network sees another address)",
+                        "No connectivity check (archlinux.org isn't contacted to detect login pages of hotspots)",
+                        "iwd instead of wpa_supplicant for Wi-Fi",
+                    ],
+                );
+                if !selected_options.is_empty() {
+                    fs::create_dir_all("/mnt/etc/NetworkManager/conf.d")
+                        .expect("Error creating /mnt/etc/NetworkManager/conf.d");
+                }
+
+                if selected_options.contains(&0) {
+                    // A stable address is kept for each network, so networks which remember devices still work.
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/NetworkManager/conf.d/wifi_rand_mac.conf",
+                        "[device]\nwifi.scan-rand-mac-address=yes\n\n[connection]\nwifi.cloned-mac-address=stable\n",
+                    )?;
+                }
+
+                if selected_options.contains(&1) {
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/NetworkManager/conf.d/connectivity.conf",
+                        "[connectivity]\nenabled=false\n",
+                    )?;
+                }
+
+                if selected_options.contains(&2) {
+                    // NetworkManager starts iwd through D-Bus, so its service isn't enabled.
+                    run_command_with_retries(
+                        "arch-chroot",
+                        Some(&["/mnt", "pacman", "-S", "--needed", "--noconfirm", "iwd"]),
+                        NETWORK_RETRY_POLICY,
+                    )?;
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/NetworkManager/conf.d/wifi_backend.conf",
+                        "[device]\nwifi.backend=iwd\n",
+                    )?;
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("dns") => {
+                app_config.print_installation_status_and_save_config("Configuring DNS");
+
+                question.selecting_ask(
+                    "How do you want DNS names to be resolved?",
+                    &[
+                        "NetworkManager (Uses the DNS servers of the network)",
+                        "systemd-resolved (Caches answers and uses the DNS servers of the network)",
+                        "systemd-resolved with my own DNS servers",
+                    ],
+                );
+                let dns_mode = question.selected_index();
+
+                if dns_mode == 2 {
+                    let dns_servers = loop {
+                        question.ask("Enter your DNS servers separated by spaces. (For example: 9.9.9.9#dns.quad9.net 1.1.1.1#cloudflare-dns.com): ");
+                        if !question.answer.is_empty() {
+                            break question.answer.clone();
+                        }
+                    };
+                    // "~." makes these servers be used for every domain instead of those of the network.
+                    let mut resolved_config = format!("[Resolve]\nDNS={dns_servers}\nDomains=~.\n");
+                    if question.bool_ask("Do you want to use DNS over TLS? (The servers have to support it and need their name after a # to be verified)") {
+                        resolved_config.push_str("DNSOverTLS=yes\n");
+                    }
+                    fs::create_dir_all("/mnt/etc/systemd/resolved.conf.d")
+                        .expect("Error creating /mnt/etc/systemd/resolved.conf.d");
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/systemd/resolved.conf.d/dns_servers.conf",
+                        resolved_config,
+                    )?;
+                }
+
+                if dns_mode != 0 {
+                    run_command(
+                        "arch-chroot",
+                        Some(&["/mnt", "systemctl", "enable", "systemd-resolved"]),
+                    )?;
+                    // NetworkManager passes the DNS servers to systemd-resolved when resolv.conf points to its stub.
+                    // arch-chroot mounts the resolv.conf of the live system over it, so it's linked from outside.
+                    run_command(
+                        "ln",
+                        Some(&[
+                            "-sf",
+                            "../run/systemd/resolve/stub-resolv.conf",
+                            "/mnt/etc/resolv.conf",
+                        ]),
+                    )?;
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("maintenance_timers") => {
+                app_config.print_installation_status_and_save_config("Enabling maintenance timers");
+
+                let selected_timers = question.multi_selecting_ask(
+                    "Which maintenance timers do you want to enable?",
+                    &[
+                        "paccache.timer (Cleans the package cache weekly)",
+                        "reflector.timer (Updates the mirror list weekly)",
+                        "fwupd-refresh.timer (Refreshes firmware update metadata)",
+                    ],
+                );
+
+                if selected_timers.contains(&0) {
+                    run_command_with_retries(
+                        "arch-chroot",
+                        Some(&["/mnt", "pacman", "-Sy", "pacman-contrib", "--noconfirm"]),
+                        NETWORK_RETRY_POLICY,
+                    )?;
+                    run_command(
+                        "arch-chroot",
+                        Some(&["/mnt", "systemctl", "enable", "paccache.timer"]),
+                    )?;
+                }
+
+                if selected_timers.contains(&1) {
+                    run_command_with_retries(
+                        "arch-chroot",
+                        Some(&["/mnt", "pacman", "-Sy", "reflector", "--noconfirm"]),
+                        NETWORK_RETRY_POLICY,
+                    )?;
+
+                    // Same options as the ones used for updating mirrors of the live environment. Containers skip
+                    // the mirrors step, so they don't have a mirror country.
+                    let country_option = if app_config.mirror_country.is_empty() {
+                        String::new()
+                    } else {
+                        format!("--country {}\n", app_config.mirror_country)
+                    };
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/xdg/reflector/reflector.conf",
+                        format!(
+                            "--save /etc/pacman.d/mirrorlist\n{country_option}--protocol http,https\n--latest 10\n--sort rate\n"
+                        ),
+                    )?;
+
+                    run_command(
+                        "arch-chroot",
+                        Some(&["/mnt", "systemctl", "enable", "reflector.timer"]),
+                    )?;
+                }
+
+                if selected_timers.contains(&2) {
+                    run_command_with_retries(
+                        "arch-chroot",
+                        Some(&["/mnt", "pacman", "-Sy", "fwupd", "--noconfirm"]),
+                        NETWORK_RETRY_POLICY,
+                    )?;
+                    run_command(
+                        "arch-chroot",
+                        Some(&["/mnt", "systemctl", "enable", "fwupd-refresh.timer"]),
+                    )?;
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("system_defaults") => {
+                app_config
+                    .print_installation_status_and_save_config("Applying sensible system defaults");
+
+                if question.bool_ask("Do you want to apply sensible defaults for journald and kernel parameters? (Journal size limit and swappiness)") {
+                    fs::create_dir_all("/mnt/etc/systemd/journald.conf.d")
+                        .expect("Error creating /mnt/etc/systemd/journald.conf.d");
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/systemd/journald.conf.d/00-journal-size.conf",
+                        "[Journal]\nSystemMaxUse=500M\n",
+                    )?;
+
+                    // Swapping to compressed memory is cheap, so zram benefits from a high swappiness.
+                    let swappiness = if app_config.zram_size.is_some() { 180 } else { 10 };
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/sysctl.d/99-swappiness.conf",
+                        format!("vm.swappiness = {swappiness}\n"),
+                    )?;
+
+                    println!("Journal size limit: 500M\nSwappiness: {swappiness}");
+                }
+
+                if question.bool_ask("Do you want to enable systemd-oomd to kill processes before the system runs out of memory?") {
+                    run_command(
+                        "arch-chroot",
+                        Some(&["/mnt", "systemctl", "enable", "systemd-oomd"]),
+                    )?;
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("kde") => {
+                app_config.print_installation_status_and_save_config(
+                    "Installing KDE desktop and applications",
+                );
+
+                let mut pacman_arguments = vec!["/mnt", "pacman", "-Sy"];
+                pacman_arguments.extend(KDE_PACKAGES);
+                run_command_with_retries(
+                    "arch-chroot",
+                    Some(&pacman_arguments),
+                    NETWORK_RETRY_POLICY,
+                )?;
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("sddm") => {
+                app_config.print_installation_status_and_save_config("Enabling SDDM service");
+
+                run_command(
+                    "arch-chroot",
+                    Some(&["/mnt", "systemctl", "enable", "sddm"]),
                 )?;
 
-                print_operation_result(OperationResult::Done);
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("user_directories") => {
+                app_config.print_installation_status_and_save_config("Setting up home directories");
+
+                // Users created later get the files from /etc/skel. The new user already exists, so they are copied
+                // to its home directory too, without replacing files of a reused home.
+                if let Some(skel_directory) = &customization.skel_directory {
+                    run_command("cp", Some(&["-rT", skel_directory, "/mnt/etc/skel"]))?;
+                    run_command(
+                        "arch-chroot",
+                        Some(&[
+                            "/mnt",
+                            "sh",
+                            "-c",
+                            format!(
+                                "cp -rnT /etc/skel ~{0} && chown -R {0}: ~{0}",
+                                app_config.username
+                            )
+                            .as_str(),
+                        ]),
+                    )?;
+                    println!(
+                        "Files of {skel_directory} were added to /etc/skel and the home directory of {}.\n",
+                        app_config.username
+                    );
+                }
+
+                if question.bool_ask("Do you want to create the standard folders (Documents, Downloads, Music,...) in your home directory?") {
+                    run_command_with_retries(
+                        "arch-chroot",
+                        Some(&[
+                            "/mnt",
+                            "pacman",
+                            "-S",
+                            "--needed",
+                            "--noconfirm",
+                            "xdg-user-dirs",
+                        ]),
+                        NETWORK_RETRY_POLICY,
+                    )?;
+                    // Login shell reads the locale, so the folders are named in the language of the system.
+                    run_command(
+                        "arch-chroot",
+                        Some(&[
+                            "/mnt",
+                            "runuser",
+                            "-l",
+                            app_config.username.as_str(),
+                            "-c",
+                            "xdg-user-dirs-update",
+                        ]),
+                    )?;
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("numlock") => {
+                app_config.print_installation_status_and_save_config("Enabling numlock on boot");
+
+                let selected_places = question.multi_selecting_ask(
+                    "Where do you want numlock to be enabled on boot?",
+                    &["SDDM login screen", "Console (TTYs)"],
+                );
+
+                if selected_places.contains(&0) {
+                    fs::create_dir_all("/mnt/etc/sddm.conf.d")
+                        .expect("Error creating /mnt/etc/sddm.conf.d");
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/sddm.conf.d/numlock.conf",
+                        "[General]\nNumlock=on\n",
+                    )?;
+                }
+
+                if selected_places.contains(&1) {
+                    // Every getty turns numlock on for its own TTY before starting.
+                    fs::create_dir_all("/mnt/etc/systemd/system/getty@.service.d")
+                        .expect("Error creating /mnt/etc/systemd/system/getty@.service.d");
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/systemd/system/getty@.service.d/activate-numlock.conf",
+                        "[Service]\nExecStartPre=/bin/sh -c 'setleds -D +num < /dev/%I'\n",
+                    )?;
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("first_boot") => {
+                app_config.print_installation_status_and_save_config("Setting up first boot tasks");
+
+                let mut first_boot_commands = Vec::new();
+                if question.bool_ask("Do you want to run some commands on the first boot of the installed system? (For example: flatpak installs)") {
+                    loop {
+                        question.ask("Enter a command to run on the first boot. (Leave empty to finish): ");
+                        if question.answer.is_empty() {
+                            break;
+                        }
+                        first_boot_commands.push(question.answer.clone());
+                    }
+                }
+
+                if !first_boot_commands.is_empty() {
+                    fs::create_dir_all("/mnt/usr/local/bin")
+                        .expect("Error creating /mnt/usr/local/bin");
+                    write_target_file(
+                        &mut question,
+                        "/mnt/usr/local/bin/first-boot.sh",
+                        format!("#!/bin/sh\nset -e\n{}\n", first_boot_commands.join("\n")),
+                    )?;
+                    run_command("chmod", Some(&["+x", "/mnt/usr/local/bin/first-boot.sh"]))?;
+
+                    // The service disables itself and removes its script after the first successful run.
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/systemd/system/first-boot.service",
+                        "[Unit]\nDescription=Deferred installation tasks\nWants=network-online.target\nAfter=network-online.target\n\n[Service]\nType=oneshot\nExecStart=/usr/local/bin/first-boot.sh\nExecStartPost=/usr/bin/systemctl disable first-boot.service\nExecStartPost=/usr/bin/rm /usr/local/bin/first-boot.sh\n\n[Install]\nWantedBy=multi-user.target\n",
+                    )?;
+
+                    run_command(
+                        "arch-chroot",
+                        Some(&["/mnt", "systemctl", "enable", "first-boot.service"]),
+                    )?;
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("paru") => {
+                app_config.print_installation_status_and_save_config("Installing paru aur helper");
+
+                let privilege_escalation_command =
+                    if app_config.use_doas { "doas" } else { "sudo" };
+                let username = app_config.username.clone();
+
+                if !app_config.is_sub_step_completed("paru/clone") {
+                    run_command_with_retries(
+                        "arch-chroot",
+                        Some(&[
+                            "-u",
+                            username.as_str(),
+                            "/mnt",
+                            "git",
+                            "clone",
+                            "https://aur.archlinux.org/paru-bin.git",
+                            format!("/home/{}/paru-bin", username).as_str(),
+                        ]),
+                        NETWORK_RETRY_POLICY,
+                    )?;
+                    app_config.complete_sub_step("paru/clone");
+                }
+
+                if !app_config.is_sub_step_completed("paru/build") {
+                    write_target_file(
+                        &mut question,
+                        format!("/mnt/home/{}/makepkg.sh", username),
+                        format!("#!/bin/bash\ncd /home/{}/paru-bin\nmakepkg -si", username),
+                    )?;
+
+                    run_command(
+                        "arch-chroot",
+                        Some(&[
+                            "-u",
+                            username.as_str(),
+                            "/mnt",
+                            privilege_escalation_command,
+                            "chmod",
+                            "+x",
+                            format!("/home/{}/makepkg.sh", username).as_str(),
+                        ]),
+                    )?;
+                    run_command(
+                        "arch-chroot",
+                        Some(&[
+                            "-u",
+                            username.as_str(),
+                            "/mnt",
+                            format!("/home/{}/makepkg.sh", username).as_str(),
+                        ]),
+                    )?;
+                    app_config.complete_sub_step("paru/build");
+                }
+
+                if !app_config.is_sub_step_completed("paru/remove_build_files") {
+                    run_command(
+                        "arch-chroot",
+                        Some(&[
+                            "/mnt",
+                            "rm",
+                            format!("/home/{}/makepkg.sh", username).as_str(),
+                        ]),
+                    )?;
+
+                    run_command(
+                        "arch-chroot",
+                        Some(&[
+                            "/mnt",
+                            "rm",
+                            "-r",
+                            format!("/home/{}/paru-bin", username).as_str(),
+                        ]),
+                    )?;
+                    app_config.complete_sub_step("paru/remove_build_files");
+                }
+
+                if app_config.use_doas {
+                    write_target_file(
+                        &mut question,
+                        "/mnt/etc/paru.conf",
+                        fs::read_to_string("/mnt/etc/paru.conf")
+                            .expect("Error reading from /mnt/etc/paru.conf")
+                            .replace("#Sudo = doas", "Sudo = doas"),
+                    )?;
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("dotfiles") => {
+                app_config.print_installation_status_and_save_config("Deploying dotfiles");
+
+                if question.bool_ask("Do you want to deploy your dotfiles from a git repository?") {
+                    question.ask("Enter the URL of your dotfiles repository: ");
+                    let repository_url = question.answer.clone();
+                    let dotfiles_path = format!("/home/{}/.dotfiles", app_config.username);
+
+                    run_command_with_retries(
+                        "arch-chroot",
+                        Some(&[
+                            "-u",
+                            app_config.username.as_str(),
+                            "/mnt",
+                            "git",
+                            "clone",
+                            repository_url.as_str(),
+                            dotfiles_path.as_str(),
+                        ]),
+                        NETWORK_RETRY_POLICY,
+                    )?;
+
+                    question.selecting_ask(
+                        "How do you want to apply your dotfiles?",
+                        &["Don't apply them", "Run a bootstrap script", "Use GNU stow"],
+                    );
+                    match question.answer.as_str() {
+                        "2" => {
+                            question.ask("Enter the path of the bootstrap script inside the repository. (Leave empty to use 'install.sh'): ");
+                            let bootstrap_script = if question.answer.is_empty() {
+                                String::from("install.sh")
+                            } else {
+                                question.answer.clone()
+                            };
+
+                            run_command(
+                                "arch-chroot",
+                                Some(&[
+                                    "-u",
+                                    app_config.username.as_str(),
+                                    "/mnt",
+                                    "/bin/sh",
+                                    "-c",
+                                    format!(
+                                        "cd {} && sh {}",
+                                        quote_for_shell(&dotfiles_path),
+                                        quote_for_shell(&format!("./{bootstrap_script}"))
+                                    )
+                                    .as_str(),
+                                ]),
+                            )?;
+                        }
+                        "3" => {
+                            run_command_with_retries(
+                                "arch-chroot",
+                                Some(&["/mnt", "pacman", "-Sy", "stow", "--noconfirm"]),
+                                NETWORK_RETRY_POLICY,
+                            )?;
+
+                            // Every top level directory of the repository is a stow package.
+                            let mut stow_packages = fs::read_dir(format!("/mnt{}", dotfiles_path))?
+                                .filter_map(|e| e.ok())
+                                .filter(|e| e.path().is_dir())
+                                .map(|e| e.file_name().to_string_lossy().to_string())
+                                .filter(|n| !n.starts_with('.'))
+                                .collect::<Vec<String>>();
+                            stow_packages.sort();
+                            let stow_package_choices = stow_packages
+                                .iter()
+                                .map(|p| p.as_str())
+                                .collect::<Vec<&str>>();
+
+                            let selected_packages = question
+                                .multi_picking_ask(
+                                    "Which packages do you want to stow?",
+                                    &stow_package_choices,
+                                )
+                                .into_iter()
+                                .map(|i| stow_package_choices[i])
+                                .collect::<Vec<&str>>();
+
+                            if !selected_packages.is_empty() {
+                                let home_path = format!("/home/{}", app_config.username);
+                                let mut stow_arguments = vec![
+                                    "-u",
+                                    app_config.username.as_str(),
+                                    "/mnt",
+                                    "stow",
+                                    "-d",
+                                    dotfiles_path.as_str(),
+                                    "-t",
+                                    home_path.as_str(),
+                                ];
+                                stow_arguments.extend(selected_packages);
+
+                                run_command("arch-chroot", Some(&stow_arguments))?;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("post_install_hooks") => {
+                app_config.print_installation_status_and_save_config("Running post-install hooks");
+
+                let hook_scripts = find_hook_scripts();
+                if hook_scripts.is_empty() {
+                    println!("No executable scripts were found in the hooks.d directory.");
+                } else {
+                    fs::create_dir_all("/mnt/tmp/arch_linux_installer_hooks")
+                        .expect("Error creating /mnt/tmp/arch_linux_installer_hooks");
+                    record_in_install_script("mkdir -p /mnt/tmp/arch_linux_installer_hooks");
+
+                    // AppConfig is passed to the scripts as ALI_* environment variables.
+                    let environment_variables = app_config
+                        .environment_variables()
+                        .into_iter()
+                        .map(|(k, v)| format!("{k}={v}"))
+                        .collect::<Vec<String>>();
+
+                    for hook_script in &hook_scripts {
+                        let script_name = hook_script.file_name().unwrap().to_string_lossy();
+                        let target_script_path =
+                            format!("/tmp/arch_linux_installer_hooks/{}", script_name);
+                        fs::copy(hook_script, format!("/mnt{}", target_script_path))?;
+                        record_in_install_script(&format!(
+                            "cp {} {}",
+                            quote_for_shell(&hook_script.to_string_lossy()),
+                            quote_for_shell(&format!("/mnt{}", target_script_path))
+                        ));
+
+                        println!("Running hook: {}", script_name);
+                        let mut arguments = vec!["/mnt", "/usr/bin/env"];
+                        arguments.extend(environment_variables.iter().map(|e| e.as_str()));
+                        arguments.push(target_script_path.as_str());
+                        run_command("arch-chroot", Some(&arguments))?;
+                    }
+
+                    fs::remove_dir_all("/mnt/tmp/arch_linux_installer_hooks")
+                        .expect("Error removing /mnt/tmp/arch_linux_installer_hooks");
+                    record_in_install_script("rm -rf /mnt/tmp/arch_linux_installer_hooks");
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("verification") => {
+                app_config.print_installation_status_and_save_config("Verifying installed system");
+
+                let verification_results = verify_installation(&app_config);
+                for (check, result) in &verification_results {
+                    match result {
+                        Ok(()) => println!("[  OK  ] {check}"),
+                        Err(reason) => println!("[FAILED] {check}: {reason}"),
+                    }
+                }
+                println!();
+
+                let failed_checks_count = verification_results
+                    .iter()
+                    .filter(|(_, r)| r.is_err())
+                    .count();
+                if failed_checks_count == 0 {
+                    print_operation_result(OperationResult::Done);
+                } else {
+                    print_operation_result(OperationResult::Error);
+                    println!("{failed_checks_count} check(s) failed.\n");
+                    if !question.bool_ask(
+                        "The installed system may not boot. Do you want to finish the installation anyway?",
+                    ) {
+                        TextManager::set_color(TextColor::Red);
+                        formatted_print("Installation failed.", PrintFormat::Bordered);
+                        return Err(AppError::InternalError(String::from(
+                            "Error! Verification of the installed system failed.",
+                        )));
+                    }
+                }
+            }
+            StepEntry::BuiltIn("install_report") => {
+                app_config.print_installation_status_and_save_config("Writing installation report");
+
+                let report_path = write_install_report(&app_config)?;
+                println!("Installation report: {report_path}");
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn("unmount_partitions") => {
+                app_config.print_installation_status_and_save_config("Unmounting partition(s)");
+
+                // Counted before unmounting for the installation summary.
+                installed_packages_count = fs::read_dir("/mnt/var/lib/pacman/local")
+                    .ok()
+                    .map(|d| d.flatten().filter(|e| e.path().is_dir()).count());
+
+                if question.bool_ask(
+                    "Do you want to open a shell in the installed system to make final changes?",
+                ) {
+                    println!("Type 'exit' to leave the shell and finish the installation.\n");
+                    // Exit code of the shell is the one of the last command typed in it, so it isn't treated as a failure.
+                    let _ = run_interactive_command("arch-chroot", Some(&["/mnt"]));
+                }
+
+                if let Some(container_path) = &app_config.container_path {
+                    finish_container(container_path)?;
+                } else {
+                    if let Some(uefi_partition) = &app_config.uefi_partition {
+                        let partition_path = app_config.partition_path(uefi_partition);
+                        run_command("umount", Some(&[partition_path.as_str()]))?;
+                        println!("UEFI ({}): Unmounted", partition_path);
+                    }
+
+                    if let Some(boot_partition) = &app_config.boot_partition {
+                        let partition_path = app_config.partition_path(boot_partition);
+                        run_command("umount", Some(&[partition_path.as_str()]))?;
+                        println!("Boot ({}): Unmounted", partition_path);
+                    }
+
+                    if let Some(home_partition) = &app_config.home_partition {
+                        if app_config.encrypted_partitons {
+                            run_command("umount", Some(&["/dev/mapper/crypthome"]))?;
+                            println!("Home (/dev/mapper/crypthome): Unmounted");
+                            run_command("cryptsetup", Some(&["close", "/dev/mapper/crypthome"]))?;
+                            println!("Home (/dev/mapper/crypthome): Closed");
+                        } else {
+                            let home_path = app_config.partition_path(home_partition);
+                            run_command("umount", Some(&[home_path.as_str()]))?;
+                            println!("Home ({}): Unmounted", home_path);
+                        }
+                    }
+
+                    if app_config.has_persistent_swap_encryption() {
+                        run_command("swapoff", Some(&["/dev/mapper/swap"]))?;
+                        println!("Swap (/dev/mapper/swap): Disabled");
+                        run_command("cryptsetup", Some(&["close", "/dev/mapper/swap"]))?;
+                        println!("Swap (/dev/mapper/swap): Closed");
+                    }
+
+                    if app_config.encrypted_partitons {
+                        run_command("umount", Some(&["/dev/mapper/cryptroot"]))?;
+                        println!("Root (/dev/mapper/cryptroot): Unmounted");
+                        run_command("cryptsetup", Some(&["close", "/dev/mapper/cryptroot"]))?;
+                        println!("Root (/dev/mapper/cryptroot): Closed");
+                    } else {
+                        let root_path = app_config.partition_path(&app_config.root_partition);
+                        run_command("umount", Some(&[root_path.as_str()]))?;
+                        println!("Root ({}): Unmounted", root_path);
+                    }
+
+                    if let (Some(image_path), Some(loop_device)) =
+                        (&app_config.image_path, &app_config.loop_device)
+                    {
+                        finish_disk_image(image_path, loop_device)?;
+                    }
+                }
+
+                print_operation_result(OperationResult::Done);
+            }
+            StepEntry::BuiltIn(step_id) => {
+                panic!("Undefined step: {}", step_id);
+            }
+        }
+
+        run_step_hooks(
+            &customization.step_hooks,
+            HookTime::After,
+            step_name,
+            &app_config,
+        )?;
+        step_durations.push((step_name.to_string(), step_start_time.elapsed()));
+
+        if !app_config.completed_steps.iter().any(|s| s == step_name) {
+            app_config.completed_steps.push(step_name.to_string());
+        }
+        let sub_step_prefix = format!("{step_name}/");
+        app_config
+            .completed_sub_steps
+            .retain(|s| !s.starts_with(&sub_step_prefix));
+        app_config.save_config();
+
+        step_menu_requested = question.was_key_pressed('m');
+    }
+
+    // Printing successful installation message.
+    {
+        app_config.remove_config();
+
+        TextManager::set_color(TextColor::Green);
+        formatted_print("Installation finished successfully.", PrintFormat::Bordered);
+        TextManager::reset_color_and_graphics();
+        send_notification("Installation finished successfully.");
+
+        print_installation_summary(
+            &step_durations,
+            installation_start_time.elapsed(),
+            installed_packages_count,
+        );
+        if Path::new(INSTALL_SCRIPT_PATH).exists() {
+            println!("Commands and file changes of the installation were written to {INSTALL_SCRIPT_PATH}.\n");
+        }
+
+        if let Some(container_path) = &app_config.container_path {
+            println!(
+                "The container was packed into {}.tar.zst. It can be imported with \"machinectl import-tar\".\n",
+                container_path.trim_end_matches('/')
+            );
+        } else {
+            loop {
+                question.selecting_ask(
+                    "What do you want to do now?",
+                    &["Reboot", "Power off", "Stay in the live environment"],
+                );
+                let (end_command, countdown_message) = match question.selected_index() {
+                    0 => ("reboot", "System will restart in"),
+                    1 => ("poweroff", "System will power off in"),
+                    _ => {
+                        println!(
+                            "\nYou can reboot into the installed system whenever you are ready."
+                        );
+                        break;
+                    }
+                };
+
+                println!("\n{countdown_message}: (Press any key to cancel)\n");
+                let mut second = 5;
+                let mut canceled = false;
+                loop {
+                    if second == 0 {
+                        print!("{second}");
+                        break;
+                    }
+                    print!("{second}...");
+                    io::stdout().flush().unwrap();
+                    second -= 1;
+                    for _ in 0..4 {
+                        thread::sleep(time::Duration::from_millis(250));
+                        canceled |= !question.read_pressed_keys().is_empty();
+                    }
+                    if canceled {
+                        break;
+                    }
+                }
+                println!("\n");
+
+                if canceled {
+                    println!("Canceled.\n");
+                    continue;
+                }
+                run_command(end_command, None)?;
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Prints the duration of every step run in this session and some totals. The summary is also appended to the log.
+fn print_installation_summary(
+    step_durations: &[(String, time::Duration)],
+    total_duration: time::Duration,
+    installed_packages_count: Option<usize>,
+) {
+    let mut summary_lines = vec![String::from("Time per step:")];
+    for (step_id, step_duration) in step_durations {
+        // Padding is read out as silence by screen readers, so the columns are separated with a colon instead.
+        if TextManager::is_accessible() {
+            summary_lines.push(format!("  {step_id}: {}", format_duration(*step_duration)));
+            continue;
+        }
+        summary_lines.push(format!(
+            "  {} {}",
+            pad_to_width(step_id, 28),
+            format_duration(*step_duration)
+        ));
+    }
+    summary_lines.push(format!(
+        "Total duration: {}",
+        format_duration(total_duration)
+    ));
+    if let Some((slowest_step_id, slowest_step_duration)) =
+        step_durations.iter().max_by_key(|(_, d)| *d)
+    {
+        summary_lines.push(format!(
+            "Slowest step: {slowest_step_id} ({})",
+            format_duration(*slowest_step_duration)
+        ));
+    }
+    if let Some(installed_packages_count) = installed_packages_count {
+        summary_lines.push(format!("Installed packages: {installed_packages_count}"));
+    }
+    summary_lines.push(format!(
+        "Executed commands: {}",
+        EXECUTED_COMMANDS_COUNT.load(Ordering::SeqCst)
+    ));
+
+    TextManager::set_color(TextColor::Cyan);
+    formatted_print("Installation summary", PrintFormat::DashedLine);
+    TextManager::reset_color_and_graphics();
+    for summary_line in &summary_lines {
+        println!("{summary_line}");
+    }
+    println!();
+
+    append_to_log(&format!(
+        "Installation summary:\n{}",
+        summary_lines.join("\n")
+    ));
+}
+
+fn format_duration(duration: time::Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!(
+            "{}h {}m {}s",
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        )
+    } else if seconds >= 60 {
+        format!("{}m {}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+fn formatted_print(text: &str, format: PrintFormat) {
+    let text = translate(text);
+    let text = text.as_str();
+    let line_length = banner_line_length();
+    if TextManager::is_plain() || line_length < MIN_BANNER_LINE_LENGTH {
+        println!("\n{text}\n");
+        return;
+    }
+
+    // Room is left for the borders and the spaces around the text.
+    let text_lines = wrap_text(text, line_length - 6);
+    let empty_line = " ".repeat(line_length);
+    match format {
+        PrintFormat::Bordered => {
+            let full_line_string = "=".repeat(line_length);
+            let empty_bordered_line = format!("|{}|", " ".repeat(line_length - 2));
+
+            println!("{}", full_line_string);
+            println!("{}", empty_bordered_line);
+            for text_line in &text_lines {
+                println!("|{}|", center_text(text_line, ' ', line_length - 2));
+            }
+            println!("{}", empty_bordered_line);
+            println!("{}", full_line_string);
+        }
+        PrintFormat::DoubleDashedLine | PrintFormat::DashedLine => {
+            let fill_character = match format {
+                PrintFormat::DoubleDashedLine => '=',
+                _ => '-',
+            };
+
+            println!("{}", empty_line);
+            for text_line in &text_lines {
+                println!(
+                    "{}",
+                    center_text(&format!(" {text_line} "), fill_character, line_length)
+                );
+            }
+            println!("{}", empty_line);
+        }
+    }
+}
+
+// Banners are as wide as MAX_LINE_LENGTH, or as the terminal if it is narrower.
+fn banner_line_length() -> usize {
+    terminal_width().map_or(MAX_LINE_LENGTH, |w| w.min(MAX_LINE_LENGTH))
+}
+
+// Returns None if stdout isn't a terminal.
+fn terminal_width() -> Option<usize> {
+    let mut window_size = WindowSize::default();
+    let result = unsafe { ioctl(1, TIOCGWINSZ, &mut window_size as *mut WindowSize) };
+
+    (result == 0 && window_size.columns > 0).then_some(window_size.columns as usize)
+}
+
+// Puts the text in the middle of the line and fills both sides with the fill character.
+// Text which is longer than the line is returned as it is.
+fn center_text(text: &str, fill_character: char, line_length: usize) -> String {
+    let remaining_line_length = line_length.saturating_sub(display_width(text));
+    let left_fill_length = remaining_line_length / 2;
+
+    format!(
+        "{}{text}{}",
+        fill_character.to_string().repeat(left_fill_length),
+        fill_character
+            .to_string()
+            .repeat(remaining_line_length - left_fill_length)
+    )
+}
+
+// Number of terminal columns the text takes.
+fn display_width(text: &str) -> usize {
+    text.chars().map(character_width).sum()
+}
+
+// Combining marks and other invisible characters take no column and East Asian wide characters and emoji take two.
+// (A simplified version of the East Asian Width property of Unicode)
+fn character_width(character: char) -> usize {
+    match character as u32 {
+        0x00..=0x1F
+        | 0x7F..=0x9F
+        | 0x0300..=0x036F
+        | 0x0483..=0x0489
+        | 0x0591..=0x05BD
+        | 0x0610..=0x061A
+        | 0x064B..=0x065F
+        | 0x0670
+        | 0x06D6..=0x06DC
+        | 0x06DF..=0x06E4
+        | 0x06E7..=0x06E8
+        | 0x06EA..=0x06ED
+        | 0x1AB0..=0x1AFF
+        | 0x1DC0..=0x1DFF
+        | 0x200B..=0x200F
+        | 0x20D0..=0x20FF
+        | 0xFE00..=0xFE0F
+        | 0xFE20..=0xFE2F
+        | 0xE0100..=0xE01EF => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+// Fills the text with spaces up to the given number of columns. Format padding ("{:<28}") counts characters instead.
+fn pad_to_width(text: &str, width: usize) -> String {
+    format!(
+        "{text}{}",
+        " ".repeat(width.saturating_sub(display_width(text)))
+    )
+}
+
+// Splits the text into lines which are at most max_line_length columns wide. Words which don't fit in a line are split.
+fn wrap_text(text: &str, max_line_length: usize) -> Vec<String> {
+    let max_line_length = max_line_length.max(1);
+    let mut lines = Vec::new();
+    let mut current_line = String::new();
+
+    for word in text.split_whitespace() {
+        let mut word = word.to_string();
+        loop {
+            let separator_length = if current_line.is_empty() { 0 } else { 1 };
+            let free_length =
+                max_line_length.saturating_sub(display_width(&current_line) + separator_length);
+            if display_width(&word) <= free_length {
+                if separator_length == 1 {
+                    current_line.push(' ');
+                }
+                current_line.push_str(&word);
+                break;
+            }
+            if current_line.is_empty() {
+                // At least one character is taken, even if it is wider than the line.
+                let mut split_index = word.chars().next().map_or(0, char::len_utf8);
+                let mut split_width = 0;
+                for (index, character) in word.char_indices() {
+                    split_width += character_width(character);
+                    if split_width > max_line_length {
+                        break;
+                    }
+                    split_index = index + character.len_utf8();
+                }
+                lines.push(word[..split_index].to_string());
+                word = word[split_index..].to_string();
+            } else {
+                lines.push(std::mem::take(&mut current_line));
+            }
+        }
+    }
+    if !current_line.is_empty() || lines.is_empty() {
+        lines.push(current_line);
+    }
+
+    lines
+}
+
+// Output of the command is shown while it runs and is also written to the log. Stdin is still the terminal,
+// so the command can ask questions.
+fn run_command(command: &str, arguments: Option<&[&str]>) -> Result<(), AppError> {
+    run_command_with_timeout(command, arguments, COMMAND_TIMEOUT.get().copied())
+}
+
+// The command is stopped and treated as failed if it doesn't finish in the given time.
+fn run_command_with_timeout(
+    command: &str,
+    arguments: Option<&[&str]>,
+    timeout: Option<time::Duration>,
+) -> Result<(), AppError> {
+    log_command(command, arguments.unwrap_or_default());
+    let child = process::Command::new(command)
+        .args(arguments.unwrap_or_default())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()?;
+
+    wait_for_child_process(child, timeout)?;
+    record_command(command, arguments.unwrap_or_default(), None);
+
+    Ok(())
+}
+
+fn run_command_with_retries(
+    command: &str,
+    arguments: Option<&[&str]>,
+    retry_policy: RetryPolicy,
+) -> Result<(), AppError> {
+    let mut attempt = 1;
+    let mut delay = retry_policy.delay;
+    loop {
+        match run_command(command, arguments) {
+            Err(error) if attempt < retry_policy.attempts => {
+                print_operation_result(OperationResult::Error);
+                println!(
+                    "{error}\nTrying '{command}' again in {} seconds. (Attempt {} of {})\n",
+                    delay.as_secs(),
+                    attempt + 1,
+                    retry_policy.attempts
+                );
+                append_to_log(&format!(
+                    "Attempt {attempt} of '{command}' failed: {error}. Trying again in {} seconds.",
+                    delay.as_secs()
+                ));
+                thread::sleep(delay);
+                attempt += 1;
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Editors, shells and fdisk need the terminal itself, so their output isn't written to the log.
+// They are used by a person, so they have no timeout.
+fn run_interactive_command(command: &str, arguments: Option<&[&str]>) -> Result<(), AppError> {
+    log_command(command, arguments.unwrap_or_default());
+    let child = process::Command::new(command)
+        .args(arguments.unwrap_or_default())
+        .spawn()?;
+
+    wait_for_child_process(child, None)?;
+    record_command(command, arguments.unwrap_or_default(), None);
+
+    Ok(())
+}
+
+// Returns stdout of the command. Stderr is shown and written to the log.
+fn run_command_with_output(command: &str, arguments: &[&str]) -> Result<String, AppError> {
+    log_command(command, arguments);
+    let mut child = process::Command::new(command)
+        .args(arguments)
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()?;
+
+    let mut output = child
+        .stdout
+        .take()
+        .expect("Error opening stdout of the external process");
+    let output_reader = thread::spawn(move || {
+        let mut output_bytes = Vec::new();
+        let _ = output.read_to_end(&mut output_bytes);
+        output_bytes
+    });
+    wait_for_child_process(child, COMMAND_TIMEOUT.get().copied())?;
+
+    Ok(String::from_utf8_lossy(&output_reader.join().unwrap_or_default()).to_string())
+}
+
+fn run_command_with_input(
+    command: &str,
+    arguments: Option<&[&str]>,
+    input: &str,
+) -> Result<(), AppError> {
+    log_command(command, arguments.unwrap_or_default());
+    let mut child = process::Command::new(command)
+        .args(arguments.unwrap_or_default())
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()?;
+
+    child
+        .stdin
+        .take()
+        .expect("Error opening stdin of the external process")
+        .write_all(input.as_bytes())?;
+
+    wait_for_child_process(child, COMMAND_TIMEOUT.get().copied())?;
+    // Input is a password or a passphrase, so it isn't recorded.
+    record_command(
+        command,
+        arguments.unwrap_or_default(),
+        Some("Reads a password or a passphrase from stdin, which isn't recorded."),
+    );
+
+    Ok(())
+}
+
+// Waits for the external process while its ID is available to the signal handling thread.
+// If the installer is interrupted meanwhile, this thread stops here and the signal handling thread exits the program.
+fn wait_for_child_process(
+    mut child: process::Child,
+    timeout: Option<time::Duration>,
+) -> Result<(), AppError> {
+    RUNNING_CHILD_PROCESS_ID.store(child.id(), Ordering::SeqCst);
+    EXECUTED_COMMANDS_COUNT.fetch_add(1, Ordering::SeqCst);
+
+    let (finished_sender, finished_receiver) = mpsc::channel();
+    let mut output_copiers_count = 0;
+    if let Some(output) = child.stdout.take() {
+        spawn_output_copier(output, false, finished_sender.clone());
+        output_copiers_count += 1;
+    }
+    if let Some(output) = child.stderr.take() {
+        spawn_output_copier(output, true, finished_sender);
+        output_copiers_count += 1;
+    }
+
+    let mut timed_out = false;
+    let exit_status = match timeout {
+        Some(timeout) => {
+            let start_time = time::Instant::now();
+            loop {
+                match child.try_wait() {
+                    Ok(None) if start_time.elapsed() > timeout => {
+                        timed_out = true;
+                        stop_child_process(&mut child);
+                        break child.wait();
+                    }
+                    Ok(None) => thread::sleep(time::Duration::from_millis(100)),
+                    Ok(Some(exit_status)) => break Ok(exit_status),
+                    Err(error) => break Err(error),
+                }
+            }
+        }
+        None => child.wait(),
+    };
+    for _ in 0..output_copiers_count {
+        if finished_receiver
+            .recv_timeout(OUTPUT_DRAIN_TIMEOUT)
+            .is_err()
+        {
+            break;
+        }
+    }
+    RUNNING_CHILD_PROCESS_ID.store(0, Ordering::SeqCst);
+
+    while RECEIVED_SIGNAL.load(Ordering::SeqCst) != 0 {
+        thread::park();
+    }
+
+    if let (true, Some(timeout)) = (timed_out, timeout) {
+        return Err(AppError::ExternalError(format!(
+            "Error! External process didn't finish in {} minutes and was stopped.",
+            timeout.as_secs().div_ceil(60)
+        )));
+    }
+
+    match exit_status?.code() {
+        Some(0) => Ok(()),
+        Some(exit_code) => Err(AppError::ExternalError(format!(
+            "Error! External process exited with error code: {}",
+            exit_code
+        ))),
+        None => Err(AppError::ExternalError(String::from(
+            "Error! External process was terminated by a signal.",
+        ))),
+    }
+}
+
+// Asks the process to terminate like the interruption handling does, so it can clean up (e.g. pacman's lock file).
+// It is killed if it's still running after 10 seconds.
+fn stop_child_process(child: &mut process::Child) {
+    let _ = process::Command::new("kill")
+        .arg(child.id().to_string())
+        .status();
+    for _ in 0..100 {
+        if !matches!(child.try_wait(), Ok(None)) {
+            return;
+        }
+        thread::sleep(time::Duration::from_millis(100));
+    }
+    let _ = child.kill();
+}
+
+fn log_command(command: &str, arguments: &[&str]) {
+    let command_line = format!("{command} {}", arguments.join(" "));
+    let command_line = command_line.trim_end();
+    if Verbosity::current() >= Verbosity::Verbose {
+        TextManager::set_graphics(TextGraphics::Dim);
+        println!("$ {command_line}");
+        TextManager::reset_color_and_graphics();
+    }
+    append_to_log(&format!("Running: {command_line}"));
+}
+
+// Copies the output of an external process to the terminal as it arrives and writes its complete lines to the log.
+fn spawn_output_copier(
+    mut output: impl Read + Send + 'static,
+    is_error_output: bool,
+    finished_sender: mpsc::Sender<()>,
+) {
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        let mut unfinished_line = Vec::new();
+        let is_shown = is_error_output || Verbosity::current() != Verbosity::Quiet;
+        // Length of the start of the unfinished line which is already shown in quiet mode.
+        let mut shown_prompt_length = 0;
+        let log_line = |line: &[u8]| {
+            append_to_log(&format!(
+                "{}: {}",
+                if is_error_output { "stderr" } else { "stdout" },
+                String::from_utf8_lossy(line).trim_end()
+            ));
+        };
+
+        loop {
+            let read_bytes_count = match output.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read_bytes_count) => read_bytes_count,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            };
+            let bytes = &buffer[..read_bytes_count];
+
+            // Written without waiting for a new line, so prompts of the command are shown.
+            if is_error_output {
+                let mut stderr = io::stderr().lock();
+                let _ = stderr.write_all(bytes);
+                let _ = stderr.flush();
+            } else if is_shown {
+                let mut stdout = io::stdout().lock();
+                let _ = stdout.write_all(bytes);
+                let _ = stdout.flush();
+            }
+
+            unfinished_line.extend_from_slice(bytes);
+            while let Some(position) = unfinished_line.iter().position(|b| *b == b'\n') {
+                let line = unfinished_line.drain(..=position).collect::<Vec<u8>>();
+                log_line(&line);
+                shown_prompt_length = 0;
+            }
+
+            // Quiet mode hides the output, but an unfinished line ending like a question is waiting for an answer.
+            let is_prompt = String::from_utf8_lossy(&unfinished_line)
+                .trim_end()
+                .ends_with([':', '?', ']']);
+            if !is_shown && is_prompt && unfinished_line.len() > shown_prompt_length {
+                let mut stdout = io::stdout().lock();
+                let _ = stdout.write_all(&unfinished_line[shown_prompt_length..]);
+                let _ = stdout.flush();
+                shown_prompt_length = unfinished_line.len();
+            }
+        }
+        if !unfinished_line.is_empty() {
+            log_line(&unfinished_line);
+        }
+
+        let _ = finished_sender.send(());
+    });
+}
+
+// Appends a line with the current unix time to the log file. Logging failures are ignored.
+fn append_to_log(text: &str) {
+    let Ok(mut file) = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(LOG_FILE_PATH)
+    else {
+        return;
+    };
+    let unix_time = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    // Written at once, so lines of output copied by other threads can't be mixed into it.
+    let _ = file.write_all(format!("[{unix_time}] {text}\n").as_bytes());
+}
+
+// Appends the successfully run command to the install script, with a comment if it's given.
+// Commands which only read information aren't run with run_command, so they aren't recorded.
+fn record_command(command: &str, arguments: &[&str], comment: Option<&str>) {
+    let mut command_line = quote_for_shell(command);
+    for argument in arguments {
+        command_line.push(' ');
+        command_line.push_str(&quote_for_shell(argument));
+    }
+    if let Some(comment) = comment {
+        command_line.push_str(&format!(" # {comment}"));
+    }
+
+    record_in_install_script(&command_line);
+}
+
+// Appends the line to the install script. The script is created with a header, so it can be run as it is.
+// It's appended to like the log, so a continued installation adds to it. Failures are ignored.
+fn record_in_install_script(line: &str) {
+    let is_new_script = !Path::new(INSTALL_SCRIPT_PATH).exists();
+    let Ok(mut file) = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(INSTALL_SCRIPT_PATH)
+    else {
+        return;
+    };
+    if is_new_script {
+        let _ = file.write_all(
+            b"#!/bin/bash\n# Commands and file changes of an installation with arch_linux_installer.\n# Review it before running it on another machine: disks, partitions and passwords are specific to this one.\nset -e\n\n",
+        );
+        let _ = fs::set_permissions(INSTALL_SCRIPT_PATH, fs::Permissions::from_mode(0o700));
+    }
+    let _ = file.write_all(format!("{line}\n").as_bytes());
+}
+
+// Quotes the text with single quotes for the shell, unless it only has characters which need no quoting.
+fn quote_for_shell(text: &str) -> String {
+    if !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c))
+    {
+        return text.to_string();
+    }
+
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+// Copies the file to "<path>.<unix time>.bak" if it exists, so a bad edit can be reverted by hand.
+// A backup made in the same second isn't overwritten, so the oldest content is kept.
+fn back_up_file(path: &str) {
+    if !Path::new(path).is_file() {
+        return;
+    }
+    let unix_time = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let backup_path = format!("{path}.{unix_time}.bak");
+    if Path::new(&backup_path).exists() {
+        return;
+    }
+
+    match fs::copy(path, &backup_path) {
+        Ok(_) => append_to_log(&format!("Backed up {path} to {backup_path}")),
+        Err(error) => append_to_log(&format!("Backing up {path} failed: {error}")),
+    }
+}
+
+// Writes a file of the live environment or the installed system after backing up its current content.
+// Changes to an existing file are shown as a diff first and have to be confirmed if the installer is used interactively
+// and not in unattended mode.
+// Declining them fails with an error so the step is not marked as completed.
+fn write_target_file(
+    question: &mut Question,
+    path: impl AsRef<Path>,
+    content: impl AsRef<[u8]>,
+) -> io::Result<()> {
+    let (path, content) = (path.as_ref(), content.as_ref());
+    if let Ok(current_content) = fs::read(path) {
+        if current_content == content {
+            return Ok(());
+        }
+
+        print_file_diff(path, content)?;
+        if io::stdin().is_terminal()
+            && !question.is_unattended
+            && !question.bool_ask(
+                format!("Do you want to apply these changes to {}?", path.display()).as_str(),
+            )
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                format!(
+                    "Error! Changes to {} were declined, so this step was not completed.",
+                    path.display()
+                ),
+            ));
+        }
+    }
+
+    back_up_file(&path.to_string_lossy());
+    fs::write(path, content)?;
+    if let Some(parent_path) = path.parent() {
+        record_in_install_script(&format!(
+            "mkdir -p {}",
+            quote_for_shell(&parent_path.to_string_lossy())
+        ));
+    }
+    record_in_install_script(&format!(
+        "printf '%s' {} > {}",
+        quote_for_shell(&String::from_utf8_lossy(content)),
+        quote_for_shell(&path.to_string_lossy())
+    ));
+
+    Ok(())
+}
+
+// Prints a unified diff between the file and its new content. Added lines are green and removed lines are red.
+fn print_file_diff(path: &Path, new_content: &[u8]) -> io::Result<()> {
+    let new_content_path = env::temp_dir().join("arch_linux_installer.diff");
+    fs::write(&new_content_path, new_content)?;
+    let diff_output = process::Command::new("diff")
+        .args([
+            "--unified",
+            "--label",
+            &path.to_string_lossy(),
+            "--label",
+            &format!("{} (new)", path.display()),
+        ])
+        .arg(path)
+        .arg(&new_content_path)
+        .output();
+    let _ = fs::remove_file(&new_content_path);
+    let diff_output = String::from_utf8_lossy(&diff_output?.stdout).to_string();
+
+    println!();
+    for line in diff_output.lines() {
+        if line.starts_with("+++") || line.starts_with("---") {
+            TextManager::set_graphics(TextGraphics::Bold);
+        } else if line.starts_with('+') {
+            TextManager::set_color(TextColor::Green);
+        } else if line.starts_with('-') {
+            TextManager::set_color(TextColor::Red);
+        } else if line.starts_with("@@") {
+            TextManager::set_color(TextColor::Cyan);
+        }
+        print!("{line}");
+        TextManager::reset_color_and_graphics();
+        println!();
+    }
+    println!();
+
+    Ok(())
+}
+
+// Downloads a small file from every mirror of the mirrorlist and writes the mirrors back sorted by the measured speed.
+// Mirrors which failed are kept at the end.
+fn sort_mirrors_by_speed(question: &mut Question) -> Result<(), AppError> {
+    let mirrorlist = fs::read_to_string("/etc/pacman.d/mirrorlist")?;
+    let servers = mirrorlist
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix("Server"))
+        .filter_map(|l| l.trim_start().strip_prefix('='))
+        .map(|l| l.trim().to_string())
+        .collect::<Vec<String>>();
+    if servers.is_empty() {
+        println!("No mirrors were found in /etc/pacman.d/mirrorlist.\n");
+        return Ok(());
+    }
+
+    let mut measured_servers = Vec::new();
+    for server in servers {
+        let test_url = format!(
+            "{}/{MIRROR_TEST_FILE}",
+            server
+                .split("$repo")
+                .next()
+                .unwrap_or_default()
+                .trim_end_matches('/')
+        );
+        // curl prints the average speed in bytes per second, which is 0 if the download failed.
+        let speed = process::Command::new("curl")
+            .args([
+                "--silent",
+                "--fail",
+                "--output",
+                "/dev/null",
+                "--max-time",
+                MIRROR_TEST_TIMEOUT_SECONDS,
+                "--write-out",
+                "%{speed_download}",
+                test_url.as_str(),
+            ])
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .and_then(|o| {
+                String::from_utf8_lossy(&o.stdout)
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+            })
+            .unwrap_or_default() as u64;
+        if speed == 0 {
+            println!("{:>12}  {server}", "Failed");
+        } else {
+            println!("{:>12}  {server}", format!("{}/s", format_size(speed)));
+        }
+        measured_servers.push((speed, server));
+    }
+    println!();
+    measured_servers.sort_by_key(|(speed, _)| std::cmp::Reverse(*speed));
+
+    let sorted_mirrorlist = measured_servers.iter().fold(
+        String::from("# Sorted by download speed with arch_linux_installer\n"),
+        |mirrorlist, (_, server)| mirrorlist + &format!("Server = {server}\n"),
+    );
+    write_target_file(question, "/etc/pacman.d/mirrorlist", sorted_mirrorlist)?;
+
+    Ok(())
+}
+
+// Posts the message to the notification URL with curl. (ntfy.sh topics and most webhooks accept a plain text body)
+// Notifications are best effort, so failures are ignored.
+fn send_notification(message: &str) {
+    let Some(notification_url) = NOTIFICATION_URL.get() else {
+        return;
+    };
+
+    let _ = process::Command::new("curl")
+        .args([
+            "--silent",
+            "--max-time",
+            "10",
+            "--header",
+            "Title: Arch Linux installer",
+            "--data",
+            message,
+            notification_url,
+        ])
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .status();
+}
+
+// Replaces the raw panic output with the failed step and recovery guidance.
+// Panic message and the saved installer state are appended to the log.
+fn install_panic_hook() {
+    panic::set_hook(Box::new(|panic_info| {
+        let _ = process::Command::new("stty")
+            .args(["echo", "icanon"])
+            .stderr(process::Stdio::null())
+            .status();
+
+        let current_step_id = CURRENT_STEP_ID
+            .lock()
+            .map(|s| s.clone())
+            .unwrap_or_default();
+        append_to_log(&format!(
+            "Installer crashed during step '{current_step_id}': {panic_info}"
+        ));
+        if let Ok(saved_config) = fs::read_to_string(CONFIG_FILE_PATH) {
+            append_to_log(&format!("Saved installer state:\n{saved_config}"));
+        }
+        send_notification(&format!(
+            "Installer crashed during step '{current_step_id}'"
+        ));
+
+        println!();
+        TextManager::set_color(TextColor::Red);
+        formatted_print("Installer crashed", PrintFormat::Bordered);
+        TextManager::reset_color_and_graphics();
+        if !current_step_id.is_empty() {
+            println!("Step: {current_step_id}");
+        }
+        println!("Reason: {panic_info}\n");
+        println!("Details were written to {LOG_FILE_PATH}.");
+        println!(
+            "Installer state is saved, so run the installer again to continue from this step."
+        );
+        println!("Run 'arch_linux_installer cleanup' first to unmount and close the partitions.");
+    }));
+}
+
+// Installs SIGINT and SIGTERM handlers and starts a thread that gracefully stops the installation when one is received:
+// Running external command is terminated, terminal settings are restored, partitions are cleaned up (unless disabled)
+// and the program exits with 128 + signal number. Installer state is already saved after every answer and step.
+fn watch_signals(no_cleanup: bool) {
+    unsafe {
+        signal(SIGINT, handle_signal);
+        signal(SIGTERM, handle_signal);
+    }
+
+    thread::spawn(move || {
+        let received_signal = loop {
+            let received_signal = RECEIVED_SIGNAL.load(Ordering::SeqCst);
+            if received_signal != 0 {
+                break received_signal;
+            }
+            thread::sleep(time::Duration::from_millis(100));
+        };
+
+        let running_child_process_id = RUNNING_CHILD_PROCESS_ID.load(Ordering::SeqCst);
+        if running_child_process_id != 0 {
+            let _ = process::Command::new("kill")
+                .arg(running_child_process_id.to_string())
+                .status();
+            for _ in 0..100 {
+                if RUNNING_CHILD_PROCESS_ID.load(Ordering::SeqCst) == 0 {
+                    break;
+                }
+                thread::sleep(time::Duration::from_millis(100));
+            }
+        }
+        let _ = process::Command::new("stty")
+            .args(["echo", "icanon"])
+            .stderr(process::Stdio::null())
+            .status();
+
+        println!();
+        TextManager::set_color(TextColor::Yellow);
+        formatted_print("Installation interrupted", PrintFormat::Bordered);
+        TextManager::reset_color_and_graphics();
+
+        if !no_cleanup {
+            if let Err(error) = cleanup_installation() {
+                println!("Cleanup failed: {error}\n");
+            }
+        }
+
+        let mut app_config = AppConfig::new(0);
+        if app_config.load_config().is_ok() {
+            println!(
+                "Run the installer again to continue from step ({}/{}).",
+                app_config.completed_steps.len() + 1,
+                app_config.total_installation_steps
+            );
+        } else {
+            println!("Run the installer again to start the installation.");
+        }
+
+        process::exit(128 + received_signal);
+    });
+}
+
+// Password is read by the installer itself and applied with chpasswd, so passwd's interactive prompt isn't needed.
+fn set_password(question: &mut Question, username: &str) -> Result<(), AppError> {
+    loop {
+        let password =
+            question.password_ask(format!("Enter the password of {username}: ").as_str());
+
+        if let Err(error) = run_command_with_input(
+            "arch-chroot",
+            Some(&["/mnt", "chpasswd"]),
+            format!("{username}:{password}\n").as_str(),
+        ) {
+            print_operation_result(OperationResult::Error);
+            if !question.bool_ask(
+                format!("Do you want to enter the password of {username} again?").as_str(),
+            ) {
+                TextManager::set_color(TextColor::Red);
+                formatted_print("Installation failed.", PrintFormat::Bordered);
+                return Err(error);
+            }
+        } else {
+            return Ok(());
+        }
+    }
+}
+
+fn estimate_password_strength(password: &str) -> PasswordStrength {
+    let character_classes = [
+        password.chars().any(|c| c.is_lowercase()),
+        password.chars().any(|c| c.is_uppercase()),
+        password.chars().any(|c| c.is_ascii_digit()),
+        password.chars().any(|c| !c.is_alphanumeric()),
+    ]
+    .iter()
+    .filter(|c| **c)
+    .count();
+
+    match (password.chars().count(), character_classes) {
+        (0..=7, _) | (8..=11, 0..=1) => PasswordStrength::Weak,
+        (8..=11, _) | (12.., 0..=2) => PasswordStrength::Medium,
+        _ => PasswordStrength::Strong,
+    }
+}
+
+fn print_operation_result(operation_result: OperationResult) {
+    match operation_result {
+        OperationResult::Done => {
+            if Verbosity::current() == Verbosity::Quiet {
+                return;
+            }
+            TextManager::set_color(TextColor::Green);
+            formatted_print("Done", PrintFormat::DashedLine);
+        }
+        OperationResult::Error => {
+            TextManager::set_color(TextColor::Red);
+            formatted_print("Error", PrintFormat::DashedLine);
+        }
+    }
+    TextManager::reset_color_and_graphics();
+}
+
+// Queries only the given device with "blkid -o export", so similar names (sda1 and sda10) can't be mixed up.
+// All fields are None if blkid doesn't know the device or it has no signature.
+fn query_block_device(device_path: &str) -> BlockDeviceInfo {
+    let mut block_device_info = BlockDeviceInfo::default();
+    let Ok(output) = process::Command::new("blkid")
+        .args(["-o", "export", device_path])
+        .output()
+    else {
+        return block_device_info;
+    };
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        // Special characters in values are escaped with a backslash.
+        let value = Some(value.replace('\\', ""));
+        match key {
+            "UUID" => block_device_info.uuid = value,
+            "PARTUUID" => block_device_info.partuuid = value,
+            "TYPE" => block_device_info.file_system_type = value,
+            "LABEL" => block_device_info.label = value,
+            _ => {}
+        }
+    }
+
+    block_device_info
+}
+
+fn find_uuid(device_path: &str) -> Result<String, AppError> {
+    query_block_device(device_path).uuid.ok_or_else(|| {
+        AppError::ExternalError(format!(
+            "Error! UUID of {device_path} wasn't found by blkid."
+        ))
+    })
+}
+
+// Host name rules of RFC 1123 for a single label. (Letters, digits and hyphens, at most 63 characters)
+fn is_valid_hostname(hostname: &str) -> bool {
+    !hostname.is_empty()
+        && hostname.len() <= 63
+        && !hostname.starts_with('-')
+        && !hostname.ends_with('-')
+        && hostname
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+// Default user name rules of useradd. ([a-z_][a-z0-9_-]*[$]? with at most 32 characters)
+fn is_valid_username(username: &str) -> bool {
+    let name = username.strip_suffix('$').unwrap_or(username);
+
+    !name.is_empty()
+        && username.len() <= 32
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+// Adds the encryption hook before filesystems. The systemd variant replaces udev, keymap and consolefont too.
+fn create_encryption_hooks(hooks: &[String], systemd_initramfs: bool) -> Vec<String> {
+    let mut new_hooks: Vec<String> = Vec::new();
+    for hook in hooks
+        .iter()
+        .filter(|h| !["encrypt", "sd-encrypt"].contains(&h.as_str()))
+    {
+        match (hook.as_str(), systemd_initramfs) {
+            ("udev", true) => add_array_value(&mut new_hooks, "systemd", None),
+            ("keymap", true) | ("consolefont", true) => {
+                add_array_value(&mut new_hooks, "sd-vconsole", None)
+            }
+            _ => add_array_value(&mut new_hooks, hook, None),
+        }
+    }
+    add_array_value(
+        &mut new_hooks,
+        if systemd_initramfs {
+            "sd-encrypt"
+        } else {
+            "encrypt"
+        },
+        Some("filesystems"),
+    );
+
+    new_hooks
+}
+
+// Options used for both the live environment and the installed system.
+fn set_pacman_options(pacman_config: &mut PacmanConfig) {
+    pacman_config.set_option("options", "Color", None);
+    pacman_config.set_option("options", "VerbosePkgLists", None);
+    pacman_config.set_option("options", "ParallelDownloads", Some("5"));
+    pacman_config.set_option("options", "ILoveCandy", None);
+}
+
+// Sets a variable of a shell style config file like /etc/default/grub. A commented out assignment is replaced, so
+// the variable stays in its place, and a missing one is added at the end.
+fn set_shell_variable(content: &str, name: &str, value: &str) -> String {
+    let assignment = format!("{name}={value}");
+    let mut is_set = false;
+    let mut lines = content
+        .lines()
+        .map(|line| {
+            let is_assignment = line
+                .trim_start_matches('#')
+                .strip_prefix(name)
+                .is_some_and(|rest| rest.starts_with('='));
+            if is_assignment && !is_set {
+                is_set = true;
+                assignment.clone()
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<String>>();
+    if !is_set {
+        lines.push(assignment);
+    }
+
+    lines.join("\n") + "\n"
+}
+
+// Returns the profile name of a btrfs profile choice and how many devices it needs.
+fn find_btrfs_profile(choice: &str) -> (&str, usize) {
+    let profile = choice.split(' ').next().unwrap_or_default();
+    let min_devices_count = if profile == "raid10" { 4 } else { 2 };
+
+    (profile, min_devices_count)
+}
+
+// Adds the value before the given one (or at the end) if it isn't in the array yet.
+fn add_array_value(array: &mut Vec<String>, value: &str, before: Option<&str>) {
+    if array.iter().any(|v| v == value) {
+        return;
+    }
+    let position = before
+        .and_then(|b| array.iter().position(|v| v == b))
+        .unwrap_or(array.len());
+    array.insert(position, value.to_string());
+}
+
+// Returns commented out locale entries of locale.gen. (For example: "en_US.UTF-8 UTF-8")
+fn find_available_locales(locale_gen_content: &str) -> Vec<&str> {
+    locale_gen_content
+        .lines()
+        .filter_map(|l| l.strip_prefix('#'))
+        .filter(|l| !l.is_empty() && !l.starts_with(char::is_whitespace) && l.contains(' '))
+        .collect()
+}
+
+// Parses "--from-step <id>", "--only <id>" and "--skip <id>". "--only" and "--skip" can be repeated or take comma separated IDs.
+// Returns None if usage help was requested.
+fn parse_command_line_arguments() -> Result<Option<CommandLineArguments>, AppError> {
+    let mut command_line_arguments = CommandLineArguments {
+        cleanup: false,
+        no_cleanup: false,
+        plain: false,
+        no_color: false,
+        from_step: None,
+        only_steps: Vec::new(),
+        skip_steps: Vec::new(),
+        image_path: None,
+        image_size: None,
+        container_path: None,
+        reinstall: false,
+        verbosity: Verbosity::Normal,
+        language: None,
+        accessible: false,
+        large_font: false,
+        validate: false,
+        plan: false,
+        json: false,
+        config_path: None,
+        answers_path: None,
+        auto: find_kernel_parameter("ali.auto").is_some(),
+        clone: false,
+        cloned_root_path: None,
+    };
+
+    let mut arguments = env::args().skip(1);
+    while let Some(argument) = arguments.next() {
+        if argument == "-h" || argument == "--help" {
+            return Ok(None);
+        }
+        if argument == "cleanup" {
+            command_line_arguments.cleanup = true;
+            continue;
+        }
+        if argument == "validate" {
+            command_line_arguments.validate = true;
+            continue;
+        }
+        if argument == "plan" {
+            command_line_arguments.plan = true;
+            continue;
+        }
+        if argument == "clone" {
+            command_line_arguments.clone = true;
+            continue;
+        }
+        if command_line_arguments.clone
+            && command_line_arguments.cloned_root_path.is_none()
+            && !argument.starts_with('-')
+        {
+            command_line_arguments.cloned_root_path = Some(argument);
+            continue;
+        }
+        if (command_line_arguments.validate || command_line_arguments.plan)
+            && command_line_arguments.config_path.is_none()
+            && !argument.starts_with('-')
+        {
+            command_line_arguments.config_path = Some(argument);
+            continue;
+        }
+        if argument == "--json" && command_line_arguments.plan {
+            command_line_arguments.json = true;
+            continue;
+        }
+        if argument == "--no-cleanup" {
+            command_line_arguments.no_cleanup = true;
+            continue;
+        }
+        if argument == "--plain" {
+            command_line_arguments.plain = true;
+            continue;
+        }
+        if argument == "--no-color" {
+            command_line_arguments.no_color = true;
+            continue;
+        }
+        if argument == "--reinstall" {
+            command_line_arguments.reinstall = true;
+            continue;
+        }
+        if argument == "--auto" {
+            command_line_arguments.auto = true;
+            continue;
+        }
+        if argument == "--accessible" {
+            command_line_arguments.accessible = true;
+            continue;
+        }
+        if argument == "--large-font" {
+            command_line_arguments.large_font = true;
+            continue;
+        }
+        let verbosity = match argument.as_str() {
+            "--quiet" => Some(Verbosity::Quiet),
+            "--verbose" => Some(Verbosity::Verbose),
+            "--debug" => Some(Verbosity::Debug),
+            _ => None,
+        };
+        if let Some(verbosity) = verbosity {
+            command_line_arguments.verbosity = verbosity;
+            continue;
+        }
+        if [
+            "--image",
+            "--image-size",
+            "--container",
+            "--language",
+            "--answers",
+        ]
+        .contains(&argument.as_str())
+        {
+            let value = arguments.next().ok_or_else(|| {
+                AppError::InternalError(format!("Error! Missing value after {argument}"))
+            })?;
+            match argument.as_str() {
+                "--image" => command_line_arguments.image_path = Some(value),
+                "--image-size" => command_line_arguments.image_size = Some(value),
+                "--container" => command_line_arguments.container_path = Some(value),
+                "--answers" => command_line_arguments.answers_path = Some(value),
+                _ => command_line_arguments.language = Some(value),
+            }
+            continue;
+        }
+
+        let step_ids = match argument.as_str() {
+            "--from-step" | "--only" | "--skip" => arguments.next().ok_or_else(|| {
+                AppError::InternalError(format!("Error! Missing step ID after {argument}"))
+            })?,
+            _ => {
+                return Err(AppError::InternalError(format!(
+                    "Error! Unknown argument: {argument}. Use --help to see the available options."
+                )));
+            }
+        };
+        let mut step_ids = step_ids
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty());
+
+        match argument.as_str() {
+            "--from-step" => command_line_arguments.from_step = step_ids.next_back(),
+            "--only" => command_line_arguments.only_steps.extend(step_ids),
+            _ => command_line_arguments.skip_steps.extend(step_ids),
+        }
+    }
+
+    Ok(Some(command_line_arguments))
+}
+
+fn print_usage() {
+    println!("Usage: arch_linux_installer [COMMAND] [OPTIONS]\n");
+    println!("Commands:");
+    println!("  cleanup           Unmount /mnt, disable the swap partition and close encrypted partitions");
+    println!("  validate [path]   Check a config file against this machine without changing anything (Default: {CONFIG_FILE_PATH})");
+    println!("  plan [path]       Print the steps, partitions, settings and packages of an installation with a config file (no commands of built-in steps)");
+    println!("                    (Add --json to print it as JSON)");
+    println!("  clone [root]      Write an answers file and a custom step which approximate the Arch Linux system at root (Default: /)\n");
+    println!("Options:");
+    println!("  --from-step <id>  Start from the given step and run all the steps after it");
+    println!("  --only <id>       Run only the given steps (can be repeated or comma separated)");
+    println!("  --skip <id>       Skip the given steps (can be repeated or comma separated)");
+    println!("  --no-cleanup      Leave partitions mounted and opened if the installation fails or is interrupted");
+    println!("  --plain           Print without colors and banners (Default if the output isn't a terminal)");
+    println!("  --no-color        Print without colors (Also enabled by the NO_COLOR environment variable)");
+    println!("  --accessible      Start the espeakup screen reader and print plain sentences without banners");
+    println!("  --large-font      Use the large console font {LARGE_CONSOLE_FONT} now and in the installed system");
+    println!("  --quiet           Print only step banners, errors and questions");
+    println!("  --verbose         Print every external command before running it");
+    println!(
+        "  --debug           Like --verbose and print every change of the saved installer state"
+    );
+    println!(
+        "  --reinstall       Format only the root partition and keep the UEFI and home partitions"
+    );
+    println!("  --image <path>    Install into a disk image file instead of a real disk (.raw, .img or .qcow2)");
+    println!("  --image-size <n>  Size of the disk image if it doesn't exist yet (Default: {DEFAULT_IMAGE_SIZE})");
+    println!("  --container <dir> Install into a directory without partitions and a boot loader and pack it into <dir>.tar.zst");
+    println!(
+        "  --language <code> Language of the installer: en, de or fa (Default: Taken from LANG)"
+    );
+    println!("  --answers <path>  Use the answers of an answers file (a path or an HTTP(S) URL) instead of asking");
+    println!("                    (Default: ali.answers=<path or URL> on the kernel command line)");
+    println!("  --auto            Install without asking anything and fail on questions the answers file doesn't answer");
+    println!("                    (Also enabled by ali.auto on the kernel command line)");
+    println!("  -h, --help        Print this help\n");
+    println!("Step IDs:");
+    for (step_id, _) in INSTALLATION_STEPS {
+        println!("  {step_id}");
+    }
+}
+
+// Checks that the selected steps exist and returns the index of the step to start from if there is one.
+fn find_from_step_index(
+    command_line_arguments: &CommandLineArguments,
+    step_order: &[StepEntry],
+    customization: &Customization,
+) -> Result<Option<usize>, AppError> {
+    let step_index = |step_id: &str| {
+        step_order
+            .iter()
+            .position(|e| step_entry_name(e, &customization.custom_steps) == step_id)
+            .ok_or_else(|| {
+                AppError::InternalError(format!(
+                    "Error! Unknown step ID: {step_id}. Use --help to see the available step IDs."
+                ))
+            })
+    };
+
+    for step_id in command_line_arguments
+        .only_steps
+        .iter()
+        .chain(command_line_arguments.skip_steps.iter())
+    {
+        step_index(step_id)?;
+    }
+
+    match &command_line_arguments.from_step {
+        Some(from_step) => step_index(from_step).map(Some),
+        None => Ok(None),
+    }
+}
+
+// Reads a value of the [System] section from the installation report of the previous installation on the home partition.
+fn find_previous_install_report_value(key: &str) -> Option<String> {
+    fs::read_dir("/mnt/home")
+        .ok()?
+        .flatten()
+        .filter_map(|e| fs::read_to_string(e.path().join("install-report.txt")).ok())
+        .find_map(|report| {
+            report
+                .lines()
+                .take_while(|l| *l != "[Partitions]")
+                .find_map(|l| l.strip_prefix(format!("{key}: ").as_str()))
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        })
+}
+
+// Lets the user pick a home directory of the reused home partition. Returns its path inside the installed system.
+// The directory with the same name as the user is used without asking.
+fn select_existing_home_directory(question: &mut Question, username: &str) -> Option<String> {
+    let mut home_directories = fs::read_dir("/mnt/home")
+        .ok()?
+        .flatten()
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|n| n != "lost+found")
+        .collect::<Vec<String>>();
+    home_directories.sort();
+
+    if home_directories.iter().any(|d| d == username) {
+        println!("Existing home directory /home/{username} will be used.");
+        return Some(format!("/home/{username}"));
+    }
+    if home_directories.is_empty() {
+        return None;
+    }
+
+    let mut choices = home_directories
+        .iter()
+        .map(|d| d.as_str())
+        .collect::<Vec<&str>>();
+    choices.push("Create a new home directory");
+    question.selecting_ask(
+        "Which existing home directory do you want to use for your user?",
+        &choices,
+    );
+
+    home_directories
+        .get(question.selected_index())
+        .map(|d| format!("/home/{d}"))
+}
+
+// Returns the name of the disk a partition belongs to. (/dev/sda1 -> sda)
+fn find_parent_disk(partition_path: &str) -> Option<String> {
+    let output = process::Command::new("lsblk")
+        .args(["-no", "PKNAME", partition_path])
+        .output()
+        .ok()?;
+    let disk_name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    if disk_name.is_empty() {
+        None
+    } else {
+        Some(disk_name)
+    }
+}
+
+// Warns about a failing disk with the details smartctl reports. Installing continues only if the user accepts the risk.
+// Disks without SMART data (virtual disks, most USB adapters) are installed to without asking.
+fn check_disk_health(question: &mut Question, disk_name: &str) -> Result<(), AppError> {
+    let disk_path = format!("/dev/{disk_name}");
+    // Exit code of smartctl is a bit mask which is also set for old errors, so only its output is used.
+    let Ok(output) = process::Command::new("smartctl")
+        .args(["-H", "-A", "-l", "selftest", disk_path.as_str()])
+        .output()
+    else {
+        println!("smartctl isn't available, so the health of {disk_path} isn't checked.\n");
+        return Ok(());
+    };
+    let smartctl_output = String::from_utf8_lossy(&output.stdout);
+    append_to_log(&format!(
+        "smartctl output of {disk_path}:\n{smartctl_output}"
+    ));
+
+    if !smartctl_output.contains("self-assessment test result") {
+        println!("SMART data of {disk_path} isn't available, so its health isn't checked.\n");
+        return Ok(());
+    }
+    let health_problems = find_disk_health_problems(&smartctl_output);
+    if health_problems.is_empty() {
+        println!("SMART health check of {disk_path} passed.\n");
+        return Ok(());
+    }
+
+    TextManager::set_color(TextColor::Yellow);
+    formatted_print(
+        &format!("Warning: {disk_path} may be failing"),
+        PrintFormat::DoubleDashedLine,
+    );
+    TextManager::reset_color_and_graphics();
+    for health_problem in &health_problems {
+        println!("- {health_problem}");
+    }
+    println!();
+    if question.bool_ask(&format!(
+        "Do you want to install on {disk_path} anyway? (Its data may be lost at any time)"
+    )) {
+        return Ok(());
+    }
+
+    Err(AppError::InternalError(format!(
+        "Error! Installation was canceled because {disk_path} failed its SMART health check."
+    )))
+}
+
+// Offers a badblocks scan of spinning disks, which are the ones wearing out with bad sectors. The read-only scan
+// keeps the data. The write test is more thorough but erases the whole disk, so it's confirmed separately.
+// Scanning takes hours on large disks, so it runs without the command timeout and shows its own progress.
+fn scan_for_bad_blocks(question: &mut Question, disk_name: &str) -> Result<(), AppError> {
+    let is_rotational = fs::read_to_string(format!("/sys/block/{disk_name}/queue/rotational"))
+        .is_ok_and(|r| r.trim() == "1");
+    if !is_rotational
+        || !question.bool_ask(&format!(
+            "Do you want to scan /dev/{disk_name} for bad blocks? (Takes a long time on large disks)"
+        ))
+    {
+        return Ok(());
+    }
+
+    let disk_path = format!("/dev/{disk_name}");
+    question.selecting_ask(
+        "Which kind of scan do you want?",
+        &[
+            "Read-only scan (Keeps the data)",
+            "Write test (Erases the whole disk)",
+        ],
+    );
+    let is_write_test = question.selected_index() == 1
+        && question.bool_ask(&format!(
+            "All data on {disk_path} will be erased. Are you sure you want to run the write test?"
+        ));
+
+    let bad_blocks_file_path = format!("/tmp/badblocks_{disk_name}.txt");
+    let mut badblocks_arguments = vec![
+        "-s",
+        "-v",
+        "-b",
+        "4096",
+        "-o",
+        bad_blocks_file_path.as_str(),
+    ];
+    if is_write_test {
+        badblocks_arguments.push("-w");
+    }
+    badblocks_arguments.push(disk_path.as_str());
+    run_interactive_command("badblocks", Some(&badblocks_arguments))?;
+
+    let bad_blocks_count = fs::read_to_string(&bad_blocks_file_path)
+        .map(|c| c.lines().filter(|l| !l.trim().is_empty()).count())
+        .unwrap_or_default();
+    if bad_blocks_count == 0 {
+        println!("\nNo bad blocks were found on {disk_path}.\n");
+        return Ok(());
+    }
+
+    TextManager::set_color(TextColor::Yellow);
+    formatted_print(
+        &format!("Warning: {bad_blocks_count} bad block(s) were found on {disk_path}"),
+        PrintFormat::DoubleDashedLine,
+    );
+    TextManager::reset_color_and_graphics();
+    println!("The list of bad blocks was saved to {bad_blocks_file_path}.\n");
+    if question.bool_ask(&format!(
+        "Do you want to install on {disk_path} anyway? (Its data may be lost at any time)"
+    )) {
+        return Ok(());
+    }
+
+    Err(AppError::InternalError(format!(
+        "Error! Installation was canceled because bad blocks were found on {disk_path}."
+    )))
+}
+
+// Finds the problems in the output of "smartctl -H -A -l selftest" for ATA and NVMe disks: A failed overall
+// health assessment, reallocated, pending or uncorrectable sectors, failed attributes, media errors, critical
+// warnings and failed self-tests.
+fn find_disk_health_problems(smartctl_output: &str) -> Vec<String> {
+    const SECTOR_ATTRIBUTES: &[(&str, &str)] = &[
+        ("Reallocated_Sector_Ct", "reallocated sectors"),
+        ("Current_Pending_Sector", "pending sectors"),
+        ("Offline_Uncorrectable", "uncorrectable sectors"),
+        ("Reported_Uncorrect", "reported uncorrectable errors"),
+    ];
+    let mut health_problems = Vec::new();
+
+    for line in smartctl_output.lines() {
+        let words = line.split_whitespace().collect::<Vec<&str>>();
+        if let Some(result) = line.split("self-assessment test result:").nth(1) {
+            if result.trim() != "PASSED" {
+                health_problems.push(format!("Overall health assessment: {}", result.trim()));
             }
-            10 => {
-                app_config.print_installation_status_and_save_config("Configuring pacman");
+        } else if let Some(value) = line.strip_prefix("Media and Data Integrity Errors:") {
+            if value.trim() != "0" {
+                health_problems.push(format!("Media and data integrity errors: {}", value.trim()));
+            }
+        } else if let Some(value) = line.strip_prefix("Critical Warning:") {
+            if value.trim() != "0x00" {
+                health_problems.push(format!("Critical warning: {}", value.trim()));
+            }
+        } else if line.starts_with('#') && line.to_lowercase().contains("fail") {
+            // Self-test log: "# 1  Extended offline    Completed: read failure       90%      1234         5678"
+            health_problems.push(format!(
+                "Failed self-test: {}",
+                words.get(2..).unwrap_or_default().join(" ")
+            ));
+        } else if words.len() >= 10 && words[0].parse::<u32>().is_ok() {
+            // Attribute table: "ID# ATTRIBUTE_NAME FLAG VALUE WORST THRESH TYPE UPDATED WHEN_FAILED RAW_VALUE"
+            let (attribute_name, when_failed, raw_value) = (words[1], words[8], words[9]);
+            if when_failed != "-" {
+                health_problems.push(format!("Attribute {attribute_name} failed ({when_failed})"));
+            }
+            if let Some((_, description)) =
+                SECTOR_ATTRIBUTES.iter().find(|(n, _)| *n == attribute_name)
+            {
+                if raw_value.parse::<u64>().is_ok_and(|v| v > 0) {
+                    health_problems.push(format!("{raw_value} {description} ({attribute_name})"));
+                }
+            }
+        }
+    }
 
-                fs::write(
-                    "/etc/pacman.conf",
-                    fs::read_to_string("/etc/pacman.conf")
-                        .expect("Error reading from /etc/pacman.conf")
-                        .replace("#Color", "Color")
-                        .replace("#VerbosePkgLists", "VerbosePkgLists")
-                        .replace(
-                            "#ParallelDownloads = 5",
-                            "ParallelDownloads = 5\nILoveCandy",
-                        ),
-                )
-                .expect("Error writing to /etc/pacman.conf");
+    health_problems
+}
 
-                print_operation_result(OperationResult::Done);
+// pacman which is killed by an interruption or a timeout leaves its lock behind, and every later pacman fails with
+// "unable to lock database". Offers to remove such stale locks, but only while no pacman is running.
+fn check_pacman_locks(question: &mut Question) -> Result<(), AppError> {
+    for lock_path in PACMAN_LOCK_PATHS {
+        while Path::new(lock_path).exists() {
+            TextManager::set_color(TextColor::Yellow);
+            if is_pacman_running() {
+                formatted_print(
+                    "Warning: pacman database is locked by a running pacman",
+                    PrintFormat::DoubleDashedLine,
+                );
+                TextManager::reset_color_and_graphics();
+                if !question.bool_ask("Do you want to wait for it to finish?") {
+                    return Err(AppError::InternalError(String::from(
+                        "Error! Installation was canceled because the pacman database is locked.",
+                    )));
+                }
+                while is_pacman_running() {
+                    thread::sleep(time::Duration::from_secs(2));
+                }
+            } else {
+                formatted_print(
+                    &format!("Warning: Stale pacman database lock ({lock_path})"),
+                    PrintFormat::DoubleDashedLine,
+                );
+                TextManager::reset_color_and_graphics();
+                println!("It was probably left behind by an interrupted run. No pacman is running now.\n");
+                if !question.bool_ask("Do you want to remove the stale pacman database lock?") {
+                    return Err(AppError::InternalError(String::from(
+                        "Error! Installation was canceled because the pacman database is locked.",
+                    )));
+                }
+                fs::remove_file(lock_path)?;
+                append_to_log(&format!("Stale pacman lock removed: {lock_path}"));
             }
-            11 => {
-                app_config.print_installation_status_and_save_config(
-                    "Starting to install base system and some softwares",
+        }
+    }
+
+    Ok(())
+}
+
+// Looks for pacman or pacstrap processes, either in the live environment or inside arch-chroot.
+fn is_pacman_running() -> bool {
+    let Ok(processes) = fs::read_dir("/proc") else {
+        return false;
+    };
+
+    processes.flatten().any(|process| {
+        fs::read_to_string(process.path().join("comm"))
+            .is_ok_and(|name| matches!(name.trim(), "pacman" | "pacstrap"))
+    })
+}
+
+// pacman checks the signatures of packages against the clock, so a wrong clock makes pacstrap fail with confusing
+// signature errors. Waits for NTP and offers to wait again or to set the time by hand if it doesn't work.
+fn check_clock(question: &mut Question) -> Result<(), AppError> {
+    loop {
+        let is_synchronized = wait_for_time_sync();
+        let year = current_year();
+        if is_synchronized && year >= MIN_CLOCK_YEAR {
+            println!("Clock is synchronized.\n");
+            return Ok(());
+        }
+
+        TextManager::set_color(TextColor::Yellow);
+        formatted_print("Warning: Clock may be wrong", PrintFormat::DoubleDashedLine);
+        TextManager::reset_color_and_graphics();
+        if !is_synchronized {
+            println!(
+                "- Clock wasn't synchronized with NTP in {} seconds. (Is the network working?)",
+                TIME_SYNC_TIMEOUT.as_secs()
+            );
+        }
+        if year < MIN_CLOCK_YEAR {
+            println!("- Clock shows the year {year}, so package signatures would look invalid.");
+        }
+        println!();
+
+        question.selecting_ask(
+            "What do you want to do?",
+            &[
+                "Wait for synchronization again",
+                "Set the time by hand",
+                "Continue anyway",
+            ],
+        );
+        match question.selected_index() {
+            0 => continue,
+            1 => {
+                question.ask("Enter the current time in UTC. (YYYY-MM-DD HH:MM:SS): ");
+                // timedatectl refuses to set the time while NTP is enabled. NTP is enabled again to correct it later.
+                run_command("timedatectl", Some(&["set-ntp", "false"]))?;
+                let set_time_result = run_command(
+                    "timedatectl",
+                    Some(&["set-time", format!("{} UTC", question.answer).as_str()]),
                 );
+                run_command("timedatectl", Some(&["set-ntp", "true"]))?;
+                if set_time_result.is_ok() && current_year() >= MIN_CLOCK_YEAR {
+                    return Ok(());
+                }
+            }
+            _ => return Ok(()),
+        }
+    }
+}
 
-                question.ask("What is your system's CPU brand? (Enter 'amd' or 'intel'): ");
-                run_command(
-                    "pacstrap",
-                    Some(&[
-                        "/mnt",
-                        "base",
-                        "linux",
-                        "linux-firmware",
-                        format!("{}-ucode", question.answer).as_str(),
-                        "sudo",
-                        "helix",
-                        "grub",
-                        "dosfstools",
-                        "mtools",
-                        "networkmanager",
-                        "git",
-                        "base-devel",
-                    ]),
-                )?;
+// Returns true once timedatectl reports the clock as synchronized, or false after TIME_SYNC_TIMEOUT.
+fn wait_for_time_sync() -> bool {
+    let start_time = time::Instant::now();
+    while start_time.elapsed() < TIME_SYNC_TIMEOUT {
+        let is_synchronized = process::Command::new("timedatectl")
+            .args(["show", "--property=NTPSynchronized", "--value"])
+            .output()
+            .is_ok_and(|o| String::from_utf8_lossy(&o.stdout).trim() == "yes");
+        if is_synchronized {
+            return true;
+        }
+        thread::sleep(time::Duration::from_secs(2));
+    }
 
-                print_operation_result(OperationResult::Done);
+    false
+}
+
+// Year of the system clock. (Approximate around new year, which is enough for a sanity check)
+fn current_year() -> u64 {
+    let seconds = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    1970 + seconds * 400 / (146097 * 24 * 3600)
+}
+
+// Whether the console runs on a screen which is wide enough to make the default 8x16 font hard to read.
+fn has_high_resolution_screen() -> bool {
+    const MIN_SCREEN_WIDTH: u32 = 3000;
+
+    // Size of the framebuffer is given as "<width>,<height>".
+    fs::read_to_string("/sys/class/graphics/fb0/virtual_size")
+        .ok()
+        .and_then(|s| {
+            s.split(',')
+                .next()
+                .and_then(|w| w.trim().parse::<u32>().ok())
+        })
+        .is_some_and(|width| width >= MIN_SCREEN_WIDTH)
+}
+
+// Mounting, partitioning and writing to /etc need root, so running as a normal user would fail in the first step
+// which changes the system.
+fn check_root_privileges() -> Result<(), AppError> {
+    if unsafe { geteuid() } == 0 {
+        return Ok(());
+    }
+
+    Err(AppError::InternalError(format!(
+        "Error! The installer has to run as root. Run it again with: sudo {}",
+        env::args().collect::<Vec<String>>().join(" ")
+    )))
+}
+
+// Checks that the tools of the Arch Linux live ISO are there before the installation starts, so it doesn't fail in
+// the middle when one of them is run. Missing tools can be installed with pacman, so any Arch based system works.
+fn check_host_tools(question: &mut Question) -> Result<(), AppError> {
+    if !is_command_available("pacman") {
+        return Err(AppError::InternalError(String::from(
+            "Error! pacman wasn't found. The installer has to run on the Arch Linux live ISO or another Arch based system.",
+        )));
+    }
+
+    let missing_tools = HOST_TOOLS
+        .iter()
+        .filter(|(command, _)| !is_command_available(command))
+        .collect::<Vec<&(&str, &str)>>();
+    if missing_tools.is_empty() {
+        return Ok(());
+    }
+
+    TextManager::set_color(TextColor::Yellow);
+    formatted_print(
+        "Warning: Tools which the installer needs are missing",
+        PrintFormat::DoubleDashedLine,
+    );
+    TextManager::reset_color_and_graphics();
+    for (command, package) in &missing_tools {
+        println!("- {command} (Package: {package})");
+    }
+    println!();
+    if !question.bool_ask("Do you want to install the missing tools with pacman?") {
+        return Err(AppError::InternalError(String::from(
+            "Error! Installation was canceled because tools it needs are missing.",
+        )));
+    }
+
+    let mut packages = missing_tools
+        .iter()
+        .map(|(_, package)| *package)
+        .collect::<Vec<&str>>();
+    packages.dedup();
+    // Refreshing the databases without upgrading the system would be a partial upgrade, so they are used as they are.
+    let mut pacman_arguments = vec!["-S", "--needed", "--noconfirm"];
+    pacman_arguments.extend(packages);
+    run_command_with_retries("pacman", Some(&pacman_arguments), NETWORK_RETRY_POLICY).map_err(
+        |error| {
+            AppError::InternalError(format!(
+                "Error! The missing tools couldn't be installed: {error}\nIf the package databases are outdated, run 'pacman -Syu' to refresh them and update the system, then start the installer again."
+            ))
+        },
+    )?;
+
+    Ok(())
+}
+
+// Whether an executable file with the name is in one of the directories of PATH.
+fn is_command_available(command: &str) -> bool {
+    env::var_os("PATH").is_some_and(|paths| {
+        env::split_paths(&paths).any(|directory| {
+            fs::metadata(directory.join(command))
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+    })
+}
+
+// Losing power while formatting or installing the boot loader leaves a machine which doesn't boot, so laptops on
+// battery are warned, and below MIN_BATTERY_CAPACITY have to be plugged in before the installation starts.
+fn check_power_supply(question: &mut Question) -> Result<(), AppError> {
+    const MIN_BATTERY_CAPACITY: u32 = 20;
+
+    loop {
+        let Some(battery_capacity) = find_battery_capacity() else {
+            return Ok(());
+        };
+
+        TextManager::set_color(TextColor::Yellow);
+        formatted_print(
+            "Warning: This machine is running on battery",
+            PrintFormat::DoubleDashedLine,
+        );
+        TextManager::reset_color_and_graphics();
+        if battery_capacity >= MIN_BATTERY_CAPACITY {
+            println!("Battery is at {battery_capacity}%. Plug the machine in to be safe.\n");
+            if question.bool_ask("Do you want to continue on battery?") {
+                return Ok(());
             }
-            12 => {
-                app_config
-                    .print_installation_status_and_save_config("Generating file system table");
+        } else {
+            println!(
+                "Battery is at {battery_capacity}%, which may not last for the installation. Plug the machine in first.\n"
+            );
+        }
+        if !question.bool_ask("Do you want to check the power supply again?") {
+            return Err(AppError::InternalError(String::from(
+                "Error! Installation was canceled because the machine is running on battery.",
+            )));
+        }
+    }
+}
 
-                let output = String::from_utf8(
-                    process::Command::new("genfstab")
-                        .args(["-U", "/mnt"])
-                        .output()?
-                        .stdout,
-                )
-                .expect("Error: Can't make string from vector of bytes.");
+// Returns the charge of the battery in percent if the machine is running on battery. (None if it's plugged in
+// or has no battery)
+fn find_battery_capacity() -> Option<u32> {
+    let power_supply_directories = fs::read_dir("/sys/class/power_supply")
+        .ok()?
+        .flatten()
+        .map(|e| e.path())
+        .collect::<Vec<PathBuf>>();
+    let read_value = |directory: &PathBuf, name: &str| {
+        fs::read_to_string(directory.join(name))
+            .map(|v| v.trim().to_string())
+            .unwrap_or_default()
+    };
+
+    let is_plugged_in = power_supply_directories
+        .iter()
+        .any(|d| read_value(d, "type") != "Battery" && read_value(d, "online") == "1");
+    if is_plugged_in {
+        return None;
+    }
+    // Batteries of mice and keyboards have a scope of Device and don't power the machine.
+    power_supply_directories
+        .iter()
+        .filter(|d| read_value(d, "type") == "Battery" && read_value(d, "scope") != "Device")
+        .filter_map(|d| read_value(d, "capacity").parse::<u32>().ok())
+        .min()
+}
 
-                fs::write("/mnt/etc/fstab", output).expect("Error writing to /mnt/etc/fstab");
+// Whether the step isn't skipped on the command line or in the step menu and is among the selected steps if
+// steps were selected with --only.
+fn is_step_selected(
+    step_id: &str,
+    command_line_arguments: &CommandLineArguments,
+    app_config: &AppConfig,
+) -> bool {
+    !command_line_arguments
+        .skip_steps
+        .iter()
+        .chain(app_config.skipped_steps.iter())
+        .any(|s| s == step_id)
+        && (command_line_arguments.only_steps.is_empty()
+            || command_line_arguments
+                .only_steps
+                .iter()
+                .any(|s| s == step_id))
+}
 
-                print_operation_result(OperationResult::Done);
+// Warns before the installation starts if KDE Plasma won't run well on this machine: With less than 4 GiB of RAM
+// or without a GPU driver with kernel mode setting (Plasma then falls back to slow software rendering).
+// Declining skips the desktop steps, so the system can get a lighter desktop later or stay without one.
+fn check_desktop_requirements(question: &mut Question, app_config: &mut AppConfig) {
+    // MemTotal is a bit less than the installed RAM, because the kernel reserves some of it.
+    const MIN_MEMORY_SIZE: u64 = 3584 * 1024 * 1024;
+    // Drivers of firmware framebuffers, which are used when no real GPU driver was loaded.
+    const FRAMEBUFFER_DRIVERS: &[&str] =
+        &["simple-framebuffer", "efi-framebuffer", "vesa-framebuffer"];
+
+    let mut problems = Vec::new();
+    let memory_size = fs::read_to_string("/proc/meminfo")
+        .ok()
+        .and_then(|m| {
+            m.lines()
+                .find_map(|l| l.strip_prefix("MemTotal:"))
+                .and_then(|v| v.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        })
+        .map(|s| s * 1024);
+    if let Some(memory_size) = memory_size.filter(|s| *s < MIN_MEMORY_SIZE) {
+        problems.push(format!(
+            "This machine has {} of RAM. KDE Plasma with its applications needs about 4 GiB to run smoothly.",
+            format_size(memory_size)
+        ));
+    }
+
+    let gpu_drivers = fs::read_dir("/sys/class/drm")
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| {
+                    let name = e.file_name().to_string_lossy().to_string();
+                    name.starts_with("card") && !name.contains('-')
+                })
+                .filter_map(|e| fs::read_link(e.path().join("device/driver")).ok())
+                .filter_map(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+    if gpu_drivers
+        .iter()
+        .all(|d| FRAMEBUFFER_DRIVERS.contains(&d.as_str()))
+    {
+        problems.push(String::from(
+            "No GPU with a kernel mode setting driver was found. KDE Plasma would fall back to slow software rendering.",
+        ));
+    }
+
+    if problems.is_empty() {
+        return;
+    }
+    TextManager::set_color(TextColor::Yellow);
+    formatted_print(
+        "Warning: KDE Plasma may not run well on this machine",
+        PrintFormat::DoubleDashedLine,
+    );
+    TextManager::reset_color_and_graphics();
+    for problem in &problems {
+        println!("- {problem}");
+    }
+    println!("\nA lighter desktop (like Xfce or LXQt) can be installed with a custom step instead, or the system can stay without a desktop.\n");
+    if question.bool_ask("Do you want to install KDE Plasma anyway?") {
+        return;
+    }
+
+    for step_id in ["kde", "sddm", "numlock"] {
+        if !app_config.skipped_steps.iter().any(|s| s == step_id) {
+            app_config.skipped_steps.push(step_id.to_string());
+        }
+    }
+    app_config.save_config();
+    println!("\nKDE, SDDM and numlock steps will be skipped.\n");
+}
+
+// Compares the size of the packages with the free space of the root partition before anything is downloaded, so
+// a small partition stops the installation here instead of in the middle of pacstrap. 10% is added to the estimate
+// for the initramfs, logs and the first updates. If the sizes can't be found, the installation just continues.
+fn check_disk_space(question: &mut Question, packages: &[&str]) -> Result<(), AppError> {
+    let (download_size, installed_size) = match estimate_packages_size(packages) {
+        Ok(sizes) => sizes,
+        Err(error) => {
+            println!("\nNeeded disk space couldn't be estimated: {error}\n");
+            return Ok(());
+        }
+    };
+    let Some(free_space) = run_command_with_output("df", &["--output=avail", "-B1", "/mnt"])?
+        .lines()
+        .nth(1)
+        .and_then(|l| l.trim().parse::<u64>().ok())
+    else {
+        println!("\nFree space of /mnt couldn't be found.\n");
+        return Ok(());
+    };
+
+    // Packages are downloaded to the cache of the installed system, so both sizes count.
+    let needed_space = (download_size + installed_size) / 10 * 11;
+    println!(
+        "\nPackages need about {} ({} to download, {} installed). {} is free on the root partition.\n",
+        format_size(needed_space),
+        format_size(download_size),
+        format_size(installed_size),
+        format_size(free_space)
+    );
+    if needed_space <= free_space {
+        return Ok(());
+    }
+
+    print_operation_result(OperationResult::Error);
+    println!(
+        "The root partition is {} too small for the packages.\n",
+        format_size(needed_space - free_space)
+    );
+    if question.bool_ask("Do you want to continue anyway? (pacstrap will probably fail)") {
+        return Ok(());
+    }
+
+    Err(AppError::InternalError(String::from(
+        "Error! Root partition doesn't have enough free space for the packages.",
+    )))
+}
+
+// Runs the task in another thread. It's waited for with wait_for_background_task.
+fn start_background_task(
+    name: &'static str,
+    task: impl FnOnce() -> Result<(), String> + Send + 'static,
+) {
+    append_to_log(&format!("Background task started: {name}"));
+    let task_thread = thread::spawn(task);
+    BACKGROUND_TASKS.lock().unwrap().push((name, task_thread));
+}
+
+// Waits until the background task finishes and returns its result. Returns None if the task wasn't started.
+fn wait_for_background_task(name: &str) -> Option<Result<(), String>> {
+    let task_thread = {
+        let mut background_tasks = BACKGROUND_TASKS.lock().unwrap();
+        let task_index = background_tasks.iter().position(|(n, _)| *n == name)?;
+        background_tasks.remove(task_index).1
+    };
+
+    let result = task_thread
+        .join()
+        .unwrap_or_else(|_| Err(String::from("Task panicked")));
+    match &result {
+        Ok(()) => append_to_log(&format!("Background task finished: {name}")),
+        Err(error) => append_to_log(&format!("Background task failed: {name}: {error}")),
+    }
+
+    Some(result)
+}
+
+// Downloads the packages of pacstrap and their dependencies in the background, so pacstrap only has to install them.
+// Output is discarded to keep the questions of the next steps readable. Nothing is downloaded if RAM is short.
+fn start_downloading_packages(cpu_brand: &str) {
+    let free_space = run_command_with_output("df", &["--output=avail", "-B1", "/tmp"])
+        .ok()
+        .and_then(|o| o.lines().nth(1).and_then(|l| l.trim().parse::<u64>().ok()))
+        .unwrap_or_default();
+    if free_space < DOWNLOAD_AHEAD_MIN_FREE_SPACE {
+        append_to_log("Packages aren't downloaded ahead, because /tmp is too small.");
+        return;
+    }
+
+    let mut packages = BASE_PACKAGES
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<String>>();
+    // CPU brand is asked in pacstrap, so microcode is only downloaded ahead when the installation is continued.
+    if !cpu_brand.is_empty() {
+        packages.push(format!("{cpu_brand}-ucode"));
+    }
+
+    println!("Packages of the base system are downloaded in the background.\n");
+    start_background_task("download_packages", move || {
+        fs::create_dir_all(format!("{DOWNLOAD_AHEAD_DATABASE_PATH}/local"))
+            .and_then(|_| fs::create_dir_all(DOWNLOAD_AHEAD_CACHE_PATH))
+            .map_err(|e| e.to_string())?;
+
+        let status = process::Command::new("pacman")
+            .args([
+                "-Syw",
+                "--noconfirm",
+                "--dbpath",
+                DOWNLOAD_AHEAD_DATABASE_PATH,
+                "--cachedir",
+                DOWNLOAD_AHEAD_CACHE_PATH,
+            ])
+            .args(&packages)
+            .stdin(process::Stdio::null())
+            .stdout(process::Stdio::null())
+            .stderr(process::Stdio::null())
+            .status()
+            .map_err(|e| e.to_string())?;
+        if !status.success() {
+            return Err(format!("pacman exited with {status}"));
+        }
+
+        Ok(())
+    });
+}
+
+// Waits for the packages which are downloaded in the background and moves them to the cache of /mnt, where pacstrap
+// looks for them. If the download failed, pacstrap downloads what's missing itself.
+fn move_downloaded_packages() {
+    if BACKGROUND_TASKS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|(n, _)| *n == "download_packages")
+    {
+        println!("Waiting for the packages which are downloaded in the background...\n");
+    }
+    if let Some(Err(error)) = wait_for_background_task("download_packages") {
+        println!("Downloading packages in the background failed: {error}\n");
+    }
+
+    let Ok(downloaded_packages) = fs::read_dir(DOWNLOAD_AHEAD_CACHE_PATH) else {
+        return;
+    };
+    let target_cache_path = Path::new("/mnt/var/cache/pacman/pkg");
+    if fs::create_dir_all(target_cache_path).is_err() {
+        return;
+    }
+    // Files are copied, because /tmp and /mnt are different file systems.
+    for downloaded_package in downloaded_packages.flatten() {
+        let target_path = target_cache_path.join(downloaded_package.file_name());
+        if fs::copy(downloaded_package.path(), target_path).is_ok() {
+            let _ = fs::remove_file(downloaded_package.path());
+        }
+    }
+}
+
+// Returns the packages which are already installed in the system at root.
+fn find_installed_packages(root: &str, packages: &[&str]) -> Vec<String> {
+    let database_path = format!("{root}/var/lib/pacman");
+    if !Path::new(&database_path).join("local").exists() {
+        return Vec::new();
+    }
+
+    // pacman fails if some packages aren't installed, but still prints the installed ones.
+    process::Command::new("pacman")
+        .args(["--root", root, "--dbpath", database_path.as_str(), "-Qq"])
+        .args(packages)
+        .stderr(process::Stdio::null())
+        .output()
+        .map(|o| {
+            String::from_utf8_lossy(&o.stdout)
+                .lines()
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Returns the download and installed sizes of the packages and all their dependencies in bytes.
+// Dependencies are resolved with an empty local database, because the live environment already has many of them.
+fn estimate_packages_size(packages: &[&str]) -> Result<(u64, u64), AppError> {
+    const PACMAN_DATABASE_PATH: &str = "/tmp/arch_linux_installer_pacman_db";
+
+    fs::create_dir_all(format!("{PACMAN_DATABASE_PATH}/local"))?;
+    run_command_with_retries(
+        "pacman",
+        Some(&["-Sy", "--dbpath", PACMAN_DATABASE_PATH]),
+        NETWORK_RETRY_POLICY,
+    )?;
+
+    let mut arguments = vec![
+        "-Sp",
+        "--print-format",
+        "%n",
+        "--dbpath",
+        PACMAN_DATABASE_PATH,
+    ];
+    arguments.extend(packages);
+    let resolved_packages_output = run_command_with_output("pacman", &arguments)?;
+
+    // Field names of pacman are translated, so they are read in English.
+    let mut arguments = vec![
+        "LC_ALL=C",
+        "pacman",
+        "-Si",
+        "--dbpath",
+        PACMAN_DATABASE_PATH,
+    ];
+    arguments.extend(
+        resolved_packages_output
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty()),
+    );
+    let package_information = run_command_with_output("env", &arguments)?;
+
+    let mut download_size = 0;
+    let mut installed_size = 0;
+    for line in package_information.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        match key.trim() {
+            "Download Size" => download_size += parse_size(value).unwrap_or_default(),
+            "Installed Size" => installed_size += parse_size(value).unwrap_or_default(),
+            _ => {}
+        }
+    }
+
+    Ok((download_size, installed_size))
+}
+
+// Parses sizes printed by pacman. ("142.38 MiB")
+fn parse_size(size: &str) -> Option<u64> {
+    let (number, unit) = size.trim().split_once(' ')?;
+    let multiplier = match unit {
+        "B" => 1.0,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+
+    Some((number.parse::<f64>().ok()? * multiplier) as u64)
+}
+
+// Returns the partitions of the disk ordered by their start.
+fn find_disk_partitions(disk_name: &str) -> Vec<DiskPartition> {
+    let read_number = |path: String| {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|s| s.trim().parse::<u64>().ok())
+    };
+    let Ok(entries) = fs::read_dir(format!("/sys/block/{disk_name}")) else {
+        return Vec::new();
+    };
+
+    let mut disk_partitions = entries
+        .flatten()
+        .filter(|e| e.path().join("partition").exists())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            Some(DiskPartition {
+                start: read_number(format!("/sys/block/{disk_name}/{name}/start"))?,
+                size: read_number(format!("/sys/block/{disk_name}/{name}/size"))?,
+                name,
+            })
+        })
+        .collect::<Vec<DiskPartition>>();
+    disk_partitions.sort_by_key(|p| p.start);
+
+    disk_partitions
+}
+
+fn format_size(size_in_bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = size_in_bytes as f64;
+    let mut unit_index = 0;
+    while size >= 1024.0 && unit_index < units.len() - 1 {
+        size /= 1024.0;
+        unit_index += 1;
+    }
+
+    format!("{size:.1} {}", units[unit_index])
+}
+
+// Draws the disk as a bar where every partition takes room proportional to its size, followed by a table of the
+// partitions with their size, file system and the given role and plan. ((partition name, role, plan))
+// Free space smaller than 16 MiB is left out, because partitions are aligned with gaps like that.
+fn print_disk_layout(disk_name: &str, partition_roles: &[(String, &str, &str)]) {
+    const MIN_FREE_SPACE: u64 = 16 * 1024 * 1024 / 512;
+
+    let Some(disk_size) = fs::read_to_string(format!("/sys/block/{disk_name}/size"))
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .filter(|s| *s > 0)
+    else {
+        println!("Layout of {disk_name} can't be shown.\n");
+        return;
+    };
+
+    // (Label, partition name, size) where free space has no partition name.
+    let mut segments: Vec<(char, Option<String>, u64)> = Vec::new();
+    let mut next_start = 0;
+    let labels = ('1'..='9').chain('a'..='z');
+    for (disk_partition, label) in find_disk_partitions(disk_name).into_iter().zip(labels) {
+        if disk_partition.start.saturating_sub(next_start) >= MIN_FREE_SPACE {
+            segments.push(('.', None, disk_partition.start - next_start));
+        }
+        next_start = disk_partition.start + disk_partition.size;
+        segments.push((label, Some(disk_partition.name), disk_partition.size));
+    }
+    if disk_size.saturating_sub(next_start) >= MIN_FREE_SPACE {
+        segments.push(('.', None, disk_size - next_start));
+    }
+
+    println!("{disk_name} ({})", format_size(disk_size * 512));
+    if !TextManager::is_accessible() && !segments.is_empty() {
+        let bar_length = banner_line_length().saturating_sub(2).max(segments.len());
+        // Every segment gets at least one column. The largest one takes what rounding left over.
+        let mut segment_lengths = segments
+            .iter()
+            .map(|(_, _, size)| {
+                ((*size as f64 / disk_size as f64) * bar_length as f64)
+                    .round()
+                    .max(1.0) as usize
+            })
+            .collect::<Vec<usize>>();
+        let largest_segment_index = (0..segments.len())
+            .max_by_key(|i| segment_lengths[*i])
+            .unwrap_or_default();
+        let other_segments_length =
+            segment_lengths.iter().sum::<usize>() - segment_lengths[largest_segment_index];
+        segment_lengths[largest_segment_index] =
+            bar_length.saturating_sub(other_segments_length).max(1);
+
+        let bar = segments
+            .iter()
+            .zip(&segment_lengths)
+            .map(|((label, _, _), length)| label.to_string().repeat(*length))
+            .collect::<String>();
+        println!("[{bar}]");
+    }
+
+    for (label, partition_name, size) in &segments {
+        let Some(partition_name) = partition_name else {
+            println!(
+                "{label}  {}  {}",
+                pad_to_width("free", 14),
+                format_size(size * 512)
+            );
+            continue;
+        };
+        let file_system_type = query_block_device(&format!("/dev/{partition_name}"))
+            .file_system_type
+            .unwrap_or_else(|| String::from("-"));
+        let partition_line = format!(
+            "{label}  {}  {}  {}",
+            pad_to_width(partition_name, 14),
+            pad_to_width(&format_size(size * 512), 10),
+            file_system_type
+        );
+        if partition_roles.is_empty() {
+            println!("{partition_line}");
+            continue;
+        }
+        let (role, plan) = partition_roles
+            .iter()
+            .find(|(name, _, _)| name == partition_name)
+            .map(|(_, role, plan)| (*role, *plan))
+            .unwrap_or(("-", "untouched"));
+        println!(
+            "{}  {}  {plan}",
+            pad_to_width(&partition_line, 43),
+            pad_to_width(role, 5)
+        );
+    }
+    println!();
+}
+
+// Reads the names and codes of the countries from the output of "reflector --list-countries":
+// Country                Code Count
+// ---------------------- ---- -----
+// United Kingdom         GB      65
+fn find_reflector_countries(reflector_output: &str) -> Vec<(String, String)> {
+    reflector_output
+        .lines()
+        .skip_while(|l| !l.starts_with('-'))
+        .skip(1)
+        .filter_map(|l| {
+            let words = l.split_whitespace().collect::<Vec<&str>>();
+            let [name_words @ .., code, count] = words.as_slice() else {
+                return None;
+            };
+            if name_words.is_empty() || count.parse::<u32>().is_err() {
+                return None;
             }
-            13 => {
-                app_config.print_installation_status_and_save_config(
-                    "Configuring swap for encryption if necessary",
-                );
-                if app_config.encrypted_partitons {
-                    if let Some(swap_partition) = &app_config.swap_partition {
-                        run_command(
-                            "swapoff",
-                            Some(&[format!("/dev/{}", swap_partition).as_str()]),
-                        )?;
-                        run_command(
-                            "mkfs.ext2",
-                            Some(&[
-                                "-L",
-                                "cryptswap",
-                                format!("/dev/{}", swap_partition).as_str(),
-                                "1M",
-                            ]),
-                        )?;
+            Some((name_words.join(" "), code.to_string()))
+        })
+        .collect()
+}
 
-                        let fstab_content = fs::read_to_string("/mnt/etc/fstab")
-                            .expect("Error reading from /mnt/etc/fstab");
-                        let found_swap_line = fstab_content
-                            .lines()
-                            .filter(|l| l.contains("swap"))
-                            .collect::<Vec<&str>>()[0];
-                        let swap_uuid =
-                            found_swap_line.split_whitespace().collect::<Vec<&str>>()[0];
+// Finds the name (e.g. "nvme0n1p1") of a UEFI partition which holds the boot manager of Windows.
+fn find_windows_esp() -> Option<String> {
+    let output = process::Command::new("lsblk")
+        .args(["-rno", "NAME,PARTTYPE"])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|l| l.split_once(' '))
+        .filter(|(_, partition_type)| partition_type.eq_ignore_ascii_case(ESP_PARTITION_TYPE))
+        .map(|(partition_name, _)| partition_name.to_string())
+        .find(|partition_name| has_windows_boot_manager(&format!("/dev/{partition_name}")))
+}
+
+// Asks a second time before a UEFI partition with boot loaders of other systems is formatted, because formatting
+// it leaves them unbootable. (True if there are none)
+fn confirm_boot_loaders_removal(question: &mut Question, uefi_path: &str) -> bool {
+    let boot_loaders = find_other_boot_loaders(uefi_path);
+    if boot_loaders.is_empty() {
+        return true;
+    }
+
+    TextManager::set_color(TextColor::Yellow);
+    formatted_print(
+        "Warning: The UEFI partition holds boot loaders of other systems",
+        PrintFormat::DoubleDashedLine,
+    );
+    TextManager::reset_color_and_graphics();
+    for boot_loader in &boot_loaders {
+        println!("- EFI/{boot_loader}");
+    }
+    println!();
+    question.bool_ask(
+        "Formatting deletes these boot loaders, so their systems won't boot anymore. Do you really want to format the UEFI partition?",
+    )
+}
+
+// Vendor directories under EFI of a UEFI partition, except the one of this installer's GRUB and the fallback
+// directory, which holds only copies.
+fn find_other_boot_loaders(uefi_path: &str) -> Vec<String> {
+    inspect_partition(uefi_path, |mount_path| {
+        fs::read_dir(mount_path.join("EFI"))
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter(|e| e.path().is_dir())
+                    .map(|e| e.file_name().to_string_lossy().to_string())
+                    .filter(|n| {
+                        !n.eq_ignore_ascii_case("grub_uefi") && !n.eq_ignore_ascii_case("BOOT")
+                    })
+                    .collect::<Vec<String>>()
+            })
+            .unwrap_or_default()
+    })
+    .unwrap_or_default()
+}
+
+// Whether the partition is a UEFI partition with the boot manager of Windows on it.
+fn has_windows_boot_manager(partition_path: &str) -> bool {
+    find_file_system_type(partition_path).as_deref() == Some("vfat")
+        // FAT is case insensitive, so the path matches however Windows wrote it.
+        && inspect_partition(partition_path, |mount_path| {
+            mount_path.join("EFI/Microsoft/Boot/bootmgfw.efi").exists()
+        })
+        .unwrap_or(false)
+}
+
+// Finds partitions which hold a Linux installation, with the name of the installed system.
+fn find_linux_installations() -> Vec<(String, String)> {
+    const LINUX_FILE_SYSTEMS: &[&str] = &["ext4", "ext3", "ext2", "btrfs", "xfs", "f2fs"];
+    // Root of a btrfs installation is usually the @ subvolume.
+    const OS_RELEASE_PATHS: &[&str] = &[
+        "etc/os-release",
+        "usr/lib/os-release",
+        "@/etc/os-release",
+        "@/usr/lib/os-release",
+    ];
+
+    let Ok(output) = process::Command::new("lsblk")
+        .args(["-rpno", "NAME,TYPE,FSTYPE"])
+        .output()
+    else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|l| {
+            let mut columns = l.split(' ');
+            let partition_path = columns.next()?;
+            (columns.next() == Some("part")
+                && LINUX_FILE_SYSTEMS.contains(&columns.next().unwrap_or_default()))
+            .then(|| partition_path.to_string())
+        })
+        .filter_map(|partition_path| {
+            let os_release = inspect_partition(&partition_path, |mount_path| {
+                OS_RELEASE_PATHS
+                    .iter()
+                    .find_map(|p| fs::read_to_string(mount_path.join(p)).ok())
+            })??;
+            let system_name = os_release
+                .lines()
+                .find_map(|l| l.strip_prefix("PRETTY_NAME="))
+                .unwrap_or("Linux")
+                .trim_matches('"')
+                .to_string();
+            Some((partition_path, system_name))
+        })
+        .collect()
+}
+
+// Runs the function on the files of a partition. A mounted partition is looked at where it is, because mounting it
+// again read only fails. Others are mounted read only for a moment, without replaying their journal.
+fn inspect_partition<T>(partition_path: &str, inspect: impl FnOnce(&Path) -> T) -> Option<T> {
+    const MOUNT_PATH: &str = "/tmp/arch_linux_installer_inspected";
+
+    if let Some(mount_path) = process::Command::new("findmnt")
+        .args(["-nro", "TARGET", "--source", partition_path])
+        .output()
+        .ok()
+        .and_then(|o| {
+            String::from_utf8_lossy(&o.stdout)
+                .lines()
+                .next()
+                .map(String::from)
+        })
+    {
+        return Some(inspect(Path::new(&mount_path)));
+    }
+
+    // A read only mount still replays the journal of a file system which wasn't unmounted cleanly, which would change
+    // the partition of another system. Replaying is turned off for the file systems which support that.
+    let mount_options = match find_file_system_type(partition_path).as_deref() {
+        Some("ext2" | "ext3" | "ext4") => "ro,noload",
+        Some("btrfs" | "xfs") => "ro,norecovery",
+        _ => "ro",
+    };
+    fs::create_dir_all(MOUNT_PATH).ok()?;
+    let is_mounted = process::Command::new("mount")
+        .args(["-o", mount_options, partition_path, MOUNT_PATH])
+        .stderr(process::Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success());
+    if !is_mounted {
+        return None;
+    }
+    let result = inspect(Path::new(MOUNT_PATH));
+    let _ = process::Command::new("umount").arg(MOUNT_PATH).status();
+
+    Some(result)
+}
+
+// Titles of the boot menu entries which os-prober added to a GRUB config.
+fn find_os_prober_entries(grub_config: &str) -> Vec<String> {
+    grub_config
+        .lines()
+        .skip_while(|l| !l.starts_with("### BEGIN /etc/grub.d/30_os-prober"))
+        .take_while(|l| !l.starts_with("### END /etc/grub.d/30_os-prober"))
+        .filter_map(|l| l.trim_start().strip_prefix("menuentry '"))
+        .filter_map(|l| l.split('\'').next())
+        .map(String::from)
+        .collect()
+}
+
+// Returns the file system (or LUKS) type of the device found by blkid.
+fn find_file_system_type(device_path: &str) -> Option<String> {
+    query_block_device(device_path).file_system_type
+}
+
+// Mentions the existing file system in the question, so data isn't destroyed by accident when continuing an installation.
+fn create_format_question(partition_kind: &str, partition_path: &str) -> String {
+    match find_file_system_type(partition_path) {
+        Some(file_system_type) => format!(
+            "Your {partition_kind} partition ({partition_path}) already contains {file_system_type}. Do you want to format it?"
+        ),
+        None => format!("Do you want to format your {partition_kind} partition?"),
+    }
+}
 
-                        fs::write(
-                            "/mnt/etc/fstab",
-                            fstab_content.replace(swap_uuid, "/dev/mapper/swap"),
-                        )
-                        .expect("Error writing to /mnt/etc/fstab");
-                    }
-                }
-                print_operation_result(OperationResult::Done);
-            }
-            14 => {
-                app_config.print_installation_status_and_save_config(
-                    "Configuring pacman for installed system",
-                );
+fn is_mounted(source: &str) -> bool {
+    process::Command::new("findmnt")
+        .args(["--source", source])
+        .stdout(process::Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
 
-                fs::write(
-                    "/mnt/etc/pacman.conf",
-                    fs::read_to_string("/mnt/etc/pacman.conf")
-                        .expect("Error reading from /mnt/etc/pacman.conf")
-                        .replace("#Color", "Color")
-                        .replace("#VerbosePkgLists", "VerbosePkgLists")
-                        .replace(
-                            "#ParallelDownloads = 5",
-                            "ParallelDownloads = 5\nILoveCandy",
-                        ),
-                )
-                .expect("Error writing to /mnt/etc/pacman.conf");
+fn is_swap_active(swap_path: &str) -> bool {
+    // /proc/swaps lists device mapper devices by their dm-* name, which /dev/mapper/<name> links to.
+    let device_path = fs::canonicalize(swap_path)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| swap_path.to_string());
+    fs::read_to_string("/proc/swaps").is_ok_and(|swaps| {
+        swaps
+            .lines()
+            .any(|l| l.split_whitespace().next() == Some(device_path.as_str()))
+    })
+}
 
-                print_operation_result(OperationResult::Done);
-            }
-            15 => {
-                app_config.print_installation_status_and_save_config("Setting time zone");
+fn ensure_not_mounted(source: &str) -> Result<(), AppError> {
+    if is_mounted(source) {
+        return Err(AppError::InternalError(format!(
+            "Error! {source} is mounted. Run 'arch_linux_installer cleanup' before formatting it."
+        )));
+    }
 
-                loop {
-                    question.ask("Enter your time zone. (For example: Europe/London): ");
-                    if !question.answer.contains("/") {
-                        print_operation_result(OperationResult::Error);
-                        if question.bool_ask("Please enter a forward slash (/) between the continent and city name. Do you want to enter the time zone again?") {
-                    continue;
-                } else {
-                    TextManager::set_color(TextColor::Red);
-                    formatted_print("Installation failed.", PrintFormat::Bordered);
-                    return Err(AppError::InternalError(String::from("Error! Internal process exited. Setting time zone failed.")));
-                }
-                    }
+    Ok(())
+}
 
-                    break;
-                }
+fn mount_partition(source: &str, target: &str) -> Result<(), AppError> {
+    let already_mounted = process::Command::new("findmnt")
+        .args(["--source", source, "--mountpoint", target])
+        .stdout(process::Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success());
 
-                let time_zone_parts = question.answer.split("/").collect::<Vec<_>>();
-                run_command(
-                    "arch-chroot",
-                    Some(&[
-                        "/mnt",
-                        "ln",
-                        "-sf",
-                        format!(
-                            "/mnt/etc/usr/share/zoneinfo/{}/{}",
-                            time_zone_parts[0], time_zone_parts[1]
-                        )
-                        .as_str(),
-                        "/etc/localtime",
-                    ]),
-                )?;
+    if already_mounted {
+        println!("{source} is already mounted on {target}, skipping mount.");
+        Ok(())
+    } else {
+        run_command("mount", Some(&[source, target]))
+    }
+}
 
-                print_operation_result(OperationResult::Done);
-            }
-            16 => {
-                app_config.print_installation_status_and_save_config("Setting hardware clock");
+// Asks the passphrase of the encrypted partitions once per run. It is kept only in memory and never saved.
+fn ask_luks_passphrase(question: &mut Question, luks_passphrase: &mut Option<String>) -> String {
+    luks_passphrase
+        .get_or_insert_with(|| {
+            question.password_ask("Enter the passphrase of the encrypted partitions: ")
+        })
+        .clone()
+}
 
-                run_command("arch-chroot", Some(&["/mnt", "hwclock", "--systohc"]))?;
+// Passphrase is given through stdin, so cryptsetup doesn't ask for it (and its confirmation) again.
+// Parameters which aren't given in the customization file are left to the cryptsetup defaults.
+fn format_encrypted_partition(
+    partition_path: &str,
+    passphrase: &str,
+    luks_format_arguments: &[String],
+) -> Result<(), AppError> {
+    let mut arguments = vec![
+        "luksFormat",
+        "--type",
+        "luks2",
+        "--batch-mode",
+        "--key-file=-",
+    ];
+    arguments.extend(luks_format_arguments.iter().map(String::as_str));
+    arguments.push(partition_path);
+
+    run_command_with_input("cryptsetup", Some(&arguments), passphrase)
+}
 
-                print_operation_result(OperationResult::Done);
-            }
-            17 => {
-                app_config.print_installation_status_and_save_config("Setting local");
+// cryptsetup asks for the passphrase itself if it isn't known. (For example when continuing an installation)
+fn open_encrypted_partition(
+    partition_path: &str,
+    mapping_name: &str,
+    passphrase: Option<&str>,
+) -> Result<(), AppError> {
+    if fs::metadata(format!("/dev/mapper/{mapping_name}")).is_ok() {
+        println!("{mapping_name} is already open, skipping cryptsetup open.");
+        return Ok(());
+    }
 
-                fs::write(
-                    "/mnt/etc/locale.gen",
-                    fs::read_to_string("/mnt/etc/locale.gen")
-                        .expect("Error reading from /mnt/etc/locale.gen")
-                        .replace("#en_US.UTF-8 UTF-8", "en_US.UTF-8 UTF-8"),
-                )
-                .expect("Error writing to /mnt/etc/locale.gen");
+    match passphrase {
+        Some(passphrase) => run_command_with_input(
+            "cryptsetup",
+            Some(&["open", "--key-file=-", partition_path, mapping_name]),
+            passphrase,
+        ),
+        None => run_command("cryptsetup", Some(&["open", partition_path, mapping_name])),
+    }
+}
 
-                run_command("arch-chroot", Some(&["/mnt", "locale-gen"]))?;
+// Creates a random keyfile in the installed system and adds it to the key slots of the home partition.
+// Returns the path of the keyfile inside the installed system.
+fn create_home_keyfile(home_path: &str, passphrase: Option<&str>) -> Result<String, AppError> {
+    let keyfile_path = "/etc/cryptsetup-keys.d/home.key";
+    let target_keyfile_path = format!("/mnt{keyfile_path}");
+
+    // The keyfile may already be enrolled if the installation was continued.
+    let is_keyfile_enrolled = Path::new(&target_keyfile_path).exists()
+        && process::Command::new("cryptsetup")
+            .args([
+                "open",
+                "--test-passphrase",
+                "--key-file",
+                target_keyfile_path.as_str(),
+                home_path,
+            ])
+            .status()
+            .is_ok_and(|s| s.success());
+    if is_keyfile_enrolled {
+        println!("{target_keyfile_path} is already enrolled, skipping keyfile creation.");
+        return Ok(keyfile_path.to_string());
+    }
 
-                print_operation_result(OperationResult::Done);
-            }
-            18 => {
-                app_config.print_installation_status_and_save_config("Setting host name");
+    fs::create_dir_all("/mnt/etc/cryptsetup-keys.d")?;
+    fs::set_permissions(
+        "/mnt/etc/cryptsetup-keys.d",
+        fs::Permissions::from_mode(0o700),
+    )?;
+    record_in_install_script("mkdir -p /mnt/etc/cryptsetup-keys.d");
+    record_in_install_script("chmod 700 /mnt/etc/cryptsetup-keys.d");
+    run_command(
+        "dd",
+        Some(&[
+            "bs=512",
+            "count=4",
+            "iflag=fullblock",
+            "if=/dev/random",
+            format!("of={target_keyfile_path}").as_str(),
+        ]),
+    )?;
+    fs::set_permissions(&target_keyfile_path, fs::Permissions::from_mode(0o600))?;
+    record_in_install_script(&format!("chmod 600 {target_keyfile_path}"));
+
+    match passphrase {
+        Some(passphrase) => run_command_with_input(
+            "cryptsetup",
+            Some(&[
+                "luksAddKey",
+                "--key-file=-",
+                home_path,
+                target_keyfile_path.as_str(),
+            ]),
+            passphrase,
+        )?,
+        None => run_command(
+            "cryptsetup",
+            Some(&["luksAddKey", home_path, target_keyfile_path.as_str()]),
+        )?,
+    }
+    println!("Home ({home_path}): Keyfile {keyfile_path} added");
 
-                question.ask("Enter your host name: ");
-                fs::write("/mnt/etc/hostname", question.answer.clone())
-                    .expect("Error writing to /mnt/etc/hostname");
+    Ok(keyfile_path.to_string())
+}
 
-                print_operation_result(OperationResult::Done);
-            }
-            19 => {
-                app_config
-                    .print_installation_status_and_save_config("Setting hosts configuaration");
+fn close_encrypted_partition(mapping_name: &str) -> Result<(), AppError> {
+    let mapping_path = format!("/dev/mapper/{mapping_name}");
+    if fs::metadata(&mapping_path).is_err() {
+        return Ok(());
+    }
+
+    run_command("cryptsetup", Some(&["close", mapping_path.as_str()]))?;
+    println!("{mapping_path}: Closed");
+
+    Ok(())
+}
+
+// Prints the steps, partitions, settings and packages of an installation with the config without changing anything.
+// A missing default config means a new installation. Commands and file changes of built-in steps depend on the
+// answers given while they run, so only custom steps list their commands. (Executed commands and written files are
+// recorded in install.sh)
+fn print_plan(command_line_arguments: &CommandLineArguments) -> Result<(), AppError> {
+    let customization = load_customization()?;
+    let step_order = create_step_order(&customization.custom_steps)?;
+    let mut app_config = AppConfig::new(step_order.len() as u8);
+    let config_file_path = command_line_arguments
+        .config_path
+        .as_deref()
+        .unwrap_or(CONFIG_FILE_PATH);
+    if command_line_arguments.config_path.is_some() || Path::new(config_file_path).exists() {
+        app_config.load_config_file(config_file_path)?;
+    }
+
+    // (ID, Kind, Status, Commands)
+    let steps = step_order
+        .iter()
+        .map(|step_entry| {
+            let step_name = step_entry_name(step_entry, &customization.custom_steps);
+            let status = if !is_step_selected(step_name, command_line_arguments, &app_config) {
+                "skipped"
+            } else if app_config.completed_steps.iter().any(|s| s == step_name)
+                && command_line_arguments.only_steps.is_empty()
+            {
+                "completed"
+            } else {
+                "pending"
+            };
+            let (kind, commands) = match step_entry {
+                StepEntry::BuiltIn(_) => ("built-in", Vec::new()),
+                StepEntry::Custom(custom_step_index) => (
+                    "custom",
+                    customization.custom_steps[*custom_step_index]
+                        .commands
+                        .clone(),
+                ),
+            };
+            (step_name, kind, status, commands)
+        })
+        .collect::<Vec<_>>();
+    let is_step_pending = |step_id: &str| {
+        steps
+            .iter()
+            .any(|(name, _, status, _)| *name == step_id && *status == "pending")
+    };
+
+    // (Role, Name, Path, Current file system)
+    let partitions = [
+        ("root", Some(app_config.root_partition.clone())),
+        ("uefi", app_config.uefi_partition.clone()),
+        ("boot", app_config.boot_partition.clone()),
+        ("home", app_config.home_partition.clone()),
+        ("swap", app_config.swap_partition.clone()),
+    ]
+    .into_iter()
+    .chain(
+        app_config
+            .root_devices
+            .iter()
+            .map(|d| ("root", Some(d.clone()))),
+    )
+    .filter_map(|(role, partition)| {
+        let partition = partition.filter(|p| !p.is_empty())?;
+        let partition_path = app_config.partition_path(&partition);
+        let file_system_type = find_file_system_type(&partition_path).unwrap_or_default();
+        Some((role, partition, partition_path, file_system_type))
+    })
+    .collect::<Vec<_>>();
+
+    let settings = [
+        (
+            "installation_mode",
+            String::from(if app_config.uefi_install {
+                "UEFI"
+            } else {
+                "BIOS"
+            }),
+        ),
+        ("encrypted", app_config.encrypted_partitons.to_string()),
+        ("btrfs_profile", app_config.btrfs_profile.clone()),
+        ("reinstall", app_config.reinstall.to_string()),
+        ("reuse_home", app_config.reuse_home.to_string()),
+        (
+            "image_path",
+            app_config.image_path.clone().unwrap_or_default(),
+        ),
+        (
+            "container_path",
+            app_config.container_path.clone().unwrap_or_default(),
+        ),
+        ("hostname", app_config.hostname.clone()),
+        ("username", app_config.username.clone()),
+        ("time_zone", app_config.time_zone.clone()),
+        ("locales", app_config.locales.join(",")),
+        ("mirror_country", app_config.mirror_country.clone()),
+        ("cpu_brand", app_config.cpu_brand.clone()),
+    ];
+
+    let mut packages = Vec::new();
+    if is_step_pending("pacstrap") {
+        packages.extend(BASE_PACKAGES.iter().map(|p| p.to_string()));
+        if !app_config.cpu_brand.is_empty() {
+            packages.push(format!("{}-ucode", app_config.cpu_brand));
+        }
+    }
+    if is_step_pending("kde") {
+        packages.extend(KDE_PACKAGES.iter().map(|p| p.to_string()));
+    }
 
-                fs::write(
-                    "/mnt/etc/hosts",
+    if command_line_arguments.json {
+        let steps_json = steps
+            .iter()
+            .map(|(name, kind, status, commands)| {
+                // Only custom steps have commands which are known before they run.
+                let commands_json = if *kind == "custom" {
                     format!(
-                        "127.0.0.1\tlocalhost\n::1 \t\tlocalhost\n127.0.1.1\t{}.localdomain\t{}",
-                        question.answer, question.answer
-                    ),
+                        ", \"commands\": [{}]",
+                        commands
+                            .iter()
+                            .map(|c| quote_for_json(c))
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    )
+                } else {
+                    String::new()
+                };
+                format!(
+                    "    {{\"id\": {}, \"kind\": {}, \"status\": {}{commands_json}}}",
+                    quote_for_json(name),
+                    quote_for_json(kind),
+                    quote_for_json(status)
+                )
+            })
+            .collect::<Vec<String>>();
+        let partitions_json = partitions
+            .iter()
+            .map(|(role, name, path, file_system_type)| {
+                format!(
+                    "    {{\"role\": {}, \"name\": {}, \"path\": {}, \"file_system\": {}}}",
+                    quote_for_json(role),
+                    quote_for_json(name),
+                    quote_for_json(path),
+                    quote_for_json(file_system_type)
                 )
-                .expect("Error writing to /mnt/etc/hosts");
+            })
+            .collect::<Vec<String>>();
+        let settings_json = settings
+            .iter()
+            .map(|(key, value)| format!("    {}: {}", quote_for_json(key), quote_for_json(value)))
+            .collect::<Vec<String>>();
+
+        println!("{{");
+        println!("  \"config\": {},", quote_for_json(config_file_path));
+        println!("  \"steps\": [\n{}\n  ],", steps_json.join(",\n"));
+        println!("  \"partitions\": [\n{}\n  ],", partitions_json.join(",\n"));
+        println!("  \"settings\": {{\n{}\n  }},", settings_json.join(",\n"));
+        println!(
+            "  \"packages\": [{}]",
+            packages
+                .iter()
+                .map(|p| quote_for_json(p))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        println!("}}");
+        return Ok(());
+    }
 
-                print_operation_result(OperationResult::Done);
+    formatted_print("Steps", PrintFormat::DashedLine);
+    for (name, kind, status, commands) in &steps {
+        if *kind == "custom" {
+            println!("{name} ({status}, custom)");
+        } else {
+            println!("{name} ({status})");
+        }
+        for command in commands {
+            println!("    $ {command}");
+        }
+    }
+    formatted_print("Partitions", PrintFormat::DashedLine);
+    if partitions.is_empty() {
+        println!("No partitions are chosen yet.");
+    }
+    for (role, name, path, file_system_type) in &partitions {
+        println!(
+            "{role}: {name} ({path}, {})",
+            if file_system_type.is_empty() {
+                "no file system"
+            } else {
+                file_system_type.as_str()
             }
-            20 => {
-                app_config.print_installation_status_and_save_config("Setting root pasword");
+        );
+    }
+    formatted_print("Settings", PrintFormat::DashedLine);
+    for (key, value) in &settings {
+        println!("{key}: {value}");
+    }
+    formatted_print("Packages", PrintFormat::DashedLine);
+    println!("{}\n", packages.join(" "));
 
-                loop {
-                    if let Err(error) = run_command("arch-chroot", Some(&["/mnt", "passwd"])) {
-                        print_operation_result(OperationResult::Error);
-                        if question.bool_ask("Do you want to enter the root password again?") {
-                            continue;
-                        } else {
-                            TextManager::set_color(TextColor::Red);
-                            formatted_print("Installation failed.", PrintFormat::Bordered);
-                            return Err(error);
-                        }
-                    } else {
-                        break;
-                    }
-                }
+    Ok(())
+}
 
-                print_operation_result(OperationResult::Done);
-            }
-            21 => {
-                app_config.print_installation_status_and_save_config("Creating user");
+// Writes an answers file which approximates the Arch Linux system at root ("/" or a mounted system): partitions,
+// file systems, swap, time zone, locales, keymap, host name, user and CPU brand. Its explicitly installed packages
+// and enabled services are added to the customization file as a custom step. Questions the answers file can't
+// answer (e.g. formatting and passwords) are still asked.
+fn clone_machine(root_path: &str) -> Result<(), AppError> {
+    if Path::new(CLONED_ANSWERS_FILE_PATH).exists() {
+        return Err(AppError::InternalError(format!(
+            "Error! {CLONED_ANSWERS_FILE_PATH} already exists. Move it away to clone a machine again."
+        )));
+    }
+    let root_path = root_path.trim_end_matches('/');
+    let read_system_file =
+        |path: &str| fs::read_to_string(format!("{root_path}{path}")).unwrap_or_default();
+    let fstab_content = read_system_file("/etc/fstab");
+    if fstab_content.is_empty() {
+        return Err(AppError::InternalError(format!(
+            "Error! {root_path}/etc/fstab can't be read. Is there an Arch Linux system at '{}'?",
+            if root_path.is_empty() { "/" } else { root_path }
+        )));
+    }
 
-                loop {
-                    question.ask("Enter your username: ");
-                    if let Err(error) = run_command(
-                        "arch-chroot",
-                        Some(&["/mnt", "useradd", "-m", question.answer.as_str()]),
-                    ) {
-                        print_operation_result(OperationResult::Error);
-                        if question.bool_ask("Do you want to enter the username again?") {
-                            continue;
-                        } else {
-                            TextManager::set_color(TextColor::Red);
-                            formatted_print("Installation failed.", PrintFormat::Bordered);
-                            return Err(error);
-                        }
-                    } else {
-                        break;
-                    }
-                }
-                app_config.username = question.answer.clone();
+    let mut answers = vec![String::from("Do you want to continue? => y")];
+    let mut add_answer = |question_part: &str, answer: &str| {
+        answers.push(format!("{question_part} => {answer}"));
+    };
+
+    // (Partition name, Encrypted, Mount point, File system type)
+    let mounts = fstab_content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|l| {
+            let fields = l.split_whitespace().collect::<Vec<&str>>();
+            let (partition_name, encrypted) = find_partition_of_source(fields.first()?)?;
+            Some((
+                partition_name,
+                encrypted,
+                fields.get(1)?.to_string(),
+                fields.get(2)?.to_string(),
+            ))
+        })
+        .collect::<Vec<_>>();
+    let find_mount = |mount_points: &[&str], is_vfat: bool| {
+        mounts.iter().find(|(_, _, mount_point, file_system_type)| {
+            mount_points.contains(&mount_point.as_str()) && (file_system_type == "vfat") == is_vfat
+        })
+    };
+    let Some((root_partition, root_encrypted, _, _)) = find_mount(&["/"], false) else {
+        return Err(AppError::InternalError(format!(
+            "Error! Root partition of {root_path}/etc/fstab wasn't found on this machine."
+        )));
+    };
+    let uefi_mount = find_mount(&["/boot/EFI", "/boot/efi", "/efi", "/boot"], true);
+    let boot_mount = find_mount(&["/boot"], false);
+    let home_mount = find_mount(&["/home"], false).filter(|(p, _, _, _)| p != root_partition);
+    let swap_mount = mounts.iter().find(|(_, _, _, t)| t == "swap");
+    let has_zram = !read_system_file("/etc/systemd/zram-generator.conf").is_empty();
+
+    // Partitions
+    add_answer(
+        "Which installation mode do you want?",
+        if uefi_mount.is_some() {
+            "choice:UEFI"
+        } else {
+            "choice:BIOS"
+        },
+    );
+    add_answer(
+        "Do you want to encrypt your root and home partitions?",
+        if *root_encrypted { "y" } else { "n" },
+    );
+    if *root_encrypted {
+        let mkinitcpio_content = read_system_file("/etc/mkinitcpio.conf");
+        add_answer(
+            "Do you want to use the systemd based initramfs hooks",
+            if mkinitcpio_content.contains("sd-encrypt") {
+                "y"
+            } else {
+                "n"
+            },
+        );
+    }
+    add_answer("Enter the name of your root partition:", root_partition);
+    match boot_mount {
+        Some((boot_partition, _, _, _)) => {
+            add_answer("Do you have a separate boot partition?", "y");
+            add_answer("Enter the name of your boot partition:", boot_partition);
+        }
+        None => add_answer("Do you have a separate boot partition?", "n"),
+    }
+    if let Some((uefi_partition, _, _, _)) = uefi_mount {
+        add_answer("Enter the name of your uefi partition:", uefi_partition);
+    }
+    match home_mount {
+        Some((home_partition, _, _, _)) => {
+            add_answer("Do you have a separate home partition?", "y");
+            add_answer("Enter the name of your home partition:", home_partition);
+        }
+        None => add_answer("Do you have a separate home partition?", "n"),
+    }
 
-                print_operation_result(OperationResult::Done);
-            }
-            22 => {
-                app_config.print_installation_status_and_save_config("Setting your user pasword");
+    // Swap
+    add_answer(
+        "Which kind of swap do you want to use?",
+        match (swap_mount.is_some(), has_zram) {
+            (false, false) => "choice:No swap",
+            (true, false) => "choice:Swap partition",
+            (false, true) => "choice:zram",
+            (true, true) => "choice:Swap partition and zram",
+        },
+    );
+    if has_zram {
+        let zram_generator_content = read_system_file("/etc/systemd/zram-generator.conf");
+        let zram_value = |key: &str| {
+            zram_generator_content.lines().find_map(|l| {
+                let (line_key, value) = l.split_once('=')?;
+                (line_key.trim() == key).then(|| value.trim().to_string())
+            })
+        };
+        add_answer(
+            "Enter the zram size expression.",
+            &zram_value("zram-size").unwrap_or_default(),
+        );
+        if let Some(compression_algorithm) = zram_value("compression-algorithm") {
+            add_answer(
+                "Which compression algorithm do you want zram to use?",
+                &format!("choice:{compression_algorithm}"),
+            );
+        }
+    }
+    if let Some((swap_partition, _, _, _)) = swap_mount {
+        add_answer("Enter name of the swap partition:", swap_partition);
+        add_answer(
+            "Do you want to enable hibernation to the swap partition?",
+            if read_system_file("/etc/default/grub").contains("resume=") {
+                "y"
+            } else {
+                "n"
+            },
+        );
+    }
 
-                loop {
-                    if let Err(error) = run_command(
-                        "arch-chroot",
-                        Some(&["/mnt", "passwd", question.answer.as_str()]),
-                    ) {
-                        print_operation_result(OperationResult::Error);
-                        if question.bool_ask("Do you want to enter the user password again?") {
-                            continue;
-                        } else {
-                            TextManager::set_color(TextColor::Red);
-                            formatted_print("Installation failed.", PrintFormat::Bordered);
-                            return Err(error);
-                        }
-                    } else {
-                        break;
-                    }
-                }
+    // System settings
+    if let Some(time_zone) = fs::read_link(format!("{root_path}/etc/localtime"))
+        .ok()
+        .and_then(|p| Some(p.to_string_lossy().split_once("zoneinfo/")?.1.to_string()))
+    {
+        add_answer(
+            "Which time zone do you want to use?",
+            &format!("choice:{time_zone}"),
+        );
+    }
+    let enabled_locales = read_system_file("/etc/locale.gen")
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect::<Vec<String>>();
+    for (locale_index, locale) in enabled_locales.iter().enumerate() {
+        add_answer(
+            "Which locale do you want to enable?",
+            &format!("choice:{locale}"),
+        );
+        add_answer(
+            "Do you want to enable another locale?",
+            if locale_index + 1 < enabled_locales.len() {
+                "y"
+            } else {
+                "n"
+            },
+        );
+    }
+    if let Some(language) = read_system_file("/etc/locale.conf")
+        .lines()
+        .find_map(|l| l.strip_prefix("LANG="))
+    {
+        add_answer(
+            "Which locale do you want to use as the system language (LANG)?",
+            &format!("choice:{}", language.trim()),
+        );
+    }
+    if let Some(keymap) = read_system_file("/etc/vconsole.conf")
+        .lines()
+        .find_map(|l| l.strip_prefix("KEYMAP="))
+    {
+        add_answer(
+            "Which console keymap do you want to use?",
+            &format!("choice:{}", keymap.trim()),
+        );
+    }
+    let hostname = read_system_file("/etc/hostname");
+    if !hostname.trim().is_empty() {
+        add_answer("Enter your host name:", hostname.trim());
+    }
+    // First user with a login shell is taken as the user created by the installer.
+    if let Some((username, full_name)) = read_system_file("/etc/passwd").lines().find_map(|l| {
+        let fields = l.split(':').collect::<Vec<&str>>();
+        let user_id = fields.get(2)?.parse::<u32>().ok()?;
+        ((1000..60000).contains(&user_id) && !fields.get(6)?.ends_with("nologin")).then(|| {
+            // Full name is the first part of the GECOS field.
+            let full_name = fields[4].split(',').next().unwrap_or_default();
+            (fields[0].to_string(), full_name.to_string())
+        })
+    }) {
+        add_answer("Enter your username:", &username);
+        add_answer("Enter your full name.", &full_name);
+    }
 
-                print_operation_result(OperationResult::Done);
-            }
-            23 => {
-                app_config.print_installation_status_and_save_config("Adding user to wheel group");
+    // Packages and services
+    let pacman_database_path = format!("{root_path}/var/lib/pacman");
+    let query_packages = |query: &str| {
+        let root = if root_path.is_empty() { "/" } else { root_path };
+        process::Command::new("pacman")
+            .args([
+                "--root",
+                root,
+                "--dbpath",
+                pacman_database_path.as_str(),
+                query,
+            ])
+            .output()
+            .map(|o| {
+                String::from_utf8_lossy(&o.stdout)
+                    .lines()
+                    .map(String::from)
+                    .collect::<Vec<String>>()
+            })
+            .unwrap_or_default()
+    };
+    let native_packages = query_packages("-Qqen");
+    if let Some(cpu_brand) = ["amd", "intel"]
+        .into_iter()
+        .find(|b| native_packages.contains(&format!("{b}-ucode")))
+    {
+        add_answer("What is your system's CPU brand?", cpu_brand);
+    }
+    // Packages which the installer installs anyway are left out.
+    let extra_packages = native_packages
+        .iter()
+        .filter(|p| {
+            !BASE_PACKAGES.contains(&p.as_str())
+                && !KDE_PACKAGES.contains(&p.as_str())
+                && !p.ends_with("-ucode")
+        })
+        .map(String::as_str)
+        .collect::<Vec<&str>>();
+    let foreign_packages = query_packages("-Qqem");
+    let enabled_services = process::Command::new("systemctl")
+        .args([
+            format!(
+                "--root={}",
+                if root_path.is_empty() { "/" } else { root_path }
+            )
+            .as_str(),
+            "list-unit-files",
+            "--state=enabled",
+            "--type=service,timer",
+            "--no-legend",
+        ])
+        .output()
+        .map(|o| {
+            String::from_utf8_lossy(&o.stdout)
+                .lines()
+                .filter_map(|l| l.split_whitespace().next().map(String::from))
+                .filter(|u| !u.starts_with("systemd-") && !u.starts_with("getty@"))
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+
+    fs::write(
+        CLONED_ANSWERS_FILE_PATH,
+        format!(
+            "# Answers which approximate the system at '{}'. Use them with --answers {CLONED_ANSWERS_FILE_PATH}.\n# Each line has the form \"<part of the question> => <answer>\". Missing answers are asked.\n{}\n",
+            if root_path.is_empty() { "/" } else { root_path },
+            answers.join("\n")
+        ),
+    )?;
+    println!("Answers were written to {CLONED_ANSWERS_FILE_PATH}.");
+
+    if extra_packages.is_empty() && enabled_services.is_empty() && foreign_packages.is_empty() {
+        println!();
+        return Ok(());
+    }
+    let customization_content = fs::read_to_string(CUSTOMIZATION_FILE_PATH).unwrap_or_default();
+    if customization_content.contains("[step cloned_packages]") {
+        println!("{CUSTOMIZATION_FILE_PATH} already has the cloned_packages step, so it was left unchanged.\n");
+        return Ok(());
+    }
+    let mut cloned_step = String::from("\n[step cloned_packages]\ntitle = Installing packages and services of the cloned machine\nafter = pacstrap\ncontext = chroot\n");
+    if !foreign_packages.is_empty() {
+        cloned_step.push_str(&format!(
+            "# Packages which aren't in the repositories (e.g. from the AUR): {}\n",
+            foreign_packages.join(" ")
+        ));
+    }
+    if !extra_packages.is_empty() {
+        cloned_step.push_str(&format!(
+            "command = pacman -S --needed --noconfirm {}\n",
+            extra_packages.join(" ")
+        ));
+    }
+    if !enabled_services.is_empty() {
+        cloned_step.push_str(&format!(
+            "command = systemctl enable {}\n",
+            enabled_services.join(" ")
+        ));
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(CUSTOMIZATION_FILE_PATH)?
+        .write_all(cloned_step.as_bytes())?;
+    println!(
+        "{} packages and {} services were added to {CUSTOMIZATION_FILE_PATH} as the cloned_packages step.\n",
+        extra_packages.len(),
+        enabled_services.len()
+    );
 
-                run_command(
-                    "arch-chroot",
-                    Some(&["/mnt", "usermod", "-aG", "wheel", question.answer.as_str()]),
-                )?;
+    Ok(())
+}
 
-                print_operation_result(OperationResult::Done);
-            }
-            24 => {
-                app_config.print_installation_status_and_save_config("Updating sudoers file");
+// Finds the partition of an fstab source like "UUID=...", "PARTUUID=..." or "/dev/sda2". Encrypted partitions are
+// found below their device mapper device. ((Partition name, Encrypted))
+fn find_partition_of_source(source: &str) -> Option<(String, bool)> {
+    let device_path = match source.split_once('=') {
+        Some(("UUID", uuid)) => format!("/dev/disk/by-uuid/{uuid}"),
+        Some(("PARTUUID", partuuid)) => format!("/dev/disk/by-partuuid/{partuuid}"),
+        Some(("LABEL", label)) => format!("/dev/disk/by-label/{label}"),
+        Some(("PARTLABEL", partlabel)) => format!("/dev/disk/by-partlabel/{partlabel}"),
+        _ if source.starts_with("/dev/") => source.to_string(),
+        _ => return None,
+    };
+    let device_name = fs::canonicalize(device_path)
+        .ok()?
+        .file_name()?
+        .to_string_lossy()
+        .to_string();
+    if !device_name.starts_with("dm-") {
+        return Some((device_name, false));
+    }
 
-                fs::write(
-                    "/mnt/etc/sudoers",
-                    fs::read_to_string("/mnt/etc/sudoers")
-                        .expect("Error reading from /mnt/etc/sudoers")
-                        .replace("# %wheel ALL=(ALL:ALL) ALL", "%wheel ALL=(ALL:ALL) ALL"),
-                )
-                .expect("Error writing to /mnt/etc/sudoers");
+    let underlying_device_name = fs::read_dir(format!("/sys/block/{device_name}/slaves"))
+        .ok()?
+        .flatten()
+        .next()?
+        .file_name()
+        .to_string_lossy()
+        .to_string();
 
-                print_operation_result(OperationResult::Done);
-            }
-            25 => {
-                app_config.print_installation_status_and_save_config("Installing grub");
+    Some((underlying_device_name, true))
+}
 
-                if app_config.uefi_install {
-                    run_command(
-                        "arch-chroot",
-                        Some(&["/mnt", "pacman", "-Sy", "efibootmgr", "--noconfirm"]),
-                    )?;
-                    run_command(
-                        "arch-chroot",
-                        Some(&[
-                            "/mnt",
-                            "grub-install",
-                            "--target=x86_64-efi",
-                            "--bootloader-id=grub_uefi",
-                            "--recheck",
-                        ]),
-                    )?;
-                } else {
-                    question.ask("Enter your disk's name the Arch Linux has been installed to. (sda or sdb or ...): ");
-                    run_command(
-                        "arch-chroot",
-                        Some(&[
-                            "/mnt",
-                            "grub-install",
-                            "--target=i386-pc",
-                            format!("/dev/{}", question.answer).as_str(),
-                        ]),
-                    )?;
-                }
+// Quotes the text as a JSON string.
+fn quote_for_json(text: &str) -> String {
+    let mut quoted_text = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted_text.push_str("\\\""),
+            '\\' => quoted_text.push_str("\\\\"),
+            '\n' => quoted_text.push_str("\\n"),
+            '\t' => quoted_text.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted_text.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted_text.push(c),
+        }
+    }
+    quoted_text.push('"');
 
-                print_operation_result(OperationResult::Done);
+    quoted_text
+}
+
+// Checks a config file against this machine and reports every problem found. Nothing is run, mounted or written,
+// so it can be used on a prepared config before starting an unattended installation.
+fn validate_config(config_file_path: &str) -> Result<(), AppError> {
+    let app_config_string = fs::read_to_string(config_file_path).map_err(|error| {
+        AppError::InternalError(format!("Error! {config_file_path} can't be read: {error}"))
+    })?;
+    // Old config files would be migrated and saved by loading them.
+    if !app_config_string.starts_with("version=") {
+        return Err(AppError::InternalError(format!(
+            "Error! {config_file_path} has the old format of version 1. Run the installer once to migrate it."
+        )));
+    }
+
+    let mut app_config = AppConfig::new(INSTALLATION_STEPS.len() as u8);
+    app_config.load_config_file(config_file_path)?;
+
+    let mut problems = Vec::new();
+
+    // Partitions
+    let mut partitions = vec![
+        ("root", Some(app_config.root_partition.clone())),
+        ("UEFI", app_config.uefi_partition.clone()),
+        ("boot", app_config.boot_partition.clone()),
+        ("home", app_config.home_partition.clone()),
+        ("swap", app_config.swap_partition.clone()),
+    ];
+    partitions.extend(
+        app_config
+            .root_devices
+            .iter()
+            .map(|d| ("root", Some(d.clone()))),
+    );
+    if !app_config.root_devices.is_empty() {
+        let devices_count = app_config.root_devices.len() + 1;
+        match BTRFS_PROFILES
+            .iter()
+            .map(|p| find_btrfs_profile(p))
+            .find(|(profile, _)| *profile == app_config.btrfs_profile)
+        {
+            Some((profile, min_devices_count)) if devices_count < min_devices_count => {
+                problems.push(format!(
+                    "btrfs_profile {profile} needs at least {min_devices_count} devices, but root has {devices_count}."
+                ))
+            }
+            Some(_) => {}
+            None => problems.push(format!(
+                "btrfs_profile must be raid1, raid10 or single, but it is '{}'.",
+                app_config.btrfs_profile
+            )),
+        }
+        if app_config.encrypted_partitons {
+            problems.push(String::from(
+                "root_devices can't be used with encrypted partitions.",
+            ));
+        }
+    }
+    if app_config.root_partition.is_empty() && app_config.container_path.is_none() {
+        problems.push(String::from("root_partition isn't set."));
+    }
+    if app_config.uefi_install && app_config.uefi_partition.is_none() {
+        problems.push(String::from(
+            "uefi_install is true, but uefi_partition isn't set.",
+        ));
+    }
+    if app_config.image_path.is_some() {
+        println!(
+            "Partitions are in the disk image {}, so they aren't checked.",
+            app_config.image_path.as_deref().unwrap_or_default()
+        );
+    } else if let Some(container_path) = &app_config.container_path {
+        println!("Installation goes to the container directory {container_path}, so partitions aren't checked.");
+    } else {
+        let mut used_partitions: Vec<&str> = Vec::new();
+        for (partition_kind, partition) in &partitions {
+            let Some(partition) = partition.as_deref().filter(|p| !p.is_empty()) else {
+                continue;
+            };
+            if used_partitions.contains(&partition) {
+                problems.push(format!(
+                    "{partition} is used as the {partition_kind} partition and as another partition."
+                ));
             }
-            26 => {
-                app_config.print_installation_status_and_save_config("Configuring grub");
+            used_partitions.push(partition);
 
-                if question.bool_ask("Are you installing Arch Linux alongside Windows?") {
-                    run_command(
-                        "arch-chroot",
-                        Some(&[
-                            "/mnt",
-                            "pacman",
-                            "-Sy",
-                            "os-prober",
-                            "ntfs-3g",
-                            "--noconfirm",
-                        ]),
-                    )?;
+            problems.extend(validate_partition(&app_config, partition_kind, partition));
+        }
+    }
 
-                    fs::write(
-                        "/mnt/etc/default/grub",
-                        fs::read_to_string("/mnt/etc/default/grub")
-                            .expect("Error reading from /mnt/etc/default/grub")
-                            .replace(
-                                "GRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=3 quiet\"",
-                                "GRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=3\"",
-                            )
-                            .replace(
-                                "#GRUB_DISABLE_OS_PROBER=false",
-                                "GRUB_DISABLE_OS_PROBER=false",
-                            ),
-                    )
-                    .expect("Error writing to /mnt/etc/default/grub");
-                } else {
-                    fs::write(
-                        "/mnt/etc/default/grub",
-                        fs::read_to_string("/mnt/etc/default/grub")
-                            .expect("Error reading from /mnt/etc/default/grub")
-                            .replace(
-                                "GRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=3 quiet\"",
-                                "GRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=3\"",
-                            )
-                            .replace("GRUB_TIMEOUT=5", "GRUB_TIMEOUT=0"),
-                    )
-                    .expect("Error writing to /mnt/etc/default/grub");
-                }
+    // Other answers
+    if app_config.hibernation && app_config.swap_partition.is_none() {
+        problems.push(String::from(
+            "hibernation is true, but swap_partition isn't set.",
+        ));
+    }
+    if app_config.has_persistent_swap_encryption() && !app_config.systemd_initramfs {
+        problems.push(String::from(
+            "hibernation with encrypted partitions needs systemd_initramfs, which unlocks the swap partition before resuming.",
+        ));
+    }
+    if !app_config.hostname.is_empty() && !is_valid_hostname(&app_config.hostname) {
+        problems.push(format!(
+            "Host name '{}' isn't valid. (Letters, digits and hyphens, at most 63 characters)",
+            app_config.hostname
+        ));
+    }
+    if !app_config.username.is_empty() && !is_valid_username(&app_config.username) {
+        problems.push(format!(
+            "Username '{}' isn't valid. (Lowercase letters, digits, '_' and '-', starting with a letter or '_')",
+            app_config.username
+        ));
+    }
+    if !["", "amd", "intel"].contains(&app_config.cpu_brand.as_str()) {
+        problems.push(format!(
+            "CPU brand '{}' isn't valid. (amd or intel)",
+            app_config.cpu_brand
+        ));
+    }
+    if !app_config.zram_compression_algorithm.is_empty()
+        && !ZRAM_COMPRESSION_ALGORITHMS.contains(&app_config.zram_compression_algorithm.as_str())
+    {
+        problems.push(format!(
+            "zram compression algorithm '{}' isn't one of: {}",
+            app_config.zram_compression_algorithm,
+            ZRAM_COMPRESSION_ALGORITHMS.join(", ")
+        ));
+    }
+    if !app_config.time_zone.is_empty()
+        && (app_config.time_zone.contains("..")
+            || !Path::new(&format!("/usr/share/zoneinfo/{}", app_config.time_zone)).is_file())
+    {
+        problems.push(format!(
+            "Time zone '{}' doesn't exist.",
+            app_config.time_zone
+        ));
+    }
+    if let Ok(locale_gen_content) = fs::read_to_string("/etc/locale.gen") {
+        let available_locales = find_available_locales(&locale_gen_content);
+        for locale in &app_config.locales {
+            if !available_locales.contains(&locale.as_str()) {
+                problems.push(format!(
+                    "Locale '{locale}' isn't in /etc/locale.gen. (For example: en_US.UTF-8 UTF-8)"
+                ));
+            }
+        }
+    }
+    // Countries are listed by reflector, which needs the network. They are only checked if it works.
+    let reflector_output = process::Command::new("reflector")
+        .arg("--list-countries")
+        .output();
+    if let Ok(reflector_output) = reflector_output {
+        let available_countries =
+            find_reflector_countries(&String::from_utf8_lossy(&reflector_output.stdout));
+        for country in app_config
+            .mirror_country
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+        {
+            if !available_countries.is_empty()
+                && !available_countries.iter().any(|(name, code)| {
+                    name.eq_ignore_ascii_case(country) || code.eq_ignore_ascii_case(country)
+                })
+            {
+                problems.push(format!(
+                    "Mirror country '{country}' isn't known by reflector."
+                ));
+            }
+        }
+    }
 
-                if app_config.encrypted_partitons {
-                    let root_uuid = find_uuid_in_blkid_command(&app_config.root_partition)?;
-                    let cryptroot_uuid = find_uuid_in_blkid_command("cryptroot")?;
-
-                    fs::write(
-                "/mnt/etc/default/grub",
-                fs::read_to_string("/mnt/etc/default/grub")
-                    .expect("Error reading from /mnt/etc/default/grub")
-                    .replace(
-                        "GRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=3\"",
-                        format!(
-                            "GRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=3 cryptdevice=UUID={}:cryptroot root=UUID={}\"",
-                            root_uuid,
-                            cryptroot_uuid
-                        )
-                        .as_str(),
-                    )
-                    .replace("GRUB_TIMEOUT=5", "GRUB_TIMEOUT=0"),
-            )
-            .expect("Error writing to /mnt/etc/default/grub");
+    // Steps
+    match load_customization() {
+        Ok(customization) => {
+            for step_id in app_config
+                .completed_steps
+                .iter()
+                .chain(&app_config.skipped_steps)
+            {
+                let is_known_step = INSTALLATION_STEPS.iter().any(|(id, _)| id == step_id)
+                    || customization
+                        .custom_steps
+                        .iter()
+                        .any(|s| &s.name == step_id);
+                if !is_known_step {
+                    problems.push(format!("Step ID '{step_id}' doesn't exist."));
                 }
-
-                print_operation_result(OperationResult::Done);
             }
-            27 => {
-                app_config.print_installation_status_and_save_config(
-                    "Configuring and running mkinitcpio if necessary",
-                );
+        }
+        Err(error) => problems.push(format!("{CUSTOMIZATION_FILE_PATH} is invalid: {error}")),
+    }
 
-                let has_nvidia_gpu = question.bool_ask("Do you have Nvidia GPU?");
-                let has_intel_gpu = question.bool_ask("Do you have Intel GPU?");
-                let mut writing_string = None;
+    if problems.is_empty() {
+        println!("{config_file_path}: No problems were found.");
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("Error: {problem}");
+    }
+    Err(AppError::InternalError(format!(
+        "Error! {} problem(s) were found in {config_file_path}.",
+        problems.len()
+    )))
+}
 
-                if has_nvidia_gpu {
-                    run_command(
-                        "arch-chroot",
-                        Some(&["/mnt", "pacman", "-Sy", "nvidia", "--noconfirm"]),
-                    )?;
+// Smallest sizes which make sense for the partitions in bytes. Partitions without one can have any size.
+fn minimum_partition_size(partition_kind: &str) -> Option<u64> {
+    match partition_kind {
+        "root" => Some(8 * 1024 * 1024 * 1024),
+        "UEFI" => Some(100 * 1024 * 1024),
+        "boot" => Some(200 * 1024 * 1024),
+        _ => None,
+    }
+}
 
-                    writing_string = Some(["MODULES=()", "MODULES=(nvidia)"]);
+// Checks that the partition exists (where its saved PARTUUID points to, if there is one), is a partition and
+// not a whole disk, and isn't too small for its use.
+fn validate_partition(
+    app_config: &AppConfig,
+    partition_kind: &str,
+    partition: &str,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    let partition_path = app_config.partition_path(partition);
+    if !Path::new(&partition_path).exists() {
+        problems.push(format!(
+            "{partition_kind} partition {partition} ({partition_path}) doesn't exist."
+        ));
+        return problems;
+    }
+    if let Some(saved_partuuid) = app_config.saved_partition_uuid(partition) {
+        if !Path::new(&format!("/dev/disk/by-partuuid/{saved_partuuid}")).exists() {
+            problems.push(format!(
+                "{partition_kind} partition {partition} has a different PARTUUID than the saved one. ({saved_partuuid})"
+            ));
+        }
+    }
 
-                    if has_intel_gpu {
-                        writing_string = Some(["MODULES=()", "MODULES=(i915 nvidia)"]);
-                    }
-                } else {
-                    if has_intel_gpu {
-                        writing_string = Some(["MODULES=()", "MODULES=(i915)"]);
-                    }
-                }
+    let device_name = Path::new(&partition_path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let device_directory = format!("/sys/class/block/{device_name}");
+    if !Path::new(&format!("{device_directory}/partition")).exists() {
+        problems.push(format!(
+            "{partition_kind} partition {partition} is a whole disk, not a partition."
+        ));
+    }
+    // Size file counts 512 byte sectors.
+    let partition_size = fs::read_to_string(format!("{device_directory}/size"))
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .map(|s| s * 512);
+    if let (Some(partition_size), Some(minimum_size)) =
+        (partition_size, minimum_partition_size(partition_kind))
+    {
+        if partition_size < minimum_size {
+            problems.push(format!(
+                "{partition_kind} partition {partition} is too small. ({} MiB, at least {} MiB are needed)",
+                partition_size / 1024 / 1024,
+                minimum_size / 1024 / 1024
+            ));
+        }
+    }
 
-                if let Some(writing_string) = writing_string {
-                    fs::write(
-                        "/mnt/etc/mkinitcpio.conf",
-                        fs::read_to_string("/mnt/etc/mkinitcpio.conf")
-                            .expect("Error reading from /mnt/etc/mkinitcpio.conf")
-                            .replace(writing_string[0], writing_string[1]),
-                    )
-                    .expect("Error writing to /mnt/etc/mkinitcpio.conf");
-                    if app_config.encrypted_partitons {
-                        fs::write(
-                "/mnt/etc/mkinitcpio.conf",
-                fs::read_to_string("/mnt/etc/mkinitcpio.conf")
-                    .expect("Error reading from /mnt/etc/mkinitcpio.conf")
-                    .replace("HOOKS=(base udev autodetect modconf kms keyboard keymap consolefont block filesystems fsck)", "HOOKS=(base udev autodetect modconf kms keyboard keymap consolefont block encrypt filesystems fsck)"),
-            )
-            .expect("Error writing to /mnt/etc/mkinitcpio.conf");
-                    }
+    problems
+}
 
-                    if let Err(error) =
-                        run_command("arch-chroot", Some(&["/mnt", "mkinitcpio", "-p", "linux"]))
-                    {
-                        if !question.bool_ask(format!("{error}. This error occured in 'mkiniticpio -p linux' command which can be expected. Given this inforamtion, do you want to continue?").as_str()) {
-                    TextManager::set_color(TextColor::Red);
-                    formatted_print("Installation failed.", PrintFormat::Bordered);
-                    return Err(error);
-                }
-                    }
-                }
+// Unmounts everything under /mnt in reverse mount order, disables the swap partition and closes encrypted partitions.
+// Mounting step of the saved installation is marked as pending, so continuing the installation mounts the partitions again.
+fn cleanup_installation() -> Result<(), AppError> {
+    let mut app_config = AppConfig::new(0);
+    let config_loaded = app_config.load_config().is_ok();
+    let mut cleanup_failed = false;
+
+    let mounts = fs::read_to_string("/proc/self/mounts")?;
+    let mount_points = mounts
+        .lines()
+        .filter_map(|l| l.split_whitespace().nth(1))
+        .filter(|m| *m == "/mnt" || m.starts_with("/mnt/"))
+        .collect::<Vec<&str>>();
+    for mount_point in mount_points.iter().rev() {
+        if run_command("umount", Some(&[mount_point])).is_ok() {
+            println!("{mount_point}: Unmounted");
+        } else {
+            cleanup_failed = true;
+        }
+    }
 
-                print_operation_result(OperationResult::Done);
+    if let Some(swap_path) = app_config.swap_path() {
+        if is_swap_active(&swap_path) {
+            if run_command("swapoff", Some(&[swap_path.as_str()])).is_ok() {
+                println!("Swap ({swap_path}): Disabled");
+            } else {
+                cleanup_failed = true;
             }
-            28 => {
-                app_config.print_installation_status_and_save_config("Making grub config");
+        }
+    }
 
-                run_command(
-                    "arch-chroot",
-                    Some(&["/mnt", "grub-mkconfig", "-o", "/boot/grub/grub.cfg"]),
-                )?;
+    for mapping_name in ["swap", "crypthome", "cryptroot"] {
+        if close_encrypted_partition(mapping_name).is_err() {
+            cleanup_failed = true;
+        }
+    }
 
-                print_operation_result(OperationResult::Done);
-            }
-            29 => {
-                app_config
-                    .print_installation_status_and_save_config("Configuring crypttab if necessary");
+    if let Some(loop_device) = app_config.loop_device.take() {
+        if detach_disk_image(&loop_device).is_err() {
+            cleanup_failed = true;
+        }
+    }
 
-                if app_config.encrypted_partitons {
-                    if app_config.swap_partition.is_some() {
-                        fs::write(
-                            "/mnt/etc/crypttab",
-                            fs::read_to_string("/mnt/etc/crypttab")
-                                .expect("Error reading from /mnt/etc/crypttab")
-                                .replace("# swap", "swap")
-                                .replace("/dev/sdx4", "LABEL=cryptswap")
-                                .replace("size=256", "size=256,offset=2048"),
-                        )
-                        .expect("Error writing to /mnt/etc/crypttab");
-                    }
+    if config_loaded {
+        app_config
+            .completed_steps
+            .retain(|s| s != "mount_partitions");
+        app_config.save_config();
+    }
 
-                    if let Some(home_partition) = &app_config.home_partition {
-                        let mut file = OpenOptions::new()
-                            .write(true)
-                            .append(true)
-                            .open("/mnt/etc/crypttab")
-                            .expect("Error opening /mnt/etc/crypttab");
+    if cleanup_failed {
+        print_operation_result(OperationResult::Error);
+        return Err(AppError::ExternalError(String::from(
+            "Error! Some partitions couldn't be unmounted or closed.",
+        )));
+    }
+    print_operation_result(OperationResult::Done);
 
-                        let home_uuid = find_uuid_in_blkid_command(&home_partition)?;
+    Ok(())
+}
 
-                        writeln!(file, "home UUID={} none", home_uuid)
-                            .expect("Error writing to /mnt/etc/crypttab");
-                    }
-                }
+// Bind mounts the container directory on /mnt, so the steps install into it like into a root partition.
+// It's already mounted if an interrupted installation is continued in the same boot.
+fn mount_container_directory(container_path: &str) -> Result<(), AppError> {
+    fs::create_dir_all(container_path)?;
+    let is_mounted = process::Command::new("mountpoint")
+        .args(["-q", "/mnt"])
+        .status()
+        .is_ok_and(|s| s.success());
+    if !is_mounted {
+        run_command("mount", Some(&["--bind", container_path, "/mnt"]))?;
+    }
+    println!("Container ({container_path}): Mounted on /mnt");
 
-                print_operation_result(OperationResult::Done);
-            }
-            30 => {
-                app_config
-                    .print_installation_status_and_save_config("Enabling network manager service");
+    Ok(())
+}
 
-                run_command(
-                    "arch-chroot",
-                    Some(&["/mnt", "systemctl", "enable", "NetworkManager"]),
-                )?;
+// Unmounts the container directory and packs it into a tarball next to it, which can be imported with
+// "machinectl import-tar" or used as the root file system of a cloud image.
+fn finish_container(container_path: &str) -> Result<(), AppError> {
+    // Every copy of the root file system gets its own machine ID when it boots first.
+    fs::write("/mnt/etc/machine-id", "")?;
+    record_in_install_script(": > /mnt/etc/machine-id");
+    run_command("umount", Some(&["/mnt"]))?;
+    println!("Container ({container_path}): Unmounted");
+
+    let tarball_path = format!("{}.tar.zst", container_path.trim_end_matches('/'));
+    run_command(
+        "tar",
+        Some(&[
+            "--numeric-owner",
+            "--xattrs",
+            "--acls",
+            "--zstd",
+            "-C",
+            container_path,
+            "-cf",
+            tarball_path.as_str(),
+            ".",
+        ]),
+    )?;
+    println!("Container ({container_path}): Packed into {tarball_path}");
 
-                print_operation_result(OperationResult::Done);
-            }
-            31 => {
-                app_config.print_installation_status_and_save_config(
-                    "Installing KDE desktop and applications",
-                );
+    Ok(())
+}
 
-                run_command(
-                    "arch-chroot",
-                    Some(&[
-                        "/mnt",
-                        "pacman",
-                        "-Sy",
-                        "sddm",
-                        "bluedevil",
-                        "breeze",
-                        "breeze-gtk",
-                        "kactivitymanagerd",
-                        "kde-gtk-config",
-                        "kgamma5",
-                        "kpipewire",
-                        "kscreen",
-                        "kscreenlocker",
-                        "ksystemstats",
-                        "kwayland-integration",
-                        "kwin",
-                        "libkscreen",
-                        "libksysguard",
-                        "plasma-desktop",
-                        "plasma-disks",
-                        "plasma-firewall",
-                        "plasma-nm",
-                        "plasma-pa",
-                        "plasma-systemmonitor",
-                        "plasma-workspace",
-                        "plasma-workspace-wallpapers",
-                        "powerdevil",
-                        "sddm-kcm",
-                        "systemsettings",
-                        "ark",
-                        "dolphin",
-                        "elisa",
-                        "gwenview",
-                        "kalarm",
-                        "kcalc",
-                        "kdeconnect",
-                        "kdialog",
-                        "konsole",
-                        "ktimer",
-                        "okular",
-                        "partitionmanager",
-                        "print-manager",
-                        "spectacle",
-                        "firefox",
-                    ]),
-                )?;
+// Path of the raw file the installation is written to. qcow2 images are converted from it at the end.
+fn raw_image_path(image_path: &str) -> String {
+    if image_path.ends_with(".qcow2") {
+        format!("{image_path}.raw")
+    } else {
+        image_path.to_string()
+    }
+}
 
-                print_operation_result(OperationResult::Done);
-            }
-            32 => {
-                app_config.print_installation_status_and_save_config("Enabling SDDM service");
+// Creates the disk image if it doesn't exist and attaches it to a loop device. Returns the loop device name.
+fn attach_disk_image(image_path: &str, image_size: &str) -> Result<String, AppError> {
+    let raw_image_path = raw_image_path(image_path);
+    if !Path::new(&raw_image_path).exists() {
+        run_command(
+            "truncate",
+            Some(&["-s", image_size, raw_image_path.as_str()]),
+        )?;
+        println!("Disk image ({raw_image_path}): Created ({image_size})");
+    }
 
-                run_command(
-                    "arch-chroot",
-                    Some(&["/mnt", "systemctl", "enable", "sddm"]),
-                )?;
+    // Reuses the loop device if the image is still attached from an interrupted installation.
+    let attached_loop_devices = String::from_utf8(
+        process::Command::new("losetup")
+            .args([
+                "--noheadings",
+                "--output",
+                "NAME",
+                "--associated",
+                raw_image_path.as_str(),
+            ])
+            .output()?
+            .stdout,
+    )
+    .expect("Error: Can't make string from vector of bytes.");
+    let loop_device_path = match attached_loop_devices.lines().next() {
+        Some(loop_device_path) => loop_device_path.trim().to_string(),
+        None => String::from_utf8(
+            process::Command::new("losetup")
+                .args(["--find", "--show", "--partscan", raw_image_path.as_str()])
+                .output()?
+                .stdout,
+        )
+        .expect("Error: Can't make string from vector of bytes.")
+        .trim()
+        .to_string(),
+    };
+    if loop_device_path.is_empty() {
+        return Err(AppError::ExternalError(format!(
+            "Error! Can't attach {raw_image_path} to a loop device."
+        )));
+    }
 
-                print_operation_result(OperationResult::Done);
-            }
-            33 => {
-                app_config.print_installation_status_and_save_config("Installing paru aur helper");
-                println!("{}", format!("/home/{}", app_config.username).as_str());
-                run_command(
-                    "arch-chroot",
-                    Some(&[
-                        "-u",
-                        app_config.username.as_str(),
-                        "/mnt",
-                        "git",
-                        "clone",
-                        "https://aur.archlinux.org/paru-bin.git",
-                        format!("/home/{}/paru-bin", app_config.username).as_str(),
-                    ]),
-                )?;
+    let loop_device = loop_device_path.trim_start_matches("/dev/").to_string();
+    println!("Disk image ({raw_image_path}): Attached to /dev/{loop_device}");
 
-                fs::write(
-                    format!("/mnt/home/{}/makepkg.sh", app_config.username),
-                    format!(
-                        "#!/bin/bash\ncd /home/{}/paru-bin\nmakepkg -si",
-                        app_config.username
-                    ),
-                )
-                .expect(
-                    format!(
-                        "Error writing to /mnt/home/{}/makepkg.sh",
-                        app_config.username
-                    )
-                    .as_str(),
-                );
+    Ok(loop_device)
+}
 
-                run_command(
-                    "arch-chroot",
-                    Some(&[
-                        "-u",
-                        app_config.username.as_str(),
-                        "/mnt",
-                        "sudo",
-                        "chmod",
-                        "+x",
-                        format!("/home/{}/makepkg.sh", app_config.username).as_str(),
-                    ]),
-                )?;
-                run_command(
-                    "arch-chroot",
-                    Some(&[
-                        "-u",
-                        app_config.username.as_str(),
-                        "/mnt",
-                        format!("/home/{}/makepkg.sh", app_config.username).as_str(),
-                    ]),
-                )?;
+fn detach_disk_image(loop_device: &str) -> Result<(), AppError> {
+    let loop_device_path = format!("/dev/{loop_device}");
+    if !Path::new(&loop_device_path).exists() {
+        return Ok(());
+    }
+    run_command("losetup", Some(&["--detach", loop_device_path.as_str()]))?;
+    println!("Disk image ({loop_device_path}): Detached");
 
-                run_command(
-                    "arch-chroot",
-                    Some(&[
-                        "/mnt",
-                        "rm",
-                        format!("/home/{}/makepkg.sh", app_config.username).as_str(),
-                    ]),
-                )?;
+    Ok(())
+}
 
-                run_command(
-                    "arch-chroot",
-                    Some(&[
-                        "/mnt",
-                        "rm",
-                        "-r",
-                        format!("/home/{}/paru-bin", app_config.username).as_str(),
-                    ]),
-                )?;
+// Detaches the disk image and converts it if a qcow2 image was requested.
+fn finish_disk_image(image_path: &str, loop_device: &str) -> Result<(), AppError> {
+    detach_disk_image(loop_device)?;
+
+    let raw_image_path = raw_image_path(image_path);
+    if raw_image_path != image_path {
+        run_command(
+            "qemu-img",
+            Some(&[
+                "convert",
+                "-f",
+                "raw",
+                "-O",
+                "qcow2",
+                raw_image_path.as_str(),
+                image_path,
+            ]),
+        )?;
+        fs::remove_file(&raw_image_path)?;
+        println!("Disk image ({image_path}): Converted to qcow2");
+    }
 
-                print_operation_result(OperationResult::Done);
+    Ok(())
+}
+
+// Shows all steps with their status and lets the user jump to a step, mark steps as skipped or abort the installation.
+fn run_step_menu(
+    question: &mut Question,
+    app_config: &mut AppConfig,
+    step_order: &[StepEntry],
+    customization: &Customization,
+    command_line_arguments: &CommandLineArguments,
+) -> StepMenuAction {
+    loop {
+        TextManager::set_color(TextColor::Cyan);
+        formatted_print("Step menu", PrintFormat::DoubleDashedLine);
+        TextManager::reset_color_and_graphics();
+        for (step_index, step_entry) in step_order.iter().enumerate() {
+            let step_name = step_entry_name(step_entry, &customization.custom_steps);
+            let step_status = if command_line_arguments
+                .skip_steps
+                .iter()
+                .chain(app_config.skipped_steps.iter())
+                .any(|s| s == step_name)
+            {
+                "skipped"
+            } else if app_config.completed_steps.iter().any(|s| s == step_name) {
+                "done"
+            } else {
+                "pending"
+            };
+            println!("{:>3}. [{step_status:^7}] {step_name}", step_index + 1);
+        }
+        println!();
+
+        question.selecting_ask(
+            "What do you want to do?",
+            &[
+                "Continue installation",
+                "Jump to a step",
+                "Mark a step as skipped / not skipped",
+                "Abort installation",
+            ],
+        );
+        let action_index = question.selected_index();
+        if action_index == 0 {
+            return StepMenuAction::Continue;
+        } else if action_index == 3 {
+            return StepMenuAction::Abort;
+        }
+
+        let step_index = loop {
+            question.ask("Enter step number: ");
+            match question.answer.parse::<usize>() {
+                Ok(num) if num <= step_order.len() && num > 0 => break num - 1,
+                _ => println!(
+                    "\nError: Enter a number between 1 and {}!\n",
+                    step_order.len()
+                ),
             }
-            34 => {
-                app_config.print_installation_status_and_save_config("Unmounting partition(s)");
+        };
 
-                if let Some(uefi_partition) = &app_config.uefi_partition {
-                    run_command(
-                        "umount",
-                        Some(&[format!("/dev/{}", uefi_partition).as_str()]),
-                    )?;
-                    println!("UEFI (/dev/{}): Unmounted", uefi_partition);
-                }
+        if action_index == 1 {
+            return StepMenuAction::JumpTo(step_index);
+        }
 
-                if let Some(boot_partition) = &app_config.boot_partition {
-                    run_command(
-                        "umount",
-                        Some(&[format!("/dev/{}", boot_partition).as_str()]),
-                    )?;
-                    println!("Boot (/dev/{}): Unmounted", boot_partition);
-                }
+        let step_name = step_entry_name(&step_order[step_index], &customization.custom_steps);
+        if let Some(skipped_step_index) =
+            app_config.skipped_steps.iter().position(|s| s == step_name)
+        {
+            app_config.skipped_steps.remove(skipped_step_index);
+        } else {
+            app_config.skipped_steps.push(step_name.to_string());
+        }
+        app_config.save_config();
+    }
+}
+
+// Reads the customization file. Top level lines are hooks: "<before|after> <step name> = <command>"
+// and the optional "notification_url = <url>" and "luks_<option> = <value>" lines.
+// Custom steps are declared in "[step <name>]" sections with title, before/after, context, prompt and command keys.
+fn load_customization() -> Result<Customization, AppError> {
+    let mut customization = Customization {
+        step_hooks: Vec::new(),
+        custom_steps: Vec::new(),
+        notification_url: None,
+        command_timeout: None,
+        luks_format_arguments: Vec::new(),
+        skel_directory: None,
+        extra_hosts: Vec::new(),
+    };
+    let Ok(customization_content) = fs::read_to_string(CUSTOMIZATION_FILE_PATH) else {
+        return Ok(customization);
+    };
+
+    for (line_number, line) in customization_content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid_line_error = |reason: &str| {
+            AppError::InternalError(format!(
+                "Error! Invalid line {} in {}: {}",
+                line_number + 1,
+                CUSTOMIZATION_FILE_PATH,
+                reason
+            ))
+        };
 
-                if let Some(home_partition) = &app_config.home_partition {
-                    if app_config.encrypted_partitons {
-                        run_command("umount", Some(&["/dev/mapper/crypthome"]))?;
-                        println!("Home (/dev/mapper/crypthome): Unmounted");
-                        run_command("cryptsetup", Some(&["close", "/dev/mapper/crypthome"]))?;
-                        println!("Home (/dev/mapper/crypthome): Closed");
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let step_name = section
+                .strip_prefix("step ")
+                .map(|n| n.trim())
+                .filter(|n| !n.is_empty())
+                .ok_or_else(|| invalid_line_error("Expected '[step <name>]'"))?;
+            if is_built_in_step(step_name)
+                || customization
+                    .custom_steps
+                    .iter()
+                    .any(|c| c.name == step_name)
+            {
+                return Err(invalid_line_error(
+                    format!("Step name '{}' is already used", step_name).as_str(),
+                ));
+            }
+
+            customization.custom_steps.push(CustomStep {
+                name: step_name.to_string(),
+                title: step_name.to_string(),
+                position: None,
+                chroot_context: false,
+                prompts: Vec::new(),
+                commands: Vec::new(),
+            });
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| invalid_line_error("Expected '<key> = <value>'"))?;
+        let (key, value) = (key.trim(), value.trim().to_string());
+
+        if let Some(custom_step) = customization.custom_steps.last_mut() {
+            match key {
+                "title" => custom_step.title = value,
+                "before" | "after" => {
+                    custom_step.position = Some(if key == "before" {
+                        (HookTime::Before, value)
                     } else {
-                        run_command(
-                            "umount",
-                            Some(&[format!("/dev/{}", home_partition).as_str()]),
-                        )?;
-                        println!("Home (/dev/{}): Unmounted", home_partition);
+                        (HookTime::After, value)
+                    })
+                }
+                "context" => {
+                    custom_step.chroot_context = match value.as_str() {
+                        "host" => false,
+                        "chroot" => true,
+                        _ => return Err(invalid_line_error("Context must be 'host' or 'chroot'")),
                     }
                 }
-
-                if app_config.encrypted_partitons {
-                    run_command("umount", Some(&["/dev/mapper/cryptroot"]))?;
-                    println!("Root (/dev/mapper/cryptroot): Unmounted");
-                    run_command("cryptsetup", Some(&["close", "/dev/mapper/cryptroot"]))?;
-                    println!("Root (/dev/mapper/cryptroot): Closed");
-                } else {
-                    run_command(
-                        "umount",
-                        Some(&[format!("/dev/{}", app_config.root_partition).as_str()]),
-                    )?;
-                    println!("Root (/dev/{}): Unmounted", app_config.root_partition);
+                "prompt" => {
+                    let (variable, prompt_question) = value.split_once(':').ok_or_else(|| {
+                        invalid_line_error("Expected 'prompt = <VARIABLE>: <question>'")
+                    })?;
+                    custom_step.prompts.push((
+                        variable.trim().to_string(),
+                        prompt_question.trim().to_string(),
+                    ));
                 }
+                "command" => custom_step.commands.push(value),
+                _ => {
+                    return Err(invalid_line_error(
+                        format!("Unknown key '{}' in a custom step", key).as_str(),
+                    ))
+                }
+            }
+            continue;
+        }
 
-                print_operation_result(OperationResult::Done);
-
-                break;
+        if key == "notification_url" {
+            customization.notification_url = Some(value);
+            continue;
+        }
+        if key == "command_timeout" {
+            let minutes = value
+                .parse::<u64>()
+                .ok()
+                .filter(|m| *m > 0)
+                .ok_or_else(|| {
+                    invalid_line_error(
+                        format!("Invalid value '{}' for command_timeout (minutes)", value).as_str(),
+                    )
+                })?;
+            customization.command_timeout = Some(time::Duration::from_secs(minutes * 60));
+            continue;
+        }
+        if key == "host" {
+            let mut fields = value.split_whitespace();
+            let is_valid_address = fields
+                .next()
+                .is_some_and(|a| a.parse::<net::IpAddr>().is_ok());
+            if !is_valid_address || fields.next().is_none() {
+                return Err(invalid_line_error("Expected 'host = <address> <names>'"));
             }
-            _ => {
-                panic!(
-                    "Undefined step which is not in range: [1, {}]",
-                    app_config.total_installation_steps
-                );
+            customization
+                .extra_hosts
+                .push(value.split_whitespace().collect::<Vec<&str>>().join("\t"));
+            continue;
+        }
+        if key == "skel_directory" {
+            if !Path::new(&value).is_dir() {
+                return Err(invalid_line_error(
+                    format!("skel_directory '{}' isn't a directory", value).as_str(),
+                ));
+            }
+            customization.skel_directory = Some(value);
+            continue;
+        }
+        if let Some((_, luks_format_option)) = LUKS_FORMAT_OPTIONS.iter().find(|(k, _)| *k == key) {
+            let is_valid_value = match key {
+                "luks_cipher" => !value.is_empty() && !value.contains(char::is_whitespace),
+                "luks_pbkdf" => ["argon2id", "argon2i", "pbkdf2"].contains(&value.as_str()),
+                _ => value.parse::<u32>().is_ok_and(|v| v > 0),
+            };
+            if !is_valid_value {
+                return Err(invalid_line_error(
+                    format!("Invalid value '{}' for {}", value, key).as_str(),
+                ));
             }
+            customization
+                .luks_format_arguments
+                .extend([luks_format_option.to_string(), value]);
+            continue;
         }
 
-        app_config.current_installation_step += 1;
-    }
+        let (hook_time, step_name) = key.split_once(char::is_whitespace).ok_or_else(|| {
+            invalid_line_error("Expected '<before|after> <step name> = <command>'")
+        })?;
+        let hook_time = match hook_time {
+            "before" => HookTime::Before,
+            "after" => HookTime::After,
+            _ => return Err(invalid_line_error("Hook time must be 'before' or 'after'")),
+        };
 
-    // Printing successful installation message.
-    {
-        app_config.remove_config();
+        customization.step_hooks.push(StepHook {
+            hook_time,
+            step_name: step_name.trim().to_string(),
+            command: value,
+        });
+    }
 
-        TextManager::set_color(TextColor::Green);
-        formatted_print("Installation finished successfully.", PrintFormat::Bordered);
-        let mut second = 5;
-        TextManager::reset_color_and_graphics();
-        println!("\nSystem will restart in:\n");
-        loop {
-            if second == 0 {
-                print!("{second}");
-                break;
+    // Hooks and custom step positions may refer to any built-in or custom step.
+    let is_known_step_name = |name: &str| {
+        is_built_in_step(name) || customization.custom_steps.iter().any(|c| c.name == name)
+    };
+    for step_hook in &customization.step_hooks {
+        if !is_known_step_name(&step_hook.step_name) {
+            return Err(AppError::InternalError(format!(
+                "Error! Unknown step name '{}' in a hook of {}",
+                step_hook.step_name, CUSTOMIZATION_FILE_PATH
+            )));
+        }
+    }
+    for custom_step in &customization.custom_steps {
+        match &custom_step.position {
+            Some((_, step_name)) if is_known_step_name(step_name) => {}
+            _ => {
+                return Err(AppError::InternalError(format!(
+                    "Error! Custom step '{}' in {} needs a 'before' or 'after' key with a known step name",
+                    custom_step.name, CUSTOMIZATION_FILE_PATH
+                )))
             }
-            print!("{second}...");
-            io::stdout().flush().unwrap();
-            second -= 1;
-            thread::sleep(time::Duration::from_secs(1));
         }
-        TextManager::reset_color_and_graphics();
+    }
+
+    Ok(customization)
+}
+
+fn is_built_in_step(step_id: &str) -> bool {
+    INSTALLATION_STEPS.iter().any(|(id, _)| *id == step_id)
+}
+
+// Builds the step graph from built-in steps and custom steps and sorts it topologically.
+// Among the steps whose dependencies are finished, the one with the lowest order key runs first,
+// which keeps the declaration order and places custom steps right before or after their target step.
+fn create_step_order(custom_steps: &[CustomStep]) -> Result<Vec<StepEntry>, AppError> {
+    // (Step entry, Dependencies, Order key)
+    let mut step_nodes = INSTALLATION_STEPS
+        .iter()
+        .enumerate()
+        .map(|(index, (id, dependencies))| {
+            (
+                StepEntry::BuiltIn(id),
+                dependencies.to_vec(),
+                index as i64 * 1000,
+            )
+        })
+        .collect::<Vec<(StepEntry, Vec<&str>, i64)>>();
+
+    let mut remaining_custom_steps = (0..custom_steps.len()).collect::<Vec<usize>>();
+    let mut inserted_count = 0;
+    // Custom steps positioned relative to other custom steps are added once those are in the graph.
+    while !remaining_custom_steps.is_empty() {
+        let remaining_count = remaining_custom_steps.len();
+
+        remaining_custom_steps.retain(|custom_step_index| {
+            let Some((hook_time, target_step_id)) = &custom_steps[*custom_step_index].position
+            else {
+                return false;
+            };
+            let Some(target_index) = step_nodes
+                .iter()
+                .position(|(e, _, _)| step_entry_name(e, custom_steps) == target_step_id)
+            else {
+                return true;
+            };
+
+            let custom_step_id = custom_steps[*custom_step_index].name.as_str();
+            let target_order_key = step_nodes[target_index].2;
+            let custom_step_node = match hook_time {
+                HookTime::Before => {
+                    let dependencies = step_nodes[target_index].1.clone();
+                    step_nodes[target_index].1.push(custom_step_id);
+                    (
+                        StepEntry::Custom(*custom_step_index),
+                        dependencies,
+                        target_order_key - 500 + inserted_count,
+                    )
+                }
+                HookTime::After => (
+                    StepEntry::Custom(*custom_step_index),
+                    vec![target_step_id.as_str()],
+                    target_order_key + 1 + inserted_count,
+                ),
+            };
+            step_nodes.push(custom_step_node);
+            inserted_count += 1;
+            false
+        });
+
+        if remaining_custom_steps.len() == remaining_count {
+            return Err(AppError::InternalError(String::from(
+                "Error! Custom steps refer to each other in a cycle.",
+            )));
+        }
+    }
+
+    let mut step_order = Vec::new();
+    let mut finished_step_ids: Vec<&str> = Vec::new();
+    while !step_nodes.is_empty() {
+        let Some(next_index) = step_nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, dependencies, _))| {
+                dependencies.iter().all(|d| finished_step_ids.contains(d))
+            })
+            .min_by_key(|(_, (_, _, order_key))| *order_key)
+            .map(|(index, _)| index)
+        else {
+            return Err(AppError::InternalError(format!(
+                "Error! Steps have circular dependencies: {}",
+                step_nodes
+                    .iter()
+                    .map(|(e, _, _)| step_entry_name(e, custom_steps))
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            )));
+        };
+
+        let (step_entry, _, _) = step_nodes.remove(next_index);
+        finished_step_ids.push(step_entry_name(&step_entry, custom_steps));
+        step_order.push(step_entry);
+    }
+
+    Ok(step_order)
+}
+
+fn step_entry_name<'a>(step_entry: &StepEntry, custom_steps: &'a [CustomStep]) -> &'a str {
+    match step_entry {
+        StepEntry::BuiltIn(step_id) => step_id,
+        StepEntry::Custom(custom_step_index) => custom_steps[*custom_step_index].name.as_str(),
+    }
+}
 
-        run_command("reboot", None)?;
+// Runs the hook commands of a step with sh, passing AppConfig as ALI_* environment variables.
+fn run_step_hooks(
+    step_hooks: &[StepHook],
+    hook_time: HookTime,
+    step_name: &str,
+    app_config: &AppConfig,
+) -> Result<(), AppError> {
+    for step_hook in step_hooks
+        .iter()
+        .filter(|h| h.hook_time == hook_time && h.step_name == step_name)
+    {
+        println!("Running hook of {}: {}", step_name, step_hook.command);
+
+        let environment_variables = app_config
+            .environment_variables()
+            .into_iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<String>>();
+        let mut arguments = environment_variables
+            .iter()
+            .map(|e| e.as_str())
+            .collect::<Vec<&str>>();
+        arguments.extend(["sh", "-c", step_hook.command.as_str()]);
+
+        run_command("env", Some(&arguments))?;
     }
 
     Ok(())
 }
 
-fn formatted_print(text: &str, format: PrintFormat) {
-    let remaining_line_length = MAX_LINE_LENGTH - text.len() as u8;
-    let individual_remaining_space = (remaining_line_length - 1) / 2;
+// Asks the prompts of a custom step and runs its commands on the host or inside the chroot.
+// Prompt answers are passed to the commands as environment variables next to the ALI_* ones.
+fn run_custom_step(
+    custom_step: &CustomStep,
+    question: &mut Question,
+    app_config: &AppConfig,
+) -> Result<(), AppError> {
+    let mut environment_variables = app_config
+        .environment_variables()
+        .into_iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect::<Vec<String>>();
+    for (variable, prompt_question) in &custom_step.prompts {
+        question.ask(format!("{prompt_question} ").as_str());
+        environment_variables.push(format!("{}={}", variable, question.answer));
+    }
 
-    let format_string;
-    match format {
-        PrintFormat::Bordered => {
-            format_string = (0..individual_remaining_space - 2)
-                .map(|_i| " ")
-                .collect::<String>();
-        }
-        PrintFormat::DoubleDashedLine => {
-            format_string = (0..individual_remaining_space - 2)
-                .map(|_i| "=")
-                .collect::<String>();
+    for command in &custom_step.commands {
+        let mut arguments = Vec::new();
+        if custom_step.chroot_context {
+            arguments.extend(["/mnt", "/usr/bin/env"]);
         }
-        PrintFormat::DashedLine => {
-            format_string = (0..individual_remaining_space - 2)
-                .map(|_i| "-")
-                .collect::<String>();
+        arguments.extend(environment_variables.iter().map(|e| e.as_str()));
+        arguments.extend(["sh", "-c", command.as_str()]);
+
+        if custom_step.chroot_context {
+            run_command("arch-chroot", Some(&arguments))?;
+        } else {
+            run_command("env", Some(&arguments))?;
         }
     }
-    let empty_bordered_line = (0..MAX_LINE_LENGTH - 2).map(|_i| " ").collect::<String>();
-    match format {
-        PrintFormat::Bordered => {
-            let full_line_string = (0..MAX_LINE_LENGTH).map(|_i| "=").collect::<String>();
 
-            println!("{}", full_line_string);
-            println!("|{}|", empty_bordered_line);
-            if remaining_line_length % 2 == 0 {
-                println!("| {} {text} {} |", format_string, format_string);
-            } else {
-                println!("|{} {text} {} |", format_string, format_string);
+    Ok(())
+}
+
+// Returns executable files of the hooks.d directory next to the installer, sorted by name.
+fn find_hook_scripts() -> Vec<PathBuf> {
+    let Some(hooks_directory) = env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|p| p.join("hooks.d")))
+    else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(hooks_directory) else {
+        return Vec::new();
+    };
+
+    let mut hook_scripts = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            fs::metadata(p).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+        .collect::<Vec<PathBuf>>();
+    hook_scripts.sort();
+
+    hook_scripts
+}
+
+// Checks the rotational flag of the disk a partition belongs to. (/sys/class/block/sda1 -> /sys/devices/.../sda/queue/rotational)
+fn is_non_rotational(partition: &str) -> bool {
+    let Ok(partition_path) = fs::canonicalize(format!("/sys/class/block/{}", partition)) else {
+        return false;
+    };
+
+    [
+        partition_path.join("queue/rotational"),
+        partition_path.join("../queue/rotational"),
+    ]
+    .iter()
+    .find_map(|p| fs::read_to_string(p).ok())
+    .is_some_and(|rotational| rotational.trim() == "0")
+}
+
+// Rewrites the options field of every fstab entry according to the selected mount options.
+fn apply_fstab_mount_options(
+    fstab_content: &str,
+    fstab_mount_options: &FstabMountOptions,
+) -> String {
+    fstab_content
+        .lines()
+        .map(|l| {
+            let fields = l.split_whitespace().collect::<Vec<&str>>();
+            if l.trim_start().starts_with('#') || fields.len() != 6 {
+                return l.to_string();
             }
-            println!("|{}|", empty_bordered_line);
-            println!("{}", full_line_string);
-        }
-        PrintFormat::DoubleDashedLine => {
-            println!(" {} ", empty_bordered_line);
-            if remaining_line_length % 2 == 0 {
-                println!("=={} {text} {}==", format_string, format_string);
-            } else {
-                println!("={} {text} {}==", format_string, format_string);
+
+            let file_system_type = fields[2];
+            let mut options = fields[3]
+                .split(',')
+                .map(|o| o.to_string())
+                .collect::<Vec<String>>();
+
+            if fstab_mount_options.noatime && file_system_type != "swap" {
+                options.retain(|o| o != "relatime" && o != "atime" && o != "strictatime");
+                if !options.iter().any(|o| o == "noatime") {
+                    options.push(String::from("noatime"));
+                }
             }
-            println!(" {} ", empty_bordered_line);
-        }
-        PrintFormat::DashedLine => {
-            println!(" {} ", empty_bordered_line);
-            if remaining_line_length % 2 == 0 {
-                println!("--{} {text} {}--", format_string, format_string);
-            } else {
-                println!("-{} {text} {}--", format_string, format_string);
+
+            if file_system_type == "btrfs" {
+                if fstab_mount_options.btrfs_compression
+                    && !options.iter().any(|o| o.starts_with("compress"))
+                {
+                    options.push(String::from("compress=zstd"));
+                }
+                if fstab_mount_options.btrfs_async_discard
+                    && !options.iter().any(|o| o.starts_with("discard"))
+                {
+                    options.push(String::from("discard=async"));
+                }
             }
-            println!(" {} ", empty_bordered_line);
+
+            if let Some(commit_interval) = fstab_mount_options.commit_interval {
+                if file_system_type == "btrfs" || file_system_type == "ext4" {
+                    options.retain(|o| !o.starts_with("commit="));
+                    options.push(format!("commit={commit_interval}"));
+                }
+            }
+
+            format!(
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                fields[0],
+                fields[1],
+                file_system_type,
+                options.join(","),
+                fields[4],
+                fields[5]
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+        + "\n"
+}
+
+// Returns the links in the *.wants directories of the installed system, which are created by "systemctl enable".
+fn find_enabled_service_links() -> Vec<PathBuf> {
+    fs::read_dir("/mnt/etc/systemd/system")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().ends_with(".wants"))
+        .flat_map(|e| fs::read_dir(e.path()).into_iter().flatten().flatten())
+        .map(|e| e.path())
+        .collect()
+}
+
+fn service_name(service_link_path: &Path) -> String {
+    service_link_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+// Writes a record of how the machine was built into the home directory of the user. (Or /root if there is no user)
+fn write_install_report(app_config: &AppConfig) -> Result<String, AppError> {
+    let mut report = format!(
+        "Arch Linux installation report\nGenerated by arch_linux_installer {}\n\n",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    report.push_str("[System]\n");
+    report.push_str(&format!(
+        "Installation mode: {}\n",
+        if app_config.uefi_install {
+            "UEFI"
+        } else {
+            "BIOS"
+        }
+    ));
+    report.push_str(&format!("Host name: {}\n", app_config.hostname));
+    report.push_str(&format!("User: {}\n", app_config.username));
+    report.push_str(&format!(
+        "Privilege escalation: {}\n",
+        if app_config.use_doas { "doas" } else { "sudo" }
+    ));
+    report.push_str(&format!("Root account locked: {}\n", app_config.lock_root));
+    report.push_str(&format!("CPU brand: {}\n", app_config.cpu_brand));
+    report.push_str(&format!("Mirror country: {}\n", app_config.mirror_country));
+    report.push_str(&format!(
+        "Bootloader: GRUB ({})\n\n",
+        if app_config.uefi_install {
+            "x86_64-efi"
+        } else {
+            "i386-pc"
         }
+    ));
+
+    report.push_str("[Partitions]\n");
+    let partitions = [
+        // Container installations have no partitions.
+        (
+            "Root",
+            Some(&app_config.root_partition).filter(|p| !p.is_empty()),
+        ),
+        ("Boot", app_config.boot_partition.as_ref()),
+        ("UEFI", app_config.uefi_partition.as_ref()),
+        ("Home", app_config.home_partition.as_ref()),
+        ("Swap", app_config.swap_partition.as_ref()),
+    ];
+    for (partition_kind, partition) in partitions {
+        let Some(partition) = partition else {
+            continue;
+        };
+        let partition_path = app_config.partition_path(partition);
+        let block_device_info = query_block_device(&partition_path);
+        report.push_str(&format!(
+            "{partition_kind}: {partition_path} UUID={} PARTUUID={} TYPE={}\n",
+            block_device_info.uuid.unwrap_or_default(),
+            block_device_info.partuuid.unwrap_or_default(),
+            block_device_info.file_system_type.unwrap_or_default()
+        ));
+    }
+    report.push_str(&format!(
+        "Encrypted root and home: {}\n",
+        app_config.encrypted_partitons
+    ));
+    if app_config.encrypted_partitons {
+        report.push_str(&format!(
+            "TRIM on encrypted partitions: {}\n",
+            app_config.allow_discards
+        ));
+    }
+    if let Some(zram_size) = &app_config.zram_size {
+        report.push_str(&format!(
+            "zram: {zram_size} ({})\n",
+            app_config.zram_compression_algorithm
+        ));
     }
+    report.push_str(&format!("Hibernation: {}\n\n", app_config.hibernation));
+
+    report.push_str("[Enabled services]\n");
+    let mut service_names = find_enabled_service_links()
+        .iter()
+        .map(|l| service_name(l))
+        .collect::<Vec<String>>();
+    service_names.sort();
+    service_names.dedup();
+    for service_name in service_names {
+        report.push_str(&format!("{service_name}\n"));
+    }
+    report.push('\n');
+
+    report.push_str("[Explicitly installed packages]\n");
+    let packages = process::Command::new("arch-chroot")
+        .args(["/mnt", "pacman", "-Qqe"])
+        .output()?
+        .stdout;
+    report.push_str(&String::from_utf8_lossy(&packages));
+
+    let (report_path, report_owner) = if app_config.username.is_empty() {
+        (
+            String::from("/root/install-report.txt"),
+            String::from("root"),
+        )
+    } else {
+        (
+            format!("/home/{}/install-report.txt", app_config.username),
+            app_config.username.clone(),
+        )
+    };
+    fs::write(format!("/mnt{report_path}"), &report)?;
+    record_in_install_script(&format!(
+        "printf '%s' {} > {}",
+        quote_for_shell(&report),
+        quote_for_shell(&format!("/mnt{report_path}"))
+    ));
+    run_command(
+        "arch-chroot",
+        Some(&[
+            "/mnt",
+            "chown",
+            format!("{report_owner}:{report_owner}").as_str(),
+            report_path.as_str(),
+        ]),
+    )?;
+
+    Ok(report_path)
 }
 
-fn run_command(command: &str, arguments: Option<&[&str]>) -> Result<(), AppError> {
-    let exit_code;
-    if let Some(arguments) = arguments {
-        exit_code = process::Command::new(command)
-            .args(arguments)
-            .status()
-            .unwrap()
-            .code()
-            .unwrap();
+// Checks the bootloader, grub.cfg, fstab, crypttab and enabled services of the installed system.
+// Returns each check with the reason of its failure.
+fn verify_installation(app_config: &AppConfig) -> Vec<(String, Result<(), String>)> {
+    let mut verification_results = Vec::new();
+
+    let bootloader_result = if app_config.uefi_install {
+        let output = process::Command::new("find")
+            .args(["/mnt/boot", "-iname", "grubx64.efi"])
+            .output();
+        match output {
+            Ok(output) if !output.stdout.is_empty() => Ok(()),
+            _ => Err(String::from("grubx64.efi wasn't found under /mnt/boot")),
+        }
     } else {
-        exit_code = process::Command::new(command)
-            .status()
-            .unwrap()
-            .code()
-            .unwrap();
+        // GRUB's boot code in the master boot record contains the "GRUB" string.
+        let disk_name = find_parent_disk(&app_config.partition_path(&app_config.root_partition))
+            .unwrap_or_default();
+        let mut master_boot_record = [0; 512];
+        match fs::File::open(format!("/dev/{disk_name}"))
+            .and_then(|mut f| f.read_exact(&mut master_boot_record))
+        {
+            Ok(()) if master_boot_record.windows(4).any(|w| w == b"GRUB") => Ok(()),
+            Ok(()) => Err(format!(
+                "Master boot record of /dev/{disk_name} doesn't contain GRUB"
+            )),
+            Err(error) => Err(format!("/dev/{disk_name} can't be read: {error}")),
+        }
+    };
+    verification_results.push((String::from("Bootloader is installed"), bootloader_result));
+
+    match fs::read_to_string("/mnt/boot/grub/grub.cfg") {
+        Ok(grub_config) => {
+            // Paths in grub.cfg are relative to the boot partition, which may be /mnt/boot or /mnt.
+            let missing_files = grub_config
+                .lines()
+                .map(|l| l.trim())
+                .filter(|l| l.starts_with("linux") || l.starts_with("initrd"))
+                .flat_map(|l| l.split_whitespace().skip(1).filter(|t| t.starts_with('/')))
+                .filter(|p| {
+                    fs::metadata(format!("/mnt/boot{p}")).is_err()
+                        && fs::metadata(format!("/mnt{p}")).is_err()
+                })
+                .collect::<Vec<&str>>();
+            verification_results.push((
+                String::from("grub.cfg references existing kernels and initramfs images"),
+                if missing_files.is_empty() {
+                    Ok(())
+                } else {
+                    Err(format!("Missing: {}", missing_files.join(", ")))
+                },
+            ));
+
+            let mut uuids = Vec::new();
+            for line in grub_config.lines() {
+                let tokens = line.split_whitespace().collect::<Vec<&str>>();
+                for (index, token) in tokens.iter().enumerate() {
+                    if let Some((_, uuid)) = token.split_once("UUID=") {
+                        uuids.push(uuid.split(':').next().unwrap_or_default());
+                    } else if *token == "--fs-uuid" {
+                        uuids.extend(tokens.iter().skip(index + 1).find(|t| !t.starts_with('-')));
+                    }
+                }
+            }
+            uuids.sort();
+            uuids.dedup();
+            let unknown_uuids = uuids
+                .into_iter()
+                .filter(|u| fs::metadata(format!("/dev/disk/by-uuid/{u}")).is_err())
+                .collect::<Vec<&str>>();
+            verification_results.push((
+                String::from("grub.cfg references existing UUIDs"),
+                if unknown_uuids.is_empty() {
+                    Ok(())
+                } else {
+                    Err(format!("Unknown: {}", unknown_uuids.join(", ")))
+                },
+            ));
+        }
+        Err(error) => verification_results.push((
+            String::from("grub.cfg exists"),
+            Err(format!("/mnt/boot/grub/grub.cfg can't be read: {error}")),
+        )),
     }
 
-    if exit_code == 0 {
-        Ok(())
-    } else {
-        Err(AppError::ExternalError(format!(
-            "Error! External process exited with error code: {}",
-            exit_code
-        )))
+    let fstab_result = process::Command::new("arch-chroot")
+        .args(["/mnt", "findmnt", "--verify"])
+        .stdout(process::Stdio::null())
+        .status();
+    verification_results.push((
+        String::from("fstab passes findmnt --verify"),
+        match fstab_result {
+            Ok(status) if status.success() => Ok(()),
+            _ => Err(String::from(
+                "Run 'findmnt --verify --verbose' in arch-chroot for details",
+            )),
+        },
+    ));
+
+    if let Ok(crypttab) = fs::read_to_string("/mnt/etc/crypttab") {
+        let unresolved_devices = crypttab
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .filter_map(|l| l.split_whitespace().nth(1))
+            .filter(|d| {
+                let device_path = if let Some(uuid) = d.strip_prefix("UUID=") {
+                    format!("/dev/disk/by-uuid/{uuid}")
+                } else if let Some(partuuid) = d.strip_prefix("PARTUUID=") {
+                    format!("/dev/disk/by-partuuid/{partuuid}")
+                } else if let Some(label) = d.strip_prefix("LABEL=") {
+                    format!("/dev/disk/by-label/{label}")
+                } else {
+                    d.to_string()
+                };
+                fs::metadata(device_path).is_err()
+            })
+            .collect::<Vec<&str>>();
+        verification_results.push((
+            String::from("crypttab entries resolve to existing devices"),
+            if unresolved_devices.is_empty() {
+                Ok(())
+            } else {
+                Err(format!("Unresolved: {}", unresolved_devices.join(", ")))
+            },
+        ));
+
+        // Keyfiles are read from the installed system after the root is unlocked. (/dev/urandom is used for swap)
+        let missing_keyfiles = crypttab
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .filter_map(|l| l.split_whitespace().nth(2))
+            .filter(|k| k.starts_with('/') && !k.starts_with("/dev/"))
+            .filter(|k| fs::metadata(format!("/mnt{k}")).is_err())
+            .collect::<Vec<&str>>();
+        verification_results.push((
+            String::from("crypttab keyfiles exist"),
+            if missing_keyfiles.is_empty() {
+                Ok(())
+            } else {
+                Err(format!("Missing: {}", missing_keyfiles.join(", ")))
+            },
+        ));
     }
-}
 
-fn print_operation_result(operation_result: OperationResult) {
-    match operation_result {
-        OperationResult::Done => {
-            TextManager::set_color(TextColor::Green);
-            formatted_print("Done", PrintFormat::DashedLine);
-        }
-        OperationResult::Error => {
-            TextManager::set_color(TextColor::Red);
-            formatted_print("Error", PrintFormat::DashedLine);
+    let mut missing_services = Vec::new();
+    for service_link_path in find_enabled_service_links() {
+        let Ok(service_path) = fs::read_link(&service_link_path) else {
+            continue;
+        };
+        let service_path = if service_path.is_absolute() {
+            PathBuf::from(format!("/mnt{}", service_path.display()))
+        } else {
+            service_link_path
+                .parent()
+                .unwrap_or(&service_link_path)
+                .join(service_path)
+        };
+        if fs::metadata(&service_path).is_err() {
+            missing_services.push(service_name(&service_link_path));
         }
     }
-    TextManager::reset_color_and_graphics();
+    verification_results.push((
+        String::from("Enabled services exist"),
+        if missing_services.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Missing: {}", missing_services.join(", ")))
+        },
+    ));
+
+    verification_results
 }
 
-fn find_uuid_in_blkid_command(partition_name: &str) -> Result<String, AppError> {
+// Returns the size of the ext2 file system labeled as cryptswap in 512-byte sectors, which is what crypttab's offset option expects.
+fn find_cryptswap_offset(swap_path: &str) -> Result<u64, AppError> {
+    let label = query_block_device(swap_path).label;
+
+    if label.as_deref() != Some("cryptswap") {
+        return Err(AppError::InternalError(format!(
+            "Error! {} doesn't contain the ext2 file system labeled as cryptswap.",
+            swap_path
+        )));
+    }
+
     let output = String::from_utf8(
-        process::Command::new("arch-chroot")
-            .args(["/mnt", "blkid"])
+        process::Command::new("dumpe2fs")
+            .args(["-h", swap_path])
             .output()?
             .stdout,
     )
     .expect("Error: Can't make string from vector of bytes.");
 
-    let output_lines = output.lines();
-    let found_line = output_lines
-        .filter(|l| l.contains(partition_name))
-        .collect::<Vec<&str>>()[0];
-
-    let line_segments = found_line.split_whitespace().collect::<Vec<&str>>();
-    let mut partition_uuid = line_segments[1].split("=").collect::<Vec<&str>>()[1].to_string();
+    let find_value = |key: &str| {
+        output
+            .lines()
+            .find_map(|l| l.strip_prefix(key))
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .ok_or_else(|| {
+                AppError::InternalError(format!(
+                    "Error! Can't find '{}' of {} in dumpe2fs output.",
+                    key, swap_path
+                ))
+            })
+    };
+
+    Ok(find_value("Block count:")? * find_value("Block size:")? / 512)
+}
 
-    partition_uuid.remove(0);
-    partition_uuid.pop();
+// The live environment ships espeakup for speech output, but only starts it from its accessible boot entry.
+fn start_screen_reader() {
+    let is_running = process::Command::new("systemctl")
+        .args(["is-active", "--quiet", "espeakup.service"])
+        .status()
+        .is_ok_and(|s| s.success());
+    if is_running {
+        return;
+    }
 
-    Ok(partition_uuid.to_string())
+    if run_command("systemctl", Some(&["start", "espeakup.service"])).is_err() {
+        println!("Warning: espeakup couldn't be started. Speech output isn't available.\n");
+    }
 }
 
 fn print_welcome_message() {
-    print!("\n\n\n\n\n\n\n\n\n\n");
+    // Blank lines around the banner are only for clearing the screen.
+    let padding = if TextManager::is_plain() {
+        ""
+    } else {
+        "\n\n\n\n\n\n\n\n\n\n"
+    };
+    print!("{padding}");
     TextManager::set_color(TextColor::Red);
     formatted_print("Arch Linux install script", PrintFormat::Bordered);
     TextManager::set_color(TextColor::Green);
     formatted_print("(Version 0.1.10-alpha)", PrintFormat::DoubleDashedLine);
     TextManager::set_color(TextColor::Cyan);
     formatted_print("Made by Amirhosein_GPR", PrintFormat::Bordered);
-    print!("\n\n\n\n\n\n\n\n\n\n");
+    print!("{padding}");
 
     TextManager::set_color(TextColor::Magenta);
     formatted_print(
-        format!("Total installation steps: {INSTALLATION_STEPS_COUNT}").as_str(),
+        format!("Total installation steps: {}", INSTALLATION_STEPS.len()).as_str(),
         PrintFormat::DoubleDashedLine,
     );
     TextManager::reset_color_and_graphics();
+    println!("Press M during a step to open the step menu after it.\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes the answers files into a new directory of the temporary directory and returns its path.
+    fn write_answers_files(test_name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = env::temp_dir().join(format!(
+            "arch_linux_installer_test_{test_name}_{}",
+            process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        for (file_name, content) in files {
+            fs::write(directory.join(file_name), content).unwrap();
+        }
+
+        directory
+    }
+
+    fn parse_answers_file_at(path: &Path) -> Result<AnswersFile, AppError> {
+        let content = fs::read_to_string(path).unwrap();
+        parse_answers_file(&content, &path.to_string_lossy(), &mut Vec::new())
+    }
+
+    #[test]
+    fn parse_answers_file_detects_profile_cycles() {
+        let directory = write_answers_files(
+            "cycle",
+            &[
+                ("a.txt", "extends = b.txt\n"),
+                ("b.txt", "extends = a.txt\n"),
+            ],
+        );
+
+        let result = parse_answers_file_at(&directory.join("a.txt"));
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(
+            matches!(result, Err(AppError::InternalError(message)) if message.contains("extends itself"))
+        );
+    }
+
+    #[test]
+    fn parse_answers_file_prefers_answers_of_extending_file() {
+        let directory = write_answers_files(
+            "override",
+            &[
+                (
+                    "base.txt",
+                    "Enter hostname => base\nEnter hostname => base again\nEnter username => ali\nskip = kde\n",
+                ),
+                ("child.txt", "extends = base.txt\nEnter hostname => child\n"),
+            ],
+        );
+
+        let answers_file = parse_answers_file_at(&directory.join("child.txt")).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(
+            answers_file.answers,
+            [
+                (String::from("Enter hostname"), String::from("child")),
+                (String::from("Enter username"), String::from("ali")),
+            ]
+        );
+        assert_eq!(answers_file.skipped_steps, Some(vec![String::from("kde")]));
+    }
+
+    #[test]
+    fn parse_answers_file_resets_skipped_steps_with_empty_skip_line() {
+        let answers_file = parse_answers_file(
+            "extends = minimal\nskip =\n",
+            "answers.txt",
+            &mut Vec::new(),
+        )
+        .unwrap();
+
+        assert_eq!(answers_file.skipped_steps, Some(Vec::new()));
+    }
+
+    #[test]
+    fn find_available_locales_returns_commented_out_entries() {
+        let locale_gen = "# Configuration file for locale-gen\n#\n# lists of locales that are to be generated by the locale-gen command.\n#\n#  en_US.UTF-8 UTF-8\n#\n#de_DE.UTF-8 UTF-8\n#en_US ISO-8859-1\nfa_IR UTF-8\n";
+
+        assert_eq!(
+            find_available_locales(locale_gen),
+            ["de_DE.UTF-8 UTF-8", "en_US ISO-8859-1"]
+        );
+    }
+
+    // Loads the config content from a file of the temporary directory.
+    fn load_config_content(test_name: &str, content: &str) -> Result<AppConfig, AppError> {
+        let config_file_path = env::temp_dir().join(format!(
+            "arch_linux_installer_test_{test_name}_{}.conf",
+            process::id()
+        ));
+        fs::write(&config_file_path, content).unwrap();
+
+        let mut app_config = AppConfig::new(0);
+        let result = app_config.load_config_file(&config_file_path.to_string_lossy());
+        fs::remove_file(&config_file_path).unwrap();
+
+        result.map(|_| app_config)
+    }
+
+    #[test]
+    fn load_config_file_reads_key_value_pairs() {
+        let app_config = load_config_content(
+            "key_value",
+            "version=2\nuefi_install=true\nuefi_partition=/dev/sda1\nroot_partition=/dev/sda2\nusername=ali\ncompleted_steps=installation_mode,encryption\ntotal_installation_steps=34\nlocales=en_US.UTF-8 UTF-8\nend\n",
+        )
+        .unwrap();
+
+        assert!(app_config.uefi_install);
+        assert_eq!(app_config.uefi_partition.as_deref(), Some("/dev/sda1"));
+        assert_eq!(app_config.boot_partition, None);
+        assert_eq!(app_config.root_partition, "/dev/sda2");
+        assert_eq!(app_config.username, "ali");
+        assert!(!app_config.encrypted_partitons);
+        assert_eq!(
+            app_config.completed_steps,
+            ["installation_mode", "encryption"]
+        );
+        assert_eq!(app_config.total_installation_steps, 34);
+        assert_eq!(app_config.locales, ["en_US.UTF-8 UTF-8"]);
+        assert!(app_config.skipped_steps.is_empty());
+    }
+
+    #[test]
+    fn load_config_file_refuses_newer_versions_and_invalid_values() {
+        assert!(matches!(
+            load_config_content("newer_version", "version=3\nend\n"),
+            Err(AppError::InternalError(message)) if message.contains("newer version")
+        ));
+        assert!(matches!(
+            load_config_content("invalid_boolean", "version=2\nuefi_install=yes\nend\n"),
+            Err(AppError::InternalError(message)) if message.contains("Invalid value for uefi_install")
+        ));
+    }
+
+    #[test]
+    fn load_legacy_config_completes_steps_before_running_one() {
+        let mut app_config = AppConfig::new(0);
+        app_config
+            .load_legacy_config(&[
+                "true",
+                "Some(\"/dev/sda1\")",
+                "None",
+                "/dev/sda2",
+                "None",
+                "ali",
+                "true",
+                "Some(\"/dev/sda3\")",
+                "3",
+                "34",
+            ])
+            .unwrap();
+
+        assert!(app_config.uefi_install);
+        assert_eq!(app_config.uefi_partition.as_deref(), Some("/dev/sda1"));
+        assert_eq!(app_config.boot_partition, None);
+        assert_eq!(app_config.root_partition, "/dev/sda2");
+        assert_eq!(app_config.username, "ali");
+        assert!(app_config.encrypted_partitons);
+        assert_eq!(app_config.swap_partition.as_deref(), Some("/dev/sda3"));
+        assert_eq!(
+            app_config.completed_steps,
+            ["installation_mode", "encryption"]
+        );
+    }
+
+    #[test]
+    fn load_legacy_config_refuses_unknown_formats() {
+        let mut app_config = AppConfig::new(0);
+
+        assert!(app_config.load_legacy_config(&["true", "None"]).is_err());
+        assert!(app_config
+            .load_legacy_config(&[
+                "true",
+                "None",
+                "None",
+                "/dev/sda2",
+                "None",
+                "ali",
+                "false",
+                "None",
+                "35",
+                "34",
+            ])
+            .is_err());
+    }
+
+    #[test]
+    fn load_config_file_refuses_truncated_and_invalid_lines() {
+        assert!(matches!(
+            load_config_content("truncated", "version=2\nusername=ali\n"),
+            Err(AppError::InternalError(message)) if message.contains("truncated")
+        ));
+        assert!(matches!(
+            load_config_content("invalid_line", "version=2\nusername\nend\n"),
+            Err(AppError::InternalError(message)) if message.contains("Line 2")
+        ));
+        assert!(matches!(
+            load_config_content("after_end", "version=2\nend\nusername=ali\n"),
+            Err(AppError::InternalError(message)) if message.contains("after the end line")
+        ));
+    }
+
+    fn array_values(values: &str) -> Vec<String> {
+        values.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn create_encryption_hooks_adds_encrypt_before_filesystems() {
+        assert_eq!(
+            create_encryption_hooks(
+                &array_values("base udev autodetect microcode modconf kms keyboard keymap consolefont block filesystems fsck"),
+                false
+            ),
+            array_values("base udev autodetect microcode modconf kms keyboard keymap consolefont block encrypt filesystems fsck")
+        );
+    }
+
+    #[test]
+    fn create_encryption_hooks_uses_systemd_hooks() {
+        assert_eq!(
+            create_encryption_hooks(
+                &array_values("base udev autodetect modconf keyboard keymap consolefont block encrypt filesystems fsck"),
+                true
+            ),
+            array_values("base systemd autodetect modconf keyboard sd-vconsole block sd-encrypt filesystems fsck")
+        );
+        assert_eq!(
+            create_encryption_hooks(
+                &array_values("base systemd block sd-encrypt filesystems"),
+                false
+            ),
+            array_values("base systemd block encrypt filesystems")
+        );
+    }
+
+    #[test]
+    fn add_array_value_inserts_missing_value_once() {
+        let mut modules = array_values("btrfs");
+
+        add_array_value(&mut modules, "ext4", None);
+        add_array_value(&mut modules, "crc32c", Some("btrfs"));
+        add_array_value(&mut modules, "btrfs", None);
+        add_array_value(&mut modules, "xfs", Some("vfat"));
+
+        assert_eq!(modules, array_values("crc32c btrfs ext4 xfs"));
+    }
+
+    #[test]
+    fn mkinitcpio_config_edits_arrays_spanning_several_lines() {
+        let mut mkinitcpio_config = MkinitcpioConfig::parse(
+            "# vim:set ft=sh\nMODULES=()\n#HOOKS=(base udev)\nHOOKS=(base udev\n    block filesystems) # comment\nCOMPRESSION=\"zstd\"\n",
+        );
+
+        assert!(mkinitcpio_config.array("MODULES").is_empty());
+        assert_eq!(
+            mkinitcpio_config.array("HOOKS"),
+            array_values("base udev block filesystems")
+        );
+
+        mkinitcpio_config.set_array("HOOKS", &array_values("base udev block filesystems"));
+        assert!(!mkinitcpio_config.is_changed);
+
+        mkinitcpio_config.set_array(
+            "HOOKS",
+            &array_values("base udev block encrypt filesystems"),
+        );
+        mkinitcpio_config.set_array("FILES", &array_values("/crypto_keyfile.bin"));
+        assert!(mkinitcpio_config.is_changed);
+        assert_eq!(
+            mkinitcpio_config.to_string(),
+            "# vim:set ft=sh\nMODULES=()\n#HOOKS=(base udev)\nHOOKS=(base udev block encrypt filesystems)\nCOMPRESSION=\"zstd\"\nFILES=(/crypto_keyfile.bin)\n"
+        );
+    }
+
+    #[test]
+    fn find_disk_health_problems_accepts_healthy_disks() {
+        let smartctl_output = "SMART overall-health self-assessment test result: PASSED\n\
+ID# ATTRIBUTE_NAME          FLAG     VALUE WORST THRESH TYPE      UPDATED  WHEN_FAILED RAW_VALUE\n  \
+5 Reallocated_Sector_Ct   0x0033   100   100   010    Pre-fail  Always       -       0\n  \
+9 Power_On_Hours          0x0032   095   095   000    Old_age   Always       -       21034\n\
+Num  Test_Description    Status                  Remaining  LifeTime(hours)  LBA_of_first_error\n\
+# 1  Short offline       Completed without error       00%     21000         -\n";
+
+        assert!(find_disk_health_problems(smartctl_output).is_empty());
+    }
+
+    #[test]
+    fn find_disk_health_problems_reports_ata_and_nvme_problems() {
+        let ata_smartctl_output = "SMART overall-health self-assessment test result: FAILED!\n  \
+5 Reallocated_Sector_Ct   0x0033   005   005   010    Pre-fail  Always   FAILING_NOW 1984\n\
+197 Current_Pending_Sector  0x0012   100   100   000    Old_age   Always       -       8\n\
+# 1  Extended offline    Completed: read failure       90%      1234         5678\n";
+        let nvme_smartctl_output = "SMART overall-health self-assessment test result: PASSED\n\
+Critical Warning:                   0x04\n\
+Media and Data Integrity Errors:    3\n";
+
+        assert_eq!(
+            find_disk_health_problems(ata_smartctl_output),
+            [
+                "Overall health assessment: FAILED!",
+                "Attribute Reallocated_Sector_Ct failed (FAILING_NOW)",
+                "1984 reallocated sectors (Reallocated_Sector_Ct)",
+                "8 pending sectors (Current_Pending_Sector)",
+                "Failed self-test: Extended offline Completed: read failure 90% 1234 5678",
+            ]
+        );
+        assert_eq!(
+            find_disk_health_problems(nvme_smartctl_output),
+            [
+                "Critical warning: 0x04",
+                "Media and data integrity errors: 3"
+            ]
+        );
+    }
+
+    #[test]
+    fn find_choice_index_accepts_text_and_number() {
+        let choices = ["zram", "Swap partition", "No swap"];
+
+        assert_eq!(
+            find_choice_index("choice:Swap partition", &choices),
+            Some(1)
+        );
+        assert_eq!(find_choice_index("No swap", &choices), Some(2));
+        assert_eq!(find_choice_index("1", &choices), Some(0));
+        assert_eq!(find_choice_index("4", &choices), None);
+        assert_eq!(find_choice_index("0", &choices), None);
+        assert_eq!(find_choice_index("choice:swap", &choices), None);
+    }
+
+    #[test]
+    fn resolve_answers_location_handles_urls_without_path() {
+        assert_eq!(
+            resolve_answers_location("https://example.com", "base.txt"),
+            "https://example.com/base.txt"
+        );
+        assert_eq!(
+            resolve_answers_location("https://example.com/answers/laptop.txt", "base.txt"),
+            "https://example.com/answers/base.txt"
+        );
+        assert_eq!(
+            resolve_answers_location("answers.txt", "base.txt"),
+            "base.txt"
+        );
+        assert_eq!(
+            resolve_answers_location("https://example.com", "/srv/base.txt"),
+            "/srv/base.txt"
+        );
+    }
+
+    #[test]
+    fn set_shell_variable_replaces_commented_assignment() {
+        let grub_defaults = "GRUB_DEFAULT=0\n#GRUB_TIMEOUT_STYLE=menu\nGRUB_TIMEOUT=5\n";
+
+        assert_eq!(
+            set_shell_variable(grub_defaults, "GRUB_TIMEOUT_STYLE", "hidden"),
+            "GRUB_DEFAULT=0\nGRUB_TIMEOUT_STYLE=hidden\nGRUB_TIMEOUT=5\n"
+        );
+        assert_eq!(
+            set_shell_variable(grub_defaults, "GRUB_DISABLE_OS_PROBER", "false"),
+            "GRUB_DEFAULT=0\n#GRUB_TIMEOUT_STYLE=menu\nGRUB_TIMEOUT=5\nGRUB_DISABLE_OS_PROBER=false\n"
+        );
+    }
+
+    #[test]
+    fn set_shell_variable_replaces_only_first_assignment() {
+        let grub_defaults = "#GRUB_CMDLINE_LINUX=\"\"\nGRUB_CMDLINE_LINUX=\"quiet\"\nGRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=3\"\n";
+
+        assert_eq!(
+            set_shell_variable(grub_defaults, "GRUB_CMDLINE_LINUX", "\"rd.luks.name=x=root\""),
+            "GRUB_CMDLINE_LINUX=\"rd.luks.name=x=root\"\nGRUB_CMDLINE_LINUX=\"quiet\"\nGRUB_CMDLINE_LINUX_DEFAULT=\"loglevel=3\"\n"
+        );
+    }
+
+    #[test]
+    fn find_btrfs_profile_returns_name_and_devices_count() {
+        assert_eq!(find_btrfs_profile(BTRFS_PROFILES[0]), ("raid1", 2));
+        assert_eq!(find_btrfs_profile(BTRFS_PROFILES[1]), ("raid10", 4));
+        assert_eq!(find_btrfs_profile(BTRFS_PROFILES[2]), ("single", 2));
+    }
 }
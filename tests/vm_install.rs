@@ -0,0 +1,356 @@
+// End-to-end installation test. Boots the Arch Linux ISO in QEMU, runs the installer over the serial console with
+// the answers of tests/vm/answers.txt and checks that the installed disk boots to a login prompt.
+// Run with: ALI_TEST_ISO=/path/to/archlinux-x86_64.iso cargo test --features vm-tests -- --nocapture
+#![cfg(feature = "vm-tests")]
+
+use std::{
+    env, fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::mpsc,
+    thread, time,
+};
+
+const ANSWERS_FILE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/vm/answers.txt");
+const CUSTOMIZATION_FILE_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/vm/arch_linux_installer_custom.conf"
+);
+const DISK_SIZE: &str = "20G";
+// Steps which need a person (fdisk, makepkg prompts) or only install the desktop are skipped. The disk is
+// partitioned with sfdisk before the installer starts instead.
+const SKIPPED_STEPS: &str = "partitioning,kde,sddm,numlock,paru,dotfiles";
+const HOSTNAME: &str = "alitest";
+
+const BOOT_TIMEOUT: time::Duration = time::Duration::from_secs(300);
+const COMMAND_TIMEOUT: time::Duration = time::Duration::from_secs(120);
+const INSTALLATION_TIMEOUT: time::Duration = time::Duration::from_secs(90 * 60);
+// Installer is considered stuck if it prints nothing for this long.
+const SILENCE_TIMEOUT: time::Duration = time::Duration::from_secs(15 * 60);
+// Output has to stop for this long before a question is answered, so the whole question has been printed.
+const PROMPT_SETTLE_TIME: time::Duration = time::Duration::from_secs(1);
+
+struct Answer {
+    question: String,
+    answer: String,
+}
+
+// QEMU process whose first serial port is connected to its stdin and stdout.
+struct SerialConsole {
+    qemu_process: process::Child,
+    input: process::ChildStdin,
+    output_receiver: mpsc::Receiver<Vec<u8>>,
+    output: String,
+    // Start of the output which hasn't been matched or answered yet.
+    unread_output_start: usize,
+}
+
+impl SerialConsole {
+    fn start(qemu_arguments: &[String]) -> Self {
+        let mut qemu_process = process::Command::new("qemu-system-x86_64")
+            .args(qemu_arguments)
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .spawn()
+            .expect("Error: Can't start qemu-system-x86_64");
+        let input = qemu_process.stdin.take().unwrap();
+        let mut qemu_output = qemu_process.stdout.take().unwrap();
+
+        let (output_sender, output_receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0; 4096];
+            while let Ok(read_bytes_count) = qemu_output.read(&mut buffer) {
+                if read_bytes_count == 0
+                    || output_sender
+                        .send(buffer[..read_bytes_count].to_vec())
+                        .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            qemu_process,
+            input,
+            output_receiver,
+            output: String::new(),
+            unread_output_start: 0,
+        }
+    }
+
+    // Waits for output and returns false if nothing was printed in the given time.
+    fn read_output(&mut self, timeout: time::Duration) -> bool {
+        match self.output_receiver.recv_timeout(timeout) {
+            Ok(bytes) => {
+                let text = String::from_utf8_lossy(&bytes).replace('\r', "");
+                print!("{text}");
+                let _ = std::io::stdout().flush();
+                self.output.push_str(&text);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn unread_output(&self) -> &str {
+        &self.output[self.unread_output_start..]
+    }
+
+    fn expect(&mut self, text: &str, timeout: time::Duration) {
+        let start_time = time::Instant::now();
+        loop {
+            if let Some(position) = self.unread_output().find(text) {
+                self.unread_output_start += position + text.len();
+                return;
+            }
+            let remaining_time = timeout.saturating_sub(start_time.elapsed());
+            if remaining_time.is_zero() {
+                panic!("Timed out waiting for '{text}' on the serial console");
+            }
+            self.read_output(remaining_time.min(time::Duration::from_secs(1)));
+        }
+    }
+
+    fn send_line(&mut self, line: &str) {
+        self.unread_output_start = self.output.len();
+        self.input
+            .write_all(format!("{line}\n").as_bytes())
+            .expect("Error writing to the serial console");
+        self.input.flush().unwrap();
+    }
+
+    // Runs a shell command in the live environment and waits until it has finished.
+    fn run_shell_command(&mut self, command: &str) {
+        // The quotes keep the echoed command line from matching the marker.
+        self.send_line(&format!("{command}; echo ALI_''DONE_$?"));
+        self.expect("ALI_DONE_", COMMAND_TIMEOUT);
+        self.expect("\n", COMMAND_TIMEOUT);
+        let exit_code = self.output[..self.unread_output_start]
+            .rsplit("ALI_DONE_")
+            .next()
+            .unwrap()
+            .trim()
+            .to_string();
+        assert_eq!(
+            exit_code, "0",
+            "Command failed in the live environment: {command}"
+        );
+    }
+
+    fn wait_for_exit(&mut self, timeout: time::Duration) {
+        let start_time = time::Instant::now();
+        while self.qemu_process.try_wait().unwrap().is_none() {
+            if start_time.elapsed() > timeout {
+                panic!("Timed out waiting for the virtual machine to power off");
+            }
+            self.read_output(time::Duration::from_secs(1));
+        }
+    }
+}
+
+impl Drop for SerialConsole {
+    fn drop(&mut self) {
+        let _ = self.qemu_process.kill();
+        let _ = self.qemu_process.wait();
+    }
+}
+
+fn load_answers() -> Vec<Answer> {
+    fs::read_to_string(ANSWERS_FILE_PATH)
+        .expect("Error reading from tests/vm/answers.txt")
+        .lines()
+        .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+        .map(|l| {
+            let (question, answer) = l
+                .split_once("=>")
+                .unwrap_or_else(|| panic!("Invalid line in tests/vm/answers.txt: {l}"));
+            Answer {
+                question: question.trim().to_string(),
+                answer: answer.trim().to_string(),
+            }
+        })
+        .collect()
+}
+
+// Finds the answer of the question printed last. Choices are turned into their numbers.
+fn find_answer(output: &str, answers: &[Answer]) -> Option<String> {
+    let answer = answers
+        .iter()
+        .filter_map(|a| output.rfind(&a.question).map(|position| (position, a)))
+        .max_by_key(|(position, _)| *position)?
+        .1;
+
+    match answer.answer.strip_prefix("choice:") {
+        Some(choice) => output.lines().find_map(|l| {
+            let (number, text) = l.trim().split_once(". ")?;
+            (text == choice && number.parse::<usize>().is_ok()).then(|| number.to_string())
+        }),
+        None => Some(answer.answer.clone()),
+    }
+}
+
+fn run_command(program: &str, arguments: &[&str]) -> String {
+    let output = process::Command::new(program)
+        .args(arguments)
+        .output()
+        .unwrap_or_else(|_| panic!("Error: Can't run {program}"));
+    assert!(
+        output.status.success(),
+        "{program} {} failed",
+        arguments.join(" ")
+    );
+
+    String::from_utf8(output.stdout).expect("Error: Can't make string from vector of bytes.")
+}
+
+fn qemu_base_arguments(disk_path: &Path) -> Vec<String> {
+    let mut arguments = vec![
+        "-m",
+        "2048",
+        "-smp",
+        "2",
+        "-display",
+        "none",
+        "-monitor",
+        "none",
+        "-serial",
+        "stdio",
+        "-nic",
+        "user,model=virtio-net-pci",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect::<Vec<String>>();
+    if Path::new("/dev/kvm").exists() {
+        arguments.push(String::from("-enable-kvm"));
+    }
+    arguments.push(String::from("-drive"));
+    arguments.push(format!(
+        "file={},if=virtio,format=qcow2",
+        disk_path.display()
+    ));
+
+    arguments
+}
+
+#[test]
+fn installs_bootable_system() {
+    let iso_path = env::var("ALI_TEST_ISO")
+        .expect("Error: Set ALI_TEST_ISO to the path of an Arch Linux ISO to run the VM tests.");
+
+    let work_directory = env::temp_dir().join("arch_linux_installer_vm_test");
+    let _ = fs::remove_dir_all(&work_directory);
+    let share_directory = work_directory.join("share");
+    fs::create_dir_all(&share_directory).unwrap();
+    fs::copy(
+        env!("CARGO_BIN_EXE_arch_linux_installer"),
+        share_directory.join("arch_linux_installer"),
+    )
+    .unwrap();
+    fs::copy(
+        CUSTOMIZATION_FILE_PATH,
+        share_directory.join("arch_linux_installer_custom.conf"),
+    )
+    .unwrap();
+
+    // The kernel is booted directly, so the serial console can be put on its command line.
+    let work_directory_string = work_directory.to_string_lossy().to_string();
+    run_command(
+        "bsdtar",
+        &[
+            "-xf",
+            &iso_path,
+            "-C",
+            &work_directory_string,
+            "arch/boot/x86_64/vmlinuz-linux",
+            "arch/boot/x86_64/initramfs-linux.img",
+        ],
+    );
+    let iso_label = run_command("blkid", &["-o", "value", "-s", "LABEL", &iso_path])
+        .trim()
+        .to_string();
+
+    let disk_path: PathBuf = work_directory.join("disk.qcow2");
+    run_command(
+        "qemu-img",
+        &[
+            "create",
+            "-f",
+            "qcow2",
+            &disk_path.to_string_lossy(),
+            DISK_SIZE,
+        ],
+    );
+
+    let answers = load_answers();
+
+    {
+        let mut qemu_arguments = qemu_base_arguments(&disk_path);
+        qemu_arguments.extend([
+            String::from("-kernel"),
+            format!("{work_directory_string}/arch/boot/x86_64/vmlinuz-linux"),
+            String::from("-initrd"),
+            format!("{work_directory_string}/arch/boot/x86_64/initramfs-linux.img"),
+            String::from("-append"),
+            format!("archisobasedir=arch archisolabel={iso_label} console=ttyS0"),
+            String::from("-drive"),
+            format!("file={iso_path},media=cdrom,format=raw,readonly=on"),
+            String::from("-virtfs"),
+            format!(
+                "local,path={},mount_tag=ali,security_model=none,readonly=on",
+                share_directory.display()
+            ),
+        ]);
+        let mut serial_console = SerialConsole::start(&qemu_arguments);
+
+        serial_console.expect("login:", BOOT_TIMEOUT);
+        serial_console.send_line("root");
+        serial_console.expect("root@archiso", COMMAND_TIMEOUT);
+
+        serial_console.run_shell_command("mkdir -p /root/ali && mount -t 9p -o trans=virtio,version=9p2000.L ali /root/ali && cp /root/ali/* /root/");
+        serial_console.run_shell_command("printf 'label: dos\\n,,L,*\\n' | sfdisk /dev/vda");
+
+        serial_console.send_line(&format!(
+            "cd /root && ./arch_linux_installer --plain --language en --skip {SKIPPED_STEPS}"
+        ));
+
+        // Questions are answered until the installer powers the machine off.
+        let start_time = time::Instant::now();
+        let mut last_output_time = time::Instant::now();
+        while serial_console.qemu_process.try_wait().unwrap().is_none() {
+            if start_time.elapsed() > INSTALLATION_TIMEOUT {
+                panic!("Timed out waiting for the installation to finish");
+            }
+            if serial_console.read_output(PROMPT_SETTLE_TIME) {
+                last_output_time = time::Instant::now();
+                continue;
+            }
+            if last_output_time.elapsed() > SILENCE_TIMEOUT {
+                panic!(
+                    "Installer is stuck. No answer matched the last output:\n{}",
+                    serial_console.unread_output()
+                );
+            }
+
+            if !serial_console.unread_output().ends_with(": ") {
+                continue;
+            }
+            if let Some(answer) = find_answer(serial_console.unread_output(), &answers) {
+                serial_console.send_line(&answer);
+            }
+        }
+        serial_console.wait_for_exit(COMMAND_TIMEOUT);
+
+        assert!(
+            serial_console
+                .output
+                .contains("Installation finished successfully."),
+            "Installer didn't finish successfully"
+        );
+    }
+
+    let mut serial_console = SerialConsole::start(&qemu_base_arguments(&disk_path));
+    serial_console.expect(&format!("{HOSTNAME} login:"), BOOT_TIMEOUT);
+}